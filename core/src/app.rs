@@ -0,0 +1,226 @@
+#![cfg(feature = "server")]
+
+use std::{convert::Infallible, future::Future, io, net::SocketAddr};
+
+use axum::{extract::Request, handler::Handler, response::IntoResponse, routing::Route, Router};
+use tokio::net::TcpListener;
+use tower::{Layer, Service};
+
+use crate::server_state::ServerState;
+
+type DeferredLayer = Box<dyn FnOnce(Router) -> Router + Send>;
+
+/// An application serving every route of a [ServerState], with the plumbing usually written
+/// around [ServerState::load_routes] by hand.
+///
+/// ```ignore
+/// ServerApp::new(AppState::default())
+///     .nest("/legacy", legacy_router())
+///     .fallback(|| async { (StatusCode::NOT_FOUND, "nothing here") })
+///     .layer(TraceLayer::new_for_http())
+///     .bind("0.0.0.0:3333")
+///     .serve()
+///     .await?;
+/// ```
+pub struct ServerApp {
+    router: Router,
+    layers: Vec<DeferredLayer>,
+    addr: String
+}
+
+impl ServerApp {
+    /// The address [ServerApp::serve] binds to unless [ServerApp::bind] is given another.
+    pub const DEFAULT_ADDR: &'static str = "127.0.0.1:3000";
+
+    pub fn new(state: impl ServerState) -> Self {
+        Self::from_router(state.load_routes())
+    }
+
+    pub fn from_router(router: Router) -> Self {
+        Self {
+            router,
+            layers: Vec::new(),
+            addr: Self::DEFAULT_ADDR.to_owned()
+        }
+    }
+
+    /// Mount `router` under the path prefix `path`.
+    pub fn nest(mut self, path: &str, router: Router) -> Self {
+        self.router = self.router.nest(path, router);
+        self
+    }
+
+    /// Serve the routes of `router` alongside the state's routes.
+    pub fn merge(mut self, router: Router) -> Self {
+        self.router = self.router.merge(router);
+        self
+    }
+
+    /// Answer requests that don't match any route with `handler`, instead of an empty 404.
+    pub fn fallback<H, T>(mut self, handler: H) -> Self
+    where
+        H: Handler<T, ()>,
+        T: 'static
+    {
+        self.router = self.router.fallback(handler);
+        self
+    }
+
+    /// Wrap the whole application, including nested routers and the fallback, in `layer`.
+    ///
+    /// Layers are applied in the order they're added, so the last one added is the outermost.
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + 'static,
+        L::Service: Service<Request> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static
+    {
+        self.layers
+            .push(Box::new(move |router| router.layer(layer)));
+        self
+    }
+
+    /// Set the address [ServerApp::serve] listens on.
+    pub fn bind(mut self, addr: impl Into<String>) -> Self {
+        self.addr = addr.into();
+        self
+    }
+
+    /// The finished router, with every layer applied.
+    pub fn into_router(self) -> Router {
+        self.layers
+            .into_iter()
+            .fold(self.router, |router, layer| layer(router))
+    }
+
+    /// Listen on the bound address and serve until SIGINT or SIGTERM, letting in-flight requests
+    /// finish before returning.
+    pub async fn serve(self) -> io::Result<()> {
+        let listener = TcpListener::bind(&self.addr).await?;
+
+        self.serve_until(listener, shutdown_signal()).await
+    }
+
+    /// Serve on `listener` until `signal` completes, letting in-flight requests finish before
+    /// returning.
+    ///
+    /// Requests carry the client's [ConnectInfo](axum::extract::ConnectInfo), which ip rate limits
+    /// are keyed by.
+    pub async fn serve_until(
+        self,
+        listener: TcpListener,
+        signal: impl Future<Output = ()> + Send + 'static
+    ) -> io::Result<()> {
+        let app = self
+            .into_router()
+            .into_make_service_with_connect_info::<SocketAddr>();
+
+        axum::serve(listener, app)
+            .with_graceful_shutdown(signal)
+            .await
+    }
+}
+
+/// Completes on the first SIGINT (ctrl-c), or SIGTERM on unix.
+pub async fn shutdown_signal() {
+    let interrupt = async {
+        // Without a handler there's nothing to wait on, so never complete.
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        http::{header::HeaderValue, Method, StatusCode},
+        response::Response,
+        routing::get
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        sync::oneshot
+    };
+    use tower::util::MapResponseLayer;
+
+    use super::*;
+    use crate::testing::TestServer;
+
+    fn app() -> ServerApp {
+        ServerApp::from_router(Router::new().route("/health", get(|| async { "ok" })))
+            .nest(
+                "/extra",
+                Router::new().route("/ping", get(|| async { "pong" }))
+            )
+            .fallback(|| async { (StatusCode::NOT_FOUND, "missing") })
+            .layer(MapResponseLayer::new(|mut response: Response| {
+                response
+                    .headers_mut()
+                    .insert("x-app", HeaderValue::from_static("server-fns"));
+                response
+            }))
+    }
+
+    #[tokio::test]
+    async fn build_router() {
+        let server = TestServer::from_router(app().into_router());
+
+        let response = server.request(Method::GET, "/extra/ping").send().await;
+        assert_eq!(response.text(), "pong");
+        assert_eq!(response.headers()["x-app"], "server-fns");
+
+        let response = server.request(Method::GET, "/nowhere").send().await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.text(), "missing");
+        assert_eq!(response.headers()["x-app"], "server-fns");
+    }
+
+    #[tokio::test]
+    async fn serve_until_shutdown() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown, signal) = oneshot::channel::<()>();
+
+        let server = tokio::spawn(app().serve_until(listener, async {
+            let _ = signal.await;
+        }));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("ok"));
+
+        shutdown.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
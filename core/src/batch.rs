@@ -0,0 +1,838 @@
+#![cfg(any(feature = "server", feature = "web"))]
+
+use serde_json::{json, Value};
+
+/// The most calls a single batch may carry.
+pub const MAX_BATCH_CALLS: usize = 64;
+
+/// One server function call carried by a batch: the request a client would otherwise have sent
+/// on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchCall {
+    /// The uppercase http method.
+    pub method: String,
+    /// The server function's url, including path params and any query string.
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    /// The encoded arguments sent as the request body.
+    pub body: Option<String>
+}
+
+/// The response to a [BatchCall], in the same position of the batch's response as the call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchResult {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    /// Whether `body` is base64 encoded, for responses that aren't utf-8.
+    pub base64: bool
+}
+
+impl BatchCall {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "method": self.method,
+            "url": self.url,
+            "headers": self.headers,
+            "body": self.body
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            method: value.get("method")?.as_str()?.to_owned(),
+            url: value.get("url")?.as_str()?.to_owned(),
+            headers: headers_from_json(value.get("headers"))?,
+            body: match value.get("body") {
+                None | Some(Value::Null) => None,
+                Some(body) => Some(body.as_str()?.to_owned())
+            }
+        })
+    }
+}
+
+impl BatchResult {
+    pub fn error(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: message.into(),
+            base64: false
+        }
+    }
+
+    /// The result of a call answered with `body`, base64 encoded if it isn't utf-8.
+    pub fn new(status: u16, headers: Vec<(String, String)>, body: Vec<u8>) -> Self {
+        let (body, base64) = match String::from_utf8(body) {
+            Ok(body) => (body, false),
+            Err(err) => (encode_base64(err.as_bytes()), true)
+        };
+
+        Self {
+            status,
+            headers,
+            body,
+            base64
+        }
+    }
+
+    /// The bytes of the response body, decoding it if it's base64 encoded.
+    pub fn body_bytes(&self) -> Option<Vec<u8>> {
+        match self.base64 {
+            true => decode_base64(&self.body),
+            false => Some(self.body.clone().into_bytes())
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut value = json!({
+            "status": self.status,
+            "headers": self.headers,
+            "body": self.body
+        });
+        if self.base64 {
+            value["base64"] = Value::Bool(true);
+        }
+
+        value
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            status: u16::try_from(value.get("status")?.as_u64()?).ok()?,
+            headers: headers_from_json(value.get("headers"))?,
+            body: value.get("body")?.as_str()?.to_owned(),
+            base64: match value.get("base64") {
+                None | Some(Value::Null) => false,
+                Some(base64) => base64.as_bool()?
+            }
+        })
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode `bytes` as padded, standard alphabet base64.
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | u32::from(*byte) << (16 - i * 8));
+
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(char::from(BASE64[(n >> (18 - i * 6)) as usize & 0x3F])),
+                false => encoded.push('=')
+            }
+        }
+    }
+
+    encoded
+}
+
+/// Decode padded, standard alphabet base64, or [None] if it's malformed.
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.as_bytes();
+    if !encoded.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(encoded.len() / 4 * 3);
+    for chunk in encoded.chunks(4) {
+        let padding = chunk
+            .iter()
+            .rev()
+            .take_while(|char_| **char_ == b'=')
+            .count();
+        if padding > 2 {
+            return None;
+        }
+
+        let mut n = 0u32;
+        for (i, char_) in chunk[..4 - padding].iter().enumerate() {
+            let sextet = BASE64.iter().position(|base64| base64 == char_)?;
+            n |= (sextet as u32) << (18 - i * 6);
+        }
+
+        bytes.extend(&n.to_be_bytes()[1..4 - padding]);
+    }
+
+    Some(bytes)
+}
+
+/// Headers written as `[name, value]` pairs, or none when missing.
+fn headers_from_json(headers: Option<&Value>) -> Option<Vec<(String, String)>> {
+    let Some(headers) = headers else {
+        return Some(Vec::new());
+    };
+
+    headers
+        .as_array()?
+        .iter()
+        .map(|pair| match pair.as_array()?.as_slice() {
+            [name, value] => Some((name.as_str()?.to_owned(), value.as_str()?.to_owned())),
+            _ => None
+        })
+        .collect()
+}
+
+/// Encode every item of a batch as a json array.
+pub fn encode_all<T>(items: &[T], to_json: fn(&T) -> Value) -> String {
+    Value::Array(items.iter().map(to_json).collect()).to_string()
+}
+
+/// Decode a json array of batch items, failing on any malformed item.
+pub fn decode_all<T>(body: &[u8], from_json: fn(&Value) -> Option<T>) -> Result<Vec<T>, String> {
+    let value = serde_json::from_slice::<Value>(body).map_err(|err| err.to_string())?;
+    let items = value
+        .as_array()
+        .ok_or_else(|| "Batches must be a json array".to_owned())?;
+
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| from_json(item).ok_or_else(|| format!("Malformed batch item at ({i})")))
+        .collect()
+}
+
+#[cfg(feature = "server")]
+pub use runtime::*;
+
+#[cfg(feature = "server")]
+mod runtime {
+    use std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc
+        }
+    };
+
+    use axum::{
+        body::{self, Body},
+        extract::{ConnectInfo, Request, State},
+        http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, StatusCode},
+        response::{IntoResponse, Response},
+        routing::post,
+        Router
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::csrf::CSRF_HEADER;
+
+    /// The largest batch body accepted, matching axum's default body limit, and the most bytes
+    /// the bodies of its calls' responses may take up together.
+    pub(super) const MAX_BATCH_BYTES: usize = 2 * 1024 * 1024;
+
+    /// Serve a batch route at `path`, dispatching each call it carries through `router`.
+    ///
+    /// Calls run concurrently and pass through every layer of the route they call, including its
+    /// forgery checks, so they inherit the batch request's headers. Calls can't override the
+    /// headers those checks trust.
+    pub fn route(router: Router, path: &str) -> Router {
+        let batch = Router::new()
+            .route(path, post(handle))
+            .with_state(router.clone());
+
+        router.merge(batch)
+    }
+
+    async fn handle(State(router): State<Router>, request: Request) -> Response {
+        let (parts, body) = request.into_parts();
+
+        let calls = match body::to_bytes(body, MAX_BATCH_BYTES).await {
+            Ok(body) => decode_all(&body, BatchCall::from_json),
+            Err(err) => Err(err.to_string())
+        };
+        let calls = match calls {
+            Ok(calls) if calls.len() > MAX_BATCH_CALLS => {
+                let message = format!("Batches may carry at most ({MAX_BATCH_CALLS}) calls");
+                return (StatusCode::BAD_REQUEST, message).into_response();
+            }
+            Ok(calls) => calls,
+            Err(err) => return (StatusCode::BAD_REQUEST, err).into_response()
+        };
+
+        let inherited = Inherited::of(&parts);
+        let remaining = Arc::new(AtomicUsize::new(MAX_BATCH_BYTES));
+        let handles = calls
+            .into_iter()
+            .map(|call| {
+                let router = router.clone();
+                let request = call_request(call, &inherited);
+                let remaining = remaining.clone();
+
+                tokio::spawn(async move {
+                    match request {
+                        Ok(request) => {
+                            let response = router
+                                .oneshot(request)
+                                .await
+                                .unwrap_or_else(|never| match never {});
+                            result_of(response, &remaining).await
+                        }
+                        Err(err) => BatchResult::error(400, err)
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut results = Vec::with_capacity(handles.len());
+        for handle in handles {
+            results.push(
+                handle
+                    .await
+                    .unwrap_or_else(|_| BatchResult::error(500, "Batched call panicked"))
+            );
+        }
+
+        (
+            [(header::CONTENT_TYPE, "application/json")],
+            encode_all(&results, BatchResult::to_json)
+        )
+            .into_response()
+    }
+
+    /// The parts of a request that every call dispatched on its behalf inherits.
+    #[derive(Debug, Clone)]
+    pub(crate) struct Inherited {
+        headers: HeaderMap,
+        connect_info: Option<ConnectInfo<SocketAddr>>
+    }
+
+    impl Inherited {
+        pub(crate) fn of(parts: &Parts) -> Self {
+            let mut headers = parts.headers.clone();
+            headers.remove(header::CONTENT_TYPE);
+            headers.remove(header::CONTENT_LENGTH);
+
+            Self {
+                headers,
+                connect_info: parts.extensions.get().copied()
+            }
+        }
+
+        /// Give `request` the inherited headers and client address.
+        pub(crate) fn apply(&self, request: &mut Request) {
+            let headers = request.headers_mut();
+            for (name, value) in &self.headers {
+                headers.append(name, value.clone());
+            }
+
+            if let Some(connect_info) = self.connect_info {
+                request.extensions_mut().insert(connect_info);
+            }
+        }
+    }
+
+    /// Whether a call's own `name` header is ignored in favour of the batch request's, since
+    /// forgery checks trust it.
+    fn is_inherited(name: &HeaderName) -> bool {
+        name == CSRF_HEADER
+            || name == header::ORIGIN
+            || name == header::HOST
+            || name.as_str().starts_with("sec-fetch-")
+    }
+
+    fn call_request(call: BatchCall, inherited: &Inherited) -> Result<Request, String> {
+        if !call.url.starts_with('/') {
+            return Err(format!("Batched urls must be paths; found ({})", call.url));
+        }
+
+        let mut request = Request::builder()
+            .method(call.method.as_str())
+            .uri(&call.url)
+            .body(Body::from(call.body.unwrap_or_default()))
+            .map_err(|err| err.to_string())?;
+
+        inherited.apply(&mut request);
+
+        for (name, value) in call.headers {
+            let name = HeaderName::try_from(name).map_err(|err| err.to_string())?;
+            let value = HeaderValue::try_from(value).map_err(|err| err.to_string())?;
+
+            if !is_inherited(&name) {
+                request.headers_mut().insert(name, value);
+            }
+        }
+
+        Ok(request)
+    }
+
+    /// The result of a call answered with `response`, reading its body out of the `remaining`
+    /// bytes the batch's responses may still take up.
+    async fn result_of(response: Response, remaining: &AtomicUsize) -> BatchResult {
+        let (parts, body) = response.into_parts();
+
+        let too_large = || {
+            let message =
+                format!("Batched responses may take up at most ({MAX_BATCH_BYTES}) bytes");
+            BatchResult::error(500, message)
+        };
+        let Ok(body) = body::to_bytes(body, remaining.load(Ordering::Relaxed)).await else {
+            return too_large();
+        };
+        let reserved = remaining.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| {
+            remaining.checked_sub(body.len())
+        });
+        if reserved.is_err() {
+            return too_large();
+        }
+
+        let headers = parts
+            .headers
+            .iter()
+            .filter(|(name, _)| *name != header::CONTENT_LENGTH)
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+            .collect();
+
+        BatchResult::new(parts.status.as_u16(), headers, body.into())
+    }
+}
+
+#[cfg(feature = "web")]
+pub use client::*;
+
+#[cfg(feature = "web")]
+mod client {
+    use std::{
+        future::Future,
+        mem,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll, Waker}
+    };
+
+    use super::*;
+    use crate::client::{BoxFuture, ClientRequest, ClientResponse, ServerFnError, Transport};
+
+    /// A [Transport] coalescing the calls made in the same tick into a single request to a
+    /// state's batch route, sending the rest through `transport`.
+    ///
+    /// ```ignore
+    /// set_transport(BatchTransport::new("/api/batch", FetchTransport));
+    /// ```
+    #[derive(Clone)]
+    pub struct BatchTransport {
+        path: Arc<str>,
+        transport: Arc<dyn Transport>,
+        queue: Arc<Mutex<Vec<Queued>>>
+    }
+
+    struct Queued {
+        request: ClientRequest,
+        slot: Arc<Mutex<Slot>>
+    }
+
+    #[derive(Default)]
+    struct Slot {
+        result: Option<Result<ClientResponse, ServerFnError>>,
+        waker: Option<Waker>
+    }
+
+    impl BatchTransport {
+        pub fn new(path: impl Into<String>, transport: impl Transport) -> Self {
+            Self {
+                path: path.into().into(),
+                transport: Arc::new(transport),
+                queue: Default::default()
+            }
+        }
+
+        /// Send every queued call, as a batch when there's more than one.
+        async fn flush(&self, queued: Vec<Queued>) {
+            let (requests, slots): (Vec<_>, Vec<_>) = queued
+                .into_iter()
+                .map(|queued| (queued.request, queued.slot))
+                .unzip();
+            let unsent = Unsent(slots);
+
+            let results = match <[_; 1]>::try_from(requests) {
+                Ok([request]) => vec![self.transport.send(request).await],
+                Err(requests) => self.send_batch(requests).await
+            };
+
+            for (slot, result) in unsent.0.iter().zip(results) {
+                fill(slot, result);
+            }
+        }
+
+        async fn send_batch(
+            &self,
+            requests: Vec<ClientRequest>
+        ) -> Vec<Result<ClientResponse, ServerFnError>> {
+            let count = requests.len();
+            let calls = requests
+                .into_iter()
+                .map(|request| BatchCall {
+                    method: request.method.to_owned(),
+                    url: request.url,
+                    headers: request.headers,
+                    body: request.body
+                })
+                .collect::<Vec<_>>();
+
+            let mut batch =
+                ClientRequest::new("POST", &*self.path).header("Content-Type", "application/json");
+            batch.body = Some(encode_all(&calls, BatchCall::to_json));
+
+            let results = self
+                .transport
+                .send(batch)
+                .await
+                .and_then(ClientResponse::bytes)
+                .and_then(|body| {
+                    decode_all(&body, BatchResult::from_json).map_err(ServerFnError::Decode)
+                })
+                .and_then(|results| match results.len() {
+                    len if len == count => Ok(results),
+                    len => Err(ServerFnError::Decode(format!(
+                        "Batch answered ({len}) of ({count}) calls"
+                    )))
+                });
+
+            match results {
+                Ok(results) => results
+                    .into_iter()
+                    .map(|result| {
+                        let body = result.body_bytes().ok_or_else(|| {
+                            ServerFnError::Decode("Malformed base64 batched body".to_owned())
+                        })?;
+
+                        Ok(ClientResponse {
+                            status: result.status,
+                            headers: result.headers,
+                            body
+                        })
+                    })
+                    .collect(),
+                Err(err) => vec![Err(err); count]
+            }
+        }
+    }
+
+    impl Transport for BatchTransport {
+        fn send(
+            &self,
+            request: ClientRequest
+        ) -> BoxFuture<'static, Result<ClientResponse, ServerFnError>> {
+            let slot = Arc::new(Mutex::new(Slot::default()));
+            lock(&self.queue).push(Queued {
+                request,
+                slot: slot.clone()
+            });
+
+            let this = self.clone();
+            Box::pin(async move {
+                YieldOnce(false).await;
+
+                // The first call polled after the others were made sends all of them.
+                let queued = {
+                    let mut queue = lock(&this.queue);
+                    let waiting = queue.iter().any(|queued| Arc::ptr_eq(&queued.slot, &slot));
+                    waiting.then(|| mem::take(&mut *queue))
+                };
+                if let Some(queued) = queued {
+                    this.flush(queued).await;
+                }
+
+                SlotFuture(slot).await
+            })
+        }
+    }
+
+    fn fill(slot: &Mutex<Slot>, result: Result<ClientResponse, ServerFnError>) {
+        let mut slot = lock(slot);
+        slot.result = Some(result);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// The slots of a flush, failed when the call sending them is dropped before they're filled,
+    /// so the calls waiting on them don't hang.
+    struct Unsent(Vec<Arc<Mutex<Slot>>>);
+
+    impl Drop for Unsent {
+        fn drop(&mut self) {
+            for slot in &self.0 {
+                if lock(slot).result.is_none() {
+                    let err = "The call sending the batch was dropped".to_owned();
+                    fill(slot, Err(ServerFnError::Transport(err)));
+                }
+            }
+        }
+    }
+
+    fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+        mutex.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Returns pending once, letting calls made in the same tick queue up behind it.
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    struct SlotFuture(Arc<Mutex<Slot>>);
+
+    impl Future for SlotFuture {
+        type Output = Result<ClientResponse, ServerFnError>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let mut slot = lock(&self.0);
+
+            match slot.result.take() {
+                Some(result) => Poll::Ready(result),
+                None => {
+                    slot.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_batches() {
+        let calls = vec![
+            BatchCall {
+                method: "POST".into(),
+                url: "/api/echo/2".into(),
+                headers: vec![("content-type".into(), "application/json".into())],
+                body: Some("\"hi\"".into())
+            },
+            BatchCall {
+                method: "GET".into(),
+                url: "/api/health".into(),
+                headers: Vec::new(),
+                body: None
+            },
+        ];
+
+        let encoded = encode_all(&calls, BatchCall::to_json);
+        assert_eq!(
+            decode_all(encoded.as_bytes(), BatchCall::from_json),
+            Ok(calls)
+        );
+
+        assert!(decode_all(b"{}", BatchCall::from_json).is_err());
+        assert!(decode_all(br#"[{"method": "GET"}]"#, BatchCall::from_json).is_err());
+        assert_eq!(
+            decode_all(br#"[{"status": 204, "body": ""}]"#, BatchResult::from_json),
+            Ok(vec![BatchResult::error(204, "")])
+        );
+
+        let bytes = BatchResult::new(200, Vec::new(), vec![0xFF, 0, 1, 2, 0xFE]);
+        assert!(bytes.base64);
+        assert_eq!(bytes.body, "/wABAv4=");
+        let decoded = decode_all(
+            encode_all(&[bytes], BatchResult::to_json).as_bytes(),
+            BatchResult::from_json
+        )
+        .unwrap();
+        assert_eq!(decoded[0].body_bytes().unwrap(), [0xFF, 0, 1, 2, 0xFE]);
+
+        for len in 0..6 {
+            let bytes = (0..len).map(|byte| byte * 51).collect::<Vec<u8>>();
+            assert_eq!(decode_base64(&encode_base64(&bytes)).unwrap(), bytes);
+        }
+        assert_eq!(encode_base64(b"hi"), "aGk=");
+        assert_eq!(decode_base64("a!k="), None);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn dispatch_calls() {
+        use axum::{
+            http::{HeaderMap, Method, StatusCode},
+            routing::{get, post},
+            Router
+        };
+
+        use crate::{csrf::CSRF_HEADER, testing::TestServer};
+
+        let router = Router::new()
+            .route("/api/health", get(|| async { "ok" }))
+            .route(
+                "/api/echo",
+                post(|headers: HeaderMap, body: String| async move {
+                    format!("{body} {}", headers.contains_key(CSRF_HEADER))
+                })
+            );
+        let server = TestServer::from_router(route(router, "/api/batch"));
+
+        let calls = serde_json::json!([
+            { "method": "GET", "url": "/api/health" },
+            { "method": "POST", "url": "/api/echo", "body": "hi", "headers": [[CSRF_HEADER, "1"]] },
+            { "method": "GET", "url": "/api/missing" },
+            { "method": "GET", "url": "https://elsewhere.test/" }
+        ]);
+        let response = server
+            .request(Method::POST, "/api/batch")
+            .json(&calls)
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let results = decode_all(response.body(), BatchResult::from_json).unwrap();
+        let statuses = results
+            .iter()
+            .map(|result| result.status)
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, vec![200, 200, 404, 400]);
+        assert_eq!(results[0].body, "ok");
+        assert_eq!(results[1].body, "hi false");
+
+        let response = server
+            .request(Method::POST, "/api/batch")
+            .header(CSRF_HEADER, "1")
+            .json(&serde_json::json!([{ "method": "POST", "url": "/api/echo", "body": "hi" }]))
+            .send()
+            .await;
+        let results = decode_all(response.body(), BatchResult::from_json).unwrap();
+        assert_eq!(results[0].body, "hi true");
+
+        let response = server
+            .request(Method::POST, "/api/batch")
+            .text("not json")
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn dispatch_byte_responses() {
+        use axum::{http::Method, routing::get, Router};
+
+        use super::runtime::MAX_BATCH_BYTES;
+        use crate::testing::TestServer;
+
+        let router = Router::new()
+            .route("/api/bytes", get(|| async { vec![0xFF_u8, 0] }))
+            .route(
+                "/api/large",
+                get(|| async { vec![b'a'; MAX_BATCH_BYTES / 2 + 1] })
+            );
+        let server = TestServer::from_router(route(router, "/api/batch"));
+
+        let calls = serde_json::json!([
+            { "method": "GET", "url": "/api/bytes" },
+            { "method": "GET", "url": "/api/large" },
+            { "method": "GET", "url": "/api/large" }
+        ]);
+        let response = server
+            .request(Method::POST, "/api/batch")
+            .json(&calls)
+            .send()
+            .await;
+        let results = decode_all(response.body(), BatchResult::from_json).unwrap();
+
+        assert_eq!(results[0].status, 200);
+        assert_eq!(results[0].body_bytes().unwrap(), [0xFF, 0]);
+        // The second large response goes over what the batch's responses may take up together.
+        let statuses = [results[1].status, results[2].status];
+        assert!(statuses.contains(&200) && statuses.contains(&500));
+    }
+
+    #[cfg(feature = "web")]
+    #[tokio::test]
+    async fn coalesce_calls() {
+        use crate::client::{with_transport, ClientRequest, ClientResponse, MockTransport};
+
+        let results = [
+            BatchResult::error(200, "ok"),
+            BatchResult::error(404, "missing")
+        ];
+        let mock = MockTransport::default();
+        mock.respond_with(
+            "/api/batch",
+            ClientResponse::new(200, encode_all(&results, BatchResult::to_json))
+        )
+        .respond_text("/api/health", "alone");
+        let batch = BatchTransport::new("/api/batch", mock.clone());
+
+        let (health, missing) = with_transport(batch.clone(), async {
+            tokio::join!(
+                ClientRequest::new("GET", "/api/health").send(),
+                ClientRequest::new("POST", "/api/missing").send()
+            )
+        })
+        .await;
+        assert_eq!(health.unwrap().text(), Ok("ok".to_owned()));
+        assert_eq!(missing.unwrap().status, 404);
+
+        let calls = mock.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].url, "/api/batch");
+        let batched = decode_all(
+            calls[0].body.as_deref().unwrap().as_bytes(),
+            BatchCall::from_json
+        )
+        .unwrap();
+        assert_eq!(batched[0].url, "/api/health");
+        assert_eq!(batched[1].method, "POST");
+
+        let alone = with_transport(batch, ClientRequest::new("GET", "/api/health").send()).await;
+        assert_eq!(alone.unwrap().text(), Ok("alone".to_owned()));
+        assert_eq!(mock.calls().len(), 2);
+    }
+
+    #[cfg(feature = "web")]
+    #[test]
+    fn fail_calls_of_dropped_flush() {
+        use std::{
+            future::pending,
+            task::{Context, Poll, Waker}
+        };
+
+        use crate::client::{BoxFuture, ClientRequest, ClientResponse, ServerFnError, Transport};
+
+        struct Pending;
+
+        impl Transport for Pending {
+            fn send(
+                &self,
+                _: ClientRequest
+            ) -> BoxFuture<'static, Result<ClientResponse, ServerFnError>> {
+                Box::pin(pending())
+            }
+        }
+
+        let batch = BatchTransport::new("/api/batch", Pending);
+        let mut leader = batch.send(ClientRequest::new("GET", "/api/a"));
+        let mut follower = batch.send(ClientRequest::new("GET", "/api/b"));
+        let mut cx = Context::from_waker(Waker::noop());
+
+        // The leader yields once, then takes the queue and waits on the transport.
+        assert!(leader.as_mut().poll(&mut cx).is_pending());
+        assert!(follower.as_mut().poll(&mut cx).is_pending());
+        assert!(leader.as_mut().poll(&mut cx).is_pending());
+        drop(leader);
+
+        let Poll::Ready(result) = follower.as_mut().poll(&mut cx) else {
+            panic!("The follower's call wasn't failed");
+        };
+        assert!(matches!(result, Err(ServerFnError::Transport(_))));
+    }
+}
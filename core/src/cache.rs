@@ -0,0 +1,448 @@
+#![cfg(feature = "server")]
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    sync::{Arc, Mutex, OnceLock, RwLock},
+    time::{Duration, SystemTime}
+};
+
+use axum::{
+    body::{self, Body, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response}
+};
+use itertools::Itertools;
+
+use crate::rate_limit::BoxFuture;
+
+/// The response cache of a single route, generated from its `cache(..)` server argument.
+#[derive(Debug, Clone, Copy)]
+pub struct Cache {
+    /// Keeps the responses of different routes apart within a shared cache, and names them for
+    /// [invalidate].
+    pub scope: &'static str,
+    pub ttl: Duration,
+    /// Request headers that responses vary by, alongside the path and query.
+    pub key: &'static [&'static str]
+}
+
+impl Cache {
+    /// The key `request`'s response is cached under within [Cache::scope].
+    pub fn key_of(&self, request: &Request) -> String {
+        let mut key = request
+            .uri()
+            .path_and_query()
+            .map_or("/", |path| path.as_str())
+            .to_owned();
+
+        for name in self.key {
+            let values = request
+                .headers()
+                .get_all(*name)
+                .iter()
+                .map(|value| String::from_utf8_lossy(value.as_bytes()))
+                .join(",");
+            let _ = write!(key, "\n{name}: {values}");
+        }
+
+        key
+    }
+
+    /// Whether responses vary by the request header `name`.
+    pub fn keyed_by(&self, name: &str) -> bool {
+        self.key.iter().any(|key| key.eq_ignore_ascii_case(name))
+    }
+}
+
+/// An encoded response held by a [ResponseCache].
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    /// When the response was produced, which its `Age` header counts from.
+    pub stored: SystemTime,
+    pub ttl: Duration
+}
+
+impl CachedResponse {
+    /// How long ago the response was stored, or [None] once its ttl has passed.
+    pub fn age(&self, now: SystemTime) -> Option<Duration> {
+        let age = now.duration_since(self.stored).unwrap_or_default();
+
+        (age < self.ttl).then_some(age)
+    }
+
+    /// The response to serve `age` after it was stored.
+    fn respond(&self, cache: &Cache, age: Duration) -> Response {
+        let mut response = (self.status, Body::from(self.body.clone())).into_response();
+        *response.headers_mut() = self.headers.clone();
+
+        let headers = response.headers_mut();
+        // Clients subtract the `Age` from `max-age`, so it's always the full ttl. The handler's
+        // own `Cache-Control` is kept.
+        if !headers.contains_key(header::CACHE_CONTROL) {
+            if let Ok(max_age) = HeaderValue::from_str(&format!("max-age={}", self.ttl.as_secs())) {
+                headers.insert(header::CACHE_CONTROL, max_age);
+            }
+        }
+        headers.insert(header::AGE, HeaderValue::from(age.as_secs()));
+        if !cache.key.is_empty() {
+            if let Ok(vary) = HeaderValue::from_str(&cache.key.join(", ")) {
+                headers.insert(header::VARY, vary);
+            }
+        }
+
+        response
+    }
+}
+
+/// Responses shared by every cached route, installed with [set_response_cache].
+///
+/// Implement it over a shared cache to reuse responses across server instances; the default is a
+/// [MemoryCache] local to the process.
+pub trait ResponseCache: Send + Sync + 'static {
+    fn get(&self, scope: &'static str, key: String) -> BoxFuture<'_, Option<CachedResponse>>;
+
+    fn insert(
+        &self,
+        scope: &'static str,
+        key: String,
+        response: CachedResponse
+    ) -> BoxFuture<'_, ()>;
+
+    /// Drop every response cached for the route with `scope`.
+    fn invalidate(&self, scope: &'static str) -> BoxFuture<'_, ()>;
+}
+
+static CACHE: RwLock<Option<Arc<dyn ResponseCache>>> = RwLock::new(None);
+
+/// Replace the cache every cached route stores its responses in.
+pub fn set_response_cache(cache: impl ResponseCache) {
+    let mut global = CACHE.write().unwrap_or_else(|err| err.into_inner());
+    *global = Some(Arc::new(cache));
+}
+
+fn response_cache() -> Arc<dyn ResponseCache> {
+    static DEFAULT: OnceLock<Arc<MemoryCache>> = OnceLock::new();
+
+    let global = CACHE.read().unwrap_or_else(|err| err.into_inner());
+    match global.as_ref() {
+        Some(cache) => cache.clone(),
+        None => DEFAULT.get_or_init(Default::default).clone()
+    }
+}
+
+/// Drop every cached response of a server function, given its [Cache::scope].
+///
+/// Cached server functions generate an `invalidate_<name>_cache` function calling this.
+pub async fn invalidate(scope: &'static str) {
+    response_cache().invalidate(scope).await;
+}
+
+type EntryId = (&'static str, String);
+
+/// In-memory responses, evicting the least recently used once [MemoryCache::capacity] are held.
+#[derive(Debug)]
+pub struct MemoryCache {
+    capacity: usize,
+    entries: Mutex<Lru>
+}
+
+#[derive(Debug, Default)]
+struct Lru {
+    /// Each response with the tick it was last used at.
+    entries: HashMap<EntryId, (CachedResponse, u64)>,
+    recency: BTreeMap<u64, EntryId>,
+    tick: u64
+}
+
+impl MemoryCache {
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: Default::default()
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn lru(&self) -> std::sync::MutexGuard<'_, Lru> {
+        self.entries.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Default for MemoryCache {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl Lru {
+    fn get(&mut self, id: &EntryId, now: SystemTime) -> Option<CachedResponse> {
+        let (response, used) = self.entries.get_mut(id)?;
+
+        if response.age(now).is_none() {
+            self.remove(id);
+            return None;
+        }
+
+        self.tick += 1;
+        self.recency.remove(used);
+        self.recency.insert(self.tick, id.clone());
+        *used = self.tick;
+
+        Some(response.clone())
+    }
+
+    fn insert(&mut self, id: EntryId, response: CachedResponse, capacity: usize) {
+        self.remove(&id);
+
+        while self.entries.len() >= capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+
+        self.tick += 1;
+        self.recency.insert(self.tick, id.clone());
+        self.entries.insert(id, (response, self.tick));
+    }
+
+    fn remove(&mut self, id: &EntryId) {
+        if let Some((_, used)) = self.entries.remove(id) {
+            self.recency.remove(&used);
+        }
+    }
+}
+
+impl ResponseCache for MemoryCache {
+    fn get(&self, scope: &'static str, key: String) -> BoxFuture<'_, Option<CachedResponse>> {
+        let response = self.lru().get(&(scope, key), SystemTime::now());
+
+        Box::pin(async move { response })
+    }
+
+    fn insert(
+        &self,
+        scope: &'static str,
+        key: String,
+        response: CachedResponse
+    ) -> BoxFuture<'_, ()> {
+        self.lru().insert((scope, key), response, self.capacity);
+
+        Box::pin(async {})
+    }
+
+    fn invalidate(&self, scope: &'static str) -> BoxFuture<'_, ()> {
+        let mut lru = self.lru();
+        lru.entries.retain(|(cached, _), _| *cached != scope);
+        lru.recency.retain(|_, (cached, _)| *cached != scope);
+
+        Box::pin(async {})
+    }
+}
+
+/// Middleware serving `GET` requests from the cache while their response is fresh, and caching
+/// the handler's `200 OK` responses otherwise.
+///
+/// Responses setting cookies are never cached, since they'd be served to every client, and
+/// neither are requests carrying credentials in `Authorization` or `Cookie` unless the cache is
+/// keyed by that header.
+pub async fn cache(State(cache): State<Cache>, request: Request, next: Next) -> Response {
+    let credentialed = [header::AUTHORIZATION, header::COOKIE]
+        .iter()
+        .any(|name| request.headers().contains_key(name) && !cache.keyed_by(name.as_str()));
+
+    if !matches!(*request.method(), Method::GET | Method::HEAD) || credentialed {
+        return next.run(request).await;
+    }
+
+    let key = cache.key_of(&request);
+    let responses = response_cache();
+
+    if let Some(hit) = responses.get(cache.scope, key.clone()).await {
+        if let Some(age) = hit.age(SystemTime::now()) {
+            return hit.respond(&cache, age);
+        }
+    }
+
+    let response = next.run(request).await;
+    let uncacheable = response
+        .headers()
+        .get(header::CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("no-store") || value.contains("private"));
+    if response.status() != StatusCode::OK
+        || response.headers().contains_key(header::SET_COOKIE)
+        || uncacheable
+    {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let Ok(body) = body::to_bytes(body, usize::MAX).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let cached = CachedResponse {
+        status: parts.status,
+        headers: parts.headers,
+        body,
+        stored: SystemTime::now(),
+        ttl: cache.ttl
+    };
+    let response = cached.respond(&cache, Duration::ZERO);
+    responses.insert(cache.scope, key, cached).await;
+
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{middleware::from_fn_with_state, routing::get, Router};
+
+    use super::*;
+    use crate::testing::TestServer;
+
+    fn cached(body: &'static str) -> CachedResponse {
+        CachedResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(body.as_bytes()),
+            stored: SystemTime::now(),
+            ttl: Duration::from_secs(60)
+        }
+    }
+
+    #[tokio::test]
+    async fn evict_least_recently_used() {
+        let memory = MemoryCache::new(2);
+
+        memory.insert("route", "/a".into(), cached("a")).await;
+        memory.insert("route", "/b".into(), cached("b")).await;
+        assert!(memory.get("route", "/a".into()).await.is_some());
+
+        memory.insert("route", "/c".into(), cached("c")).await;
+        assert!(memory.get("route", "/b".into()).await.is_none());
+        assert_eq!(memory.get("route", "/a".into()).await.unwrap().body, "a");
+        assert_eq!(memory.get("route", "/c".into()).await.unwrap().body, "c");
+
+        memory.insert("other", "/a".into(), cached("other")).await;
+        memory.invalidate("route").await;
+        assert!(memory.get("route", "/a".into()).await.is_none());
+        assert!(memory.get("other", "/a".into()).await.is_some());
+    }
+
+    #[test]
+    fn expire_responses() {
+        let response = cached("stale");
+
+        assert_eq!(response.age(response.stored), Some(Duration::ZERO));
+        assert_eq!(
+            response.age(response.stored + Duration::from_secs(59)),
+            Some(Duration::from_secs(59))
+        );
+        assert_eq!(
+            response.age(response.stored + Duration::from_secs(60)),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn serve_hits() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let cache = Cache {
+            scope: "cache::test::serve_hits",
+            ttl: Duration::from_secs(60),
+            key: &["x-tenant"]
+        };
+        let router = Router::new()
+            .route(
+                "/cached",
+                get(|| async { CALLS.fetch_add(1, Ordering::SeqCst).to_string() })
+            )
+            .route_layer(from_fn_with_state(cache, super::cache));
+        let server = TestServer::from_router(router);
+
+        let send = |uri: &'static str, tenant: &'static str| {
+            server
+                .request(Method::GET, uri)
+                .header("x-tenant", tenant)
+                .send()
+        };
+
+        let response = send("/cached", "a").await;
+        assert_eq!(response.text(), "0");
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "max-age=60");
+        assert_eq!(response.headers()[header::AGE], "0");
+        assert_eq!(response.headers()[header::VARY], "x-tenant");
+
+        assert_eq!(send("/cached", "a").await.text(), "0");
+        assert_eq!(send("/cached?page=2", "a").await.text(), "1");
+        assert_eq!(send("/cached", "b").await.text(), "2");
+        assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+
+        invalidate(cache.scope).await;
+        assert_eq!(send("/cached", "a").await.text(), "3");
+    }
+
+    #[tokio::test]
+    async fn skip_credentialed_requests() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let handler = || async {
+            let calls = CALLS.fetch_add(1, Ordering::SeqCst).to_string();
+            ([(header::CACHE_CONTROL, "public, max-age=5")], calls)
+        };
+        let cache = |scope, key| Cache {
+            scope,
+            ttl: Duration::from_secs(60),
+            key
+        };
+        let router = Router::new()
+            .route(
+                "/shared",
+                get(handler).route_layer(from_fn_with_state(
+                    cache("cache::test::shared", &[]),
+                    super::cache
+                ))
+            )
+            .route(
+                "/keyed",
+                get(handler).route_layer(from_fn_with_state(
+                    cache("cache::test::keyed", &["Cookie"]),
+                    super::cache
+                ))
+            );
+        let server = TestServer::from_router(router);
+        let send = |uri: &'static str| {
+            server
+                .request(Method::GET, uri)
+                .header(header::COOKIE, "session=a")
+                .send()
+        };
+
+        assert_eq!(send("/shared").await.text(), "0");
+        assert_eq!(send("/shared").await.text(), "1");
+
+        let response = send("/keyed").await;
+        assert_eq!(response.text(), "2");
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=5"
+        );
+        assert_eq!(send("/keyed").await.text(), "2");
+    }
+}
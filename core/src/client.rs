@@ -0,0 +1,645 @@
+#![cfg(feature = "web")]
+
+use std::{
+    cell::RefCell,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, RwLock},
+    task::{Context, Poll}
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::{
+    csrf::{is_unsafe, CSRF_HEADER},
+    limit::{Limit, LIMIT_HEADER},
+    path::path_matches,
+    trace::{current_trace_context, TRACEPARENT_HEADER}
+};
+
+/// The error returned by generated `<method>_<name>_fetch` clients.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ServerFnError {
+    #[error("Failed to send request; {0}")]
+    Transport(String),
+
+    #[error("Server function responded with ({status}); {body}")]
+    Status { status: u16, body: String },
+
+    #[error("Failed to encode request; {0}")]
+    Encode(String),
+
+    #[error("Failed to decode response; {0}")]
+    Decode(String),
+
+    #[error("Server function timed out")]
+    Timeout,
+
+    #[error("Request body is larger than the server function accepts")]
+    PayloadTooLarge,
+
+    #[error("Server function is handling too many requests at once")]
+    Overloaded,
+
+    #[error("Too many requests to the server function")]
+    RateLimited {
+        /// Seconds to wait before retrying, from the `Retry-After` header.
+        retry_after: Option<u64>
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+#[cfg(target_arch = "wasm32")]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Sends the requests built by generated clients.
+///
+/// In the browser requests go through `fetch` by default. Anything else, like a
+/// [MockTransport] in tests, is installed with [set_transport] or [with_transport].
+pub trait Transport: Send + Sync + 'static {
+    fn send(
+        &self,
+        request: ClientRequest
+    ) -> BoxFuture<'static, Result<ClientResponse, ServerFnError>>;
+}
+
+static TRANSPORT: RwLock<Option<Arc<dyn Transport>>> = RwLock::new(None);
+
+thread_local! {
+    static SCOPED_TRANSPORT: RefCell<Option<Arc<dyn Transport>>> = const { RefCell::new(None) };
+}
+
+/// Replace the transport used by every client call that isn't inside [with_transport].
+pub fn set_transport(transport: impl Transport) {
+    let mut global = TRANSPORT.write().unwrap_or_else(|err| err.into_inner());
+    *global = Some(Arc::new(transport));
+}
+
+/// Run `future` with every client call it makes sent through `transport`.
+///
+/// The override only applies while `future` is polled, so concurrently running tests can each
+/// script their own transport.
+pub fn with_transport<F: Future>(transport: impl Transport, future: F) -> WithTransport<F> {
+    WithTransport {
+        transport: Arc::new(transport),
+        future: Box::pin(future)
+    }
+}
+
+pub struct WithTransport<F> {
+    transport: Arc<dyn Transport>,
+    future: Pin<Box<F>>
+}
+
+impl<F: Future> Future for WithTransport<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let transport = self.transport.clone();
+        let outer = SCOPED_TRANSPORT.with(|scoped| scoped.replace(Some(transport)));
+        let poll = self.future.as_mut().poll(cx);
+
+        SCOPED_TRANSPORT.with(|scoped| scoped.replace(outer));
+        poll
+    }
+}
+
+fn current_transport() -> Option<Arc<dyn Transport>> {
+    if let Some(scoped) = SCOPED_TRANSPORT.with(|scoped| scoped.borrow().clone()) {
+        return Some(scoped);
+    }
+
+    if let Some(global) = TRANSPORT
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone()
+    {
+        return Some(global);
+    }
+
+    default_transport()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn default_transport() -> Option<Arc<dyn Transport>> {
+    Some(Arc::new(FetchTransport))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_transport() -> Option<Arc<dyn Transport>> {
+    None
+}
+
+/// Sends requests with the browser's `fetch`.
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FetchTransport;
+
+#[cfg(target_arch = "wasm32")]
+impl Transport for FetchTransport {
+    fn send(
+        &self,
+        request: ClientRequest
+    ) -> BoxFuture<'static, Result<ClientResponse, ServerFnError>> {
+        use gloo_net::http::{Method, RequestBuilder};
+
+        Box::pin(async move {
+            let transport = |err: gloo_net::Error| ServerFnError::Transport(err.to_string());
+
+            let method = Method::from_bytes(request.method.as_bytes())
+                .map_err(|err| ServerFnError::Encode(err.to_string()))?;
+            let mut builder = RequestBuilder::new(&request.url).method(method);
+
+            for (key, value) in &request.headers {
+                builder = builder.header(key, value);
+            }
+
+            let response = match request.body {
+                Some(body) => builder.body(body).map_err(transport)?.send().await,
+                None => builder.send().await
+            }
+            .map_err(transport)?;
+
+            Ok(ClientResponse {
+                status: response.status(),
+                headers: response.headers().entries().collect(),
+                body: response.binary().await.map_err(transport)?
+            })
+        })
+    }
+}
+
+/// A request built by a generated client, recording the arguments it was called with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientRequest {
+    /// The uppercase http method.
+    pub method: &'static str,
+    /// The requested url, including any query string.
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    /// The arguments of the call by name, as json.
+    pub args: Map<String, Value>,
+    error: Option<ServerFnError>
+}
+
+impl ClientRequest {
+    /// A request to `url`, carrying [CSRF_HEADER] when `method` may change state and the
+    /// [current_trace_context] when there is one.
+    pub fn new(method: &'static str, url: impl Into<String>) -> Self {
+        let mut request = Self {
+            method,
+            url: url.into(),
+            headers: Vec::new(),
+            body: None,
+            args: Map::new(),
+            error: None
+        };
+
+        if is_unsafe(method) {
+            request = request.header(CSRF_HEADER, "1");
+        }
+
+        match current_trace_context() {
+            Some(context) => request.header(TRACEPARENT_HEADER, context.to_string()),
+            None => request
+        }
+    }
+
+    /// The requested url without its query string.
+    pub fn path(&self) -> &str {
+        self.url.split('?').next().unwrap_or(&self.url)
+    }
+
+    /// Decode the recorded argument `name`.
+    pub fn arg<T: DeserializeOwned>(&self, name: &str) -> Result<T, ServerFnError> {
+        let value = self.args.get(name).cloned().unwrap_or(Value::Null);
+
+        serde_json::from_value(value).map_err(|err| ServerFnError::Decode(err.to_string()))
+    }
+
+    pub fn header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((key.into(), value.into()));
+        self
+    }
+
+    /// Record a path parameter the url was built with.
+    pub fn param(self, name: &str, value: impl fmt::Display) -> Self {
+        self.record(name, &value.to_string())
+    }
+
+    /// Url encode `query` onto the request url, as read by `axum::extract::Query`.
+    pub fn query<T: Serialize>(mut self, name: &str, query: &T) -> Self {
+        match serde_urlencoded::to_string(query) {
+            Ok(encoded) if encoded.is_empty() => {}
+            Ok(encoded) => {
+                let separator = if self.url.contains('?') { '&' } else { '?' };
+                self.url = format!("{}{separator}{encoded}", self.url);
+            }
+            Err(err) => self.error = Some(ServerFnError::Encode(err.to_string()))
+        }
+
+        self.record(name, query)
+    }
+
+    /// Set a json body, as read by `axum::Json`.
+    pub fn json<T: Serialize>(self, name: &str, body: &T) -> Self {
+        match serde_json::to_string(body) {
+            Ok(encoded) => self.body("application/json", encoded),
+            Err(err) => self.fail(err)
+        }
+        .record(name, body)
+    }
+
+    /// Set a url encoded body, as read by `axum::Form`.
+    pub fn form<T: Serialize>(self, name: &str, body: &T) -> Self {
+        match serde_urlencoded::to_string(body) {
+            Ok(encoded) => self.body("application/x-www-form-urlencoded", encoded),
+            Err(err) => self.fail(err)
+        }
+        .record(name, body)
+    }
+
+    /// Set a plain text body, as read by [String].
+    pub fn text(self, name: &str, body: impl Into<String>) -> Self {
+        let body = body.into();
+
+        self.record(name, &body)
+            .body("text/plain; charset=utf-8", body)
+    }
+
+    /// Send the request through the current [Transport].
+    pub async fn send(self) -> Result<ClientResponse, ServerFnError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        let transport = current_transport().ok_or_else(|| {
+            ServerFnError::Transport("no transport is configured for server function calls".into())
+        })?;
+
+        transport.send(self).await
+    }
+
+    fn body(mut self, content_type: &str, body: String) -> Self {
+        self.body = Some(body);
+        self.header("Content-Type", content_type)
+    }
+
+    fn record<T: Serialize + ?Sized>(mut self, name: &str, value: &T) -> Self {
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                self.args.insert(name.to_owned(), value);
+            }
+            Err(err) => self.error = Some(ServerFnError::Encode(err.to_string()))
+        }
+
+        self
+    }
+
+    fn fail(mut self, err: impl fmt::Display) -> Self {
+        self.error = Some(ServerFnError::Encode(err.to_string()));
+        self
+    }
+}
+
+/// A buffered response to a [ClientRequest].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>
+}
+
+impl ClientResponse {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into()
+        }
+    }
+
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Decode a json body, failing on non-success statuses.
+    pub fn json<T: DeserializeOwned>(self) -> Result<T, ServerFnError> {
+        let body = self.bytes()?;
+
+        serde_json::from_slice(&body).map_err(|err| ServerFnError::Decode(err.to_string()))
+    }
+
+    /// Decode a text body, failing on non-success statuses.
+    pub fn text(self) -> Result<String, ServerFnError> {
+        let body = self.bytes()?;
+
+        String::from_utf8(body).map_err(|err| ServerFnError::Decode(err.to_string()))
+    }
+
+    /// The raw body, failing on non-success statuses.
+    pub fn bytes(self) -> Result<Vec<u8>, ServerFnError> {
+        if !(200..300).contains(&self.status) {
+            if let Some(limit) = self.header(LIMIT_HEADER).and_then(Limit::from_name) {
+                return Err(match limit {
+                    Limit::Timeout => ServerFnError::Timeout,
+                    Limit::MaxBody => ServerFnError::PayloadTooLarge,
+                    Limit::Concurrency => ServerFnError::Overloaded,
+                    Limit::RateLimit => ServerFnError::RateLimited {
+                        retry_after: self
+                            .header("retry-after")
+                            .and_then(|seconds| seconds.parse().ok())
+                    }
+                });
+            }
+
+            return Err(ServerFnError::Status {
+                status: self.status,
+                body: String::from_utf8_lossy(&self.body).into_owned()
+            });
+        }
+
+        Ok(self.body)
+    }
+}
+
+/// A [Transport] answering calls with scripted responses, recording every request it's sent.
+///
+/// Scripts are matched by path, and by method when added with [MockTransport::respond_to], in the
+/// order they were added and are used once. Patterns can
+/// capture segments with `:param`, so the route constants generated for server functions can be
+/// used directly:
+///
+/// ```ignore
+/// let mock = MockTransport::default();
+/// mock.respond(POST_RENAME_USER, &true);
+///
+/// let renamed = with_transport(mock.clone(), post_rename_user_fetch(42, "name".to_owned())).await;
+///
+/// assert_eq!(renamed, Ok(true));
+/// assert_eq!(mock.calls()[0].arg::<String>("arg1"), Ok("name".to_owned()));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    inner: Arc<Mutex<MockState>>
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    scripts: Vec<Script>,
+    calls: Vec<ClientRequest>
+}
+
+#[derive(Debug)]
+struct Script {
+    /// The method answered, or any when [None].
+    method: Option<String>,
+    path: String,
+    response: Result<ClientResponse, ServerFnError>
+}
+
+impl Script {
+    fn matches(&self, request: &ClientRequest) -> bool {
+        self.method
+            .as_ref()
+            .is_none_or(|method| method.eq_ignore_ascii_case(request.method))
+            && path_matches(&self.path, request.path())
+    }
+}
+
+impl MockTransport {
+    /// Answer the next call to `path` with `value` as json.
+    pub fn respond<T: Serialize>(&self, path: &str, value: &T) -> &Self {
+        let response = serde_json::to_vec(value)
+            .map(|body| ClientResponse::new(200, body))
+            .map_err(|err| ServerFnError::Encode(err.to_string()));
+
+        self.script(path, response)
+    }
+
+    /// Answer the next call to `path` with a plain text body.
+    pub fn respond_text(&self, path: &str, text: impl Into<String>) -> &Self {
+        self.script(path, Ok(ClientResponse::new(200, text.into())))
+    }
+
+    /// Answer the next call to `path` with `response` as is.
+    pub fn respond_with(&self, path: &str, response: ClientResponse) -> &Self {
+        self.script(path, Ok(response))
+    }
+
+    /// Answer the next `method` call to `path` with `response` as is, like a `HEAD` apart from
+    /// the `GET` of the same server function.
+    pub fn respond_to(&self, method: &str, path: &str, response: ClientResponse) -> &Self {
+        self.lock().scripts.push(Script {
+            method: Some(method.to_owned()),
+            path: path.to_owned(),
+            response: Ok(response)
+        });
+        self
+    }
+
+    /// Fail the next call to `path` with `err` before it reaches a server.
+    pub fn fail(&self, path: &str, err: ServerFnError) -> &Self {
+        self.script(path, Err(err))
+    }
+
+    /// Every request sent so far, in order.
+    pub fn calls(&self) -> Vec<ClientRequest> {
+        self.lock().calls.clone()
+    }
+
+    fn script(&self, path: &str, response: Result<ClientResponse, ServerFnError>) -> &Self {
+        self.lock().scripts.push(Script {
+            method: None,
+            path: path.to_owned(),
+            response
+        });
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Transport for MockTransport {
+    fn send(
+        &self,
+        request: ClientRequest
+    ) -> BoxFuture<'static, Result<ClientResponse, ServerFnError>> {
+        let mut state = self.lock();
+        let scripted = state
+            .scripts
+            .iter()
+            .position(|script| script.matches(&request))
+            .map(|index| state.scripts.remove(index).response);
+        let response = scripted.unwrap_or_else(|| {
+            Err(ServerFnError::Transport(format!(
+                "no scripted response for {} {}",
+                request.method,
+                request.path()
+            )))
+        });
+
+        state.calls.push(request);
+
+        Box::pin(async move { response })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = std::task::Waker::noop();
+        let mut cx = Context::from_waker(waker);
+
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn script_mock_responses() {
+        let mock = MockTransport::default();
+        mock.respond("/api/users/:id", &vec!["renamed"])
+            .fail("/api/users/:id", ServerFnError::Transport("offline".into()));
+
+        let (first, second, unscripted) = block_on(with_transport(mock.clone(), async {
+            let request = || {
+                ClientRequest::new("PUT", "/api/users/42")
+                    .param("id", 42)
+                    .query("filter", &[("active", true)])
+                    .json("name", &"renamed")
+            };
+
+            (
+                request()
+                    .send()
+                    .await
+                    .and_then(ClientResponse::json::<Vec<String>>),
+                request().send().await,
+                ClientRequest::new("GET", "/api/health").send().await
+            )
+        }));
+
+        assert_eq!(first, Ok(vec!["renamed".to_owned()]));
+        assert_eq!(second, Err(ServerFnError::Transport("offline".into())));
+        assert!(matches!(unscripted, Err(ServerFnError::Transport(_))));
+
+        let calls = mock.calls();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[0].method, "PUT");
+        assert_eq!(calls[0].url, "/api/users/42?active=true");
+        assert_eq!(calls[0].path(), "/api/users/42");
+        assert_eq!(calls[0].arg::<String>("id"), Ok("42".to_owned()));
+        assert_eq!(calls[0].arg::<String>("name"), Ok("renamed".to_owned()));
+        assert_eq!(calls[0].body.as_deref(), Some("\"renamed\""));
+        assert!(calls[0].headers.contains(&(CSRF_HEADER.into(), "1".into())));
+        assert!(calls[2].headers.is_empty());
+    }
+
+    #[test]
+    fn script_by_method() {
+        let mock = MockTransport::default();
+        mock.respond_to("HEAD", "/api/health", ClientResponse::new(204, ""))
+            .respond_to("get", "/api/health", ClientResponse::new(200, "ok"));
+
+        let (get, head) = block_on(with_transport(mock, async {
+            let get = ClientRequest::new("GET", "/api/health").send().await;
+            let head = ClientRequest::new("HEAD", "/api/health").send().await;
+            (get, head)
+        }));
+
+        assert_eq!(get.unwrap().status, 200);
+        assert_eq!(head.unwrap().status, 204);
+    }
+
+    #[test]
+    fn propagate_trace_context() {
+        use crate::trace::{with_trace_context, TraceContext};
+
+        let parent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+        let context = TraceContext::parse(parent).unwrap();
+
+        let mock = MockTransport::default();
+        mock.respond_text("/api/health", "ok");
+
+        block_on(with_transport(
+            mock.clone(),
+            with_trace_context(context, async {
+                ClientRequest::new("GET", "/api/health").send().await
+            })
+        ))
+        .unwrap();
+
+        assert_eq!(
+            mock.calls()[0].headers,
+            vec![(TRACEPARENT_HEADER.to_owned(), parent.to_owned())]
+        );
+    }
+
+    #[test]
+    fn fail_on_error_status() {
+        let response = ClientResponse::new(404, "missing");
+
+        assert_eq!(
+            response.text(),
+            Err(ServerFnError::Status {
+                status: 404,
+                body: "missing".into()
+            })
+        );
+    }
+
+    #[test]
+    fn decode_limit_rejections() {
+        let rejected = |limit: Limit, headers: &[(&str, &str)]| {
+            let mut response = ClientResponse::new(limit.status(), "rejected");
+            response
+                .headers
+                .push((LIMIT_HEADER.into(), limit.as_str().into()));
+            response.headers.extend(
+                headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+            );
+            response.bytes()
+        };
+
+        assert_eq!(rejected(Limit::Timeout, &[]), Err(ServerFnError::Timeout));
+        assert_eq!(
+            rejected(Limit::MaxBody, &[]),
+            Err(ServerFnError::PayloadTooLarge)
+        );
+        assert_eq!(
+            rejected(Limit::Concurrency, &[]),
+            Err(ServerFnError::Overloaded)
+        );
+        assert_eq!(
+            rejected(Limit::RateLimit, &[("Retry-After", "30")]),
+            Err(ServerFnError::RateLimited {
+                retry_after: Some(30)
+            })
+        );
+    }
+
+    #[test]
+    fn no_transport_configured() {
+        let result = block_on(ClientRequest::new("GET", "/api/health").send());
+
+        assert!(matches!(result, Err(ServerFnError::Transport(_))));
+    }
+}
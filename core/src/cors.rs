@@ -0,0 +1,369 @@
+#![cfg(feature = "server")]
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{
+        header::{self, HeaderName},
+        HeaderMap, HeaderValue, Method, StatusCode
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router
+};
+
+use crate::{csrf::CSRF_HEADER, path::path_matches, server_router::RouteInfo};
+
+/// Cross-origin access to a state's routes, from `#[server_state(cors(..))]` or a server
+/// function's own `cors(..)` argument.
+///
+/// ```ignore
+/// #[derive(ServerState)]
+/// #[server_state(cors(origins = ["http://localhost:8080"], headers = ["content-type"]))]
+/// struct AppState {}
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cors {
+    /// Origins allowed to call the routes, or `["*"]` for any origin.
+    pub origins: &'static [&'static str],
+    /// Request headers allowed beyond the CORS-safelisted ones and [CSRF_HEADER].
+    pub headers: &'static [&'static str],
+    /// Allow cookies and authorization headers on cross-origin requests. The `cors(..)` argument
+    /// rejects this alongside `["*"]`.
+    pub credentials: bool,
+    /// How long in seconds browsers may cache a preflight response.
+    pub max_age: Option<u64>
+}
+
+impl Cors {
+    pub fn allows(&self, origin: &str) -> bool {
+        self.origins
+            .iter()
+            .any(|allowed| *allowed == "*" || *allowed == origin)
+    }
+}
+
+/// The routes of a state with the CORS configuration each is served with.
+#[derive(Debug)]
+struct CorsRoutes {
+    default: Option<Cors>,
+    routes: Vec<&'static RouteInfo>,
+    /// Paths of `POST` endpoints outside the inventory served with the default configuration,
+    /// like the batch and JSON-RPC endpoints.
+    endpoints: Vec<&'static str>
+}
+
+impl CorsRoutes {
+    /// The configuration of the route at `path` responding to `method`.
+    fn cors(&self, path: &str, method: &str) -> Option<Cors> {
+        if method == "POST" && self.endpoints.contains(&path) {
+            return self.default;
+        }
+
+        self.routes
+            .iter()
+            .find(|route| route.responds_to(path, method))
+            .and_then(|route| route.cors.or(self.default))
+    }
+
+    /// Every method served at `path`, as listed in a preflight response.
+    fn methods(&self, path: &str) -> String {
+        let mut methods = vec!["OPTIONS".to_owned()];
+        if self.endpoints.contains(&path) {
+            methods.push("POST".to_owned());
+        }
+
+        let matching = self
+            .routes
+            .iter()
+            .filter(|route| path_matches(route.path, path));
+
+        for method in matching.flat_map(|route| route.methods) {
+            let found = match *method {
+                "any" => ["DELETE", "GET", "HEAD", "PATCH", "POST", "PUT"]
+                    .map(str::to_owned)
+                    .to_vec(),
+                method => vec![method.to_uppercase()]
+            };
+
+            for method in found {
+                if !methods.contains(&method) {
+                    methods.push(method);
+                }
+            }
+        }
+
+        methods.join(", ")
+    }
+}
+
+/// Layer CORS handling, including preflight responses, over every route in `routes` and the
+/// `POST` `endpoints`.
+///
+/// The router is returned unchanged when neither the state nor any route configures CORS.
+pub fn layer<S>(
+    router: Router<S>,
+    default: Option<Cors>,
+    routes: Vec<&'static RouteInfo>,
+    endpoints: Vec<&'static str>
+) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static
+{
+    if default.is_none() && routes.iter().all(|route| route.cors.is_none()) {
+        return router;
+    }
+
+    let routes = Arc::new(CorsRoutes {
+        default,
+        routes,
+        endpoints
+    });
+
+    router.layer(middleware::from_fn_with_state(routes, handle))
+}
+
+async fn handle(State(routes): State<Arc<CorsRoutes>>, request: Request, next: Next) -> Response {
+    let Some(origin) = request.headers().get(header::ORIGIN).cloned() else {
+        return next.run(request).await;
+    };
+
+    let path = request.uri().path().to_owned();
+    let preflight_method = request
+        .headers()
+        .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|method| method.to_str().ok())
+        .filter(|_| request.method() == Method::OPTIONS)
+        .map(str::to_owned);
+    let method = preflight_method
+        .clone()
+        .unwrap_or_else(|| request.method().to_string());
+
+    let Some(cors) = routes.cors(&path, &method) else {
+        return next.run(request).await;
+    };
+
+    // Origins that aren't allowed get no CORS headers, so browsers refuse the response.
+    if !origin.to_str().is_ok_and(|origin| cors.allows(origin)) {
+        let mut response = next.run(request).await;
+        vary_by_origin(&mut response);
+        return response;
+    }
+
+    let mut response = match preflight_method {
+        Some(_) => {
+            let mut headers = HeaderMap::new();
+            insert(
+                &mut headers,
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                &routes.methods(&path)
+            );
+            let allowed = cors
+                .headers
+                .iter()
+                .copied()
+                .chain([CSRF_HEADER])
+                .collect::<Vec<_>>();
+            insert(
+                &mut headers,
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                &allowed.join(", ")
+            );
+            if let Some(max_age) = cors.max_age {
+                headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.into());
+            }
+
+            (StatusCode::NO_CONTENT, headers).into_response()
+        }
+        None => next.run(request).await
+    };
+
+    vary_by_origin(&mut response);
+    let headers = response.headers_mut();
+    // The origin is echoed rather than `*`, which browsers reject alongside credentials.
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    if cors.credentials {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true")
+        );
+    }
+
+    response
+}
+
+/// Mark a response whose CORS headers were decided by the request's origin, even when it isn't
+/// allowed, so shared caches keep the responses to different origins apart.
+fn vary_by_origin(response: &mut Response) {
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("origin"));
+}
+
+fn insert(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::routing::{get, post};
+
+    use super::*;
+    use crate::{
+        server_router::{Codec, TypeInfo},
+        testing::TestServer
+    };
+
+    const DEV: Cors = Cors {
+        origins: &["http://localhost:8080"],
+        headers: &["content-type", "x-api-key"],
+        credentials: true,
+        max_age: Some(600)
+    };
+
+    const PUBLIC: Cors = Cors {
+        origins: &["*"],
+        headers: &[],
+        credentials: false,
+        max_age: None
+    };
+
+    fn route(
+        path: &'static str,
+        methods: &'static [&'static str],
+        cors: Option<Cors>
+    ) -> RouteInfo {
+        RouteInfo {
+            path,
+            methods,
+            name: "route",
+            module_path: "cors::test",
+            package: "server-fns-core",
+            middleware_count: 0,
+            embed: None,
+            args: &[],
+            output: TypeInfo {
+                codec: Codec::Text,
+                ty: "String"
+            },
+            cors,
+            csrf: true,
+            rpc: false
+        }
+    }
+
+    fn server() -> TestServer {
+        let routes = [
+            route("/api/users/:id", &["get"], None),
+            route("/api/users/:id", &["put"], None),
+            route("/api/status", &["get"], Some(PUBLIC))
+        ]
+        .map(|route| &*Box::leak(Box::new(route)))
+        .to_vec();
+
+        let router = Router::new()
+            .route(
+                "/api/users/:id",
+                get(|| async { "user" }).put(|| async { "saved" })
+            )
+            .route("/api/status", get(|| async { "ok" }))
+            .route("/internal", post(|| async { "internal" }))
+            .route("/api/batch", post(|| async { "batched" }));
+
+        TestServer::from_router(layer(router, Some(DEV), routes, vec!["/api/batch"]))
+    }
+
+    #[tokio::test]
+    async fn answer_preflight() {
+        let server = server();
+
+        let response = server
+            .request(Method::OPTIONS, "/api/users/42")
+            .header(header::ORIGIN, "http://localhost:8080")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PUT")
+            .send()
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:8080"
+        );
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_METHODS],
+            "OPTIONS, GET, PUT"
+        );
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "content-type, x-api-key, x-server-fns-csrf"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+
+        let response = server
+            .request(Method::OPTIONS, "/api/batch")
+            .header(header::ORIGIN, "http://localhost:8080")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_METHODS],
+            "OPTIONS, POST"
+        );
+    }
+
+    #[tokio::test]
+    async fn allow_origins() {
+        let server = server();
+        let send = |path: &'static str, origin: &'static str| {
+            server
+                .request(Method::GET, path)
+                .header(header::ORIGIN, origin)
+                .send()
+        };
+
+        let response = send("/api/users/42", "http://localhost:8080").await;
+        assert_eq!(response.text(), "user");
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:8080"
+        );
+
+        let response = send("/api/users/42", "https://elsewhere.test").await;
+        assert_eq!(response.text(), "user");
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert_eq!(response.headers()[header::VARY], "origin");
+
+        // The route's own configuration replaces the state's.
+        let response = send("/api/status", "https://elsewhere.test").await;
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://elsewhere.test"
+        );
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+
+        // Routes outside the inventory are left alone.
+        let response = server
+            .request(Method::POST, "/internal")
+            .header(header::ORIGIN, "http://localhost:8080")
+            .send()
+            .await;
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert!(!response.headers().contains_key(header::VARY));
+
+        // Requests without an origin don't evaluate the policy.
+        let response = server.request(Method::GET, "/api/users/42").send().await;
+        assert!(!response.headers().contains_key(header::VARY));
+    }
+}
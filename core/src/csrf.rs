@@ -0,0 +1,232 @@
+/// The request header generated clients send with every unsafe request.
+///
+/// Browsers only let a page set it on a cross-origin request once a CORS preflight allows it, so
+/// a request carrying it wasn't forged by a form or link on another site.
+pub const CSRF_HEADER: &str = "x-server-fns-csrf";
+
+/// Whether requests with the http `method` may change state, and so are checked for forgery.
+pub fn is_unsafe(method: &str) -> bool {
+    !["GET", "HEAD", "OPTIONS", "TRACE"]
+        .iter()
+        .any(|safe| safe.eq_ignore_ascii_case(method))
+}
+
+#[cfg(feature = "server")]
+pub use runtime::*;
+
+#[cfg(feature = "server")]
+mod runtime {
+    use std::sync::Arc;
+
+    use axum::{
+        extract::{Request, State},
+        http::{header, HeaderMap, StatusCode},
+        middleware::{self, Next},
+        response::{IntoResponse, Response},
+        Router
+    };
+
+    use super::*;
+    use crate::{cors::Cors, server_router::RouteInfo};
+
+    /// The routes of a state, with the CORS configuration used by those without their own.
+    #[derive(Debug)]
+    struct CsrfRoutes {
+        cors: Option<Cors>,
+        routes: Vec<&'static RouteInfo>
+    }
+
+    /// Layer cross-site request forgery checks over the unsafe requests to every route in
+    /// `routes` that hasn't opted out with `csrf = false`.
+    pub fn layer<S>(
+        router: Router<S>,
+        cors: Option<Cors>,
+        routes: Vec<&'static RouteInfo>
+    ) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static
+    {
+        if routes.iter().all(|route| !route.csrf) {
+            return router;
+        }
+
+        let routes = Arc::new(CsrfRoutes { cors, routes });
+
+        router.layer(middleware::from_fn_with_state(routes, handle))
+    }
+
+    async fn handle(
+        State(routes): State<Arc<CsrfRoutes>>,
+        request: Request,
+        next: Next
+    ) -> Response {
+        let method = request.method().as_str();
+        let checked = is_unsafe(method)
+            .then(|| {
+                routes
+                    .routes
+                    .iter()
+                    .find(|route| route.responds_to(request.uri().path(), method))
+            })
+            .flatten()
+            .filter(|route| route.csrf);
+
+        match checked {
+            Some(route) if !allows(request.headers(), route.cors.or(routes.cors)) => {
+                (StatusCode::FORBIDDEN, "Cross-site request rejected").into_response()
+            }
+            _ => next.run(request).await
+        }
+    }
+
+    /// Whether an unsafe request to a route served with `cors` came from a page allowed to make
+    /// it.
+    ///
+    /// Requests pass when they carry [CSRF_HEADER], when the browser's `Sec-Fetch-Site` marks
+    /// them same-origin, or when their `Origin` is the server's own host or allowed by `cors`.
+    /// Requests with neither `Sec-Fetch-Site` nor `Origin` weren't sent by a browser, so pass as
+    /// well.
+    pub fn allows(headers: &HeaderMap, cors: Option<Cors>) -> bool {
+        let value = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+        if headers.contains_key(CSRF_HEADER) {
+            return true;
+        }
+
+        let origin = value(header::ORIGIN.as_str());
+        match (value("sec-fetch-site"), origin) {
+            (Some("same-origin" | "none"), _) | (None, None) => true,
+            (_, None) => false,
+            (_, Some(origin)) => {
+                let own = origin
+                    .split_once("://")
+                    .is_some_and(|(_, host)| Some(host) == value(header::HOST.as_str()));
+
+                own || cors.is_some_and(|cors| cors.allows(origin))
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod test {
+    use axum::{
+        http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+        routing::post,
+        Router
+    };
+
+    use super::*;
+    use crate::{
+        cors::Cors,
+        server_router::{Codec, RouteInfo, TypeInfo},
+        testing::TestServer
+    };
+
+    const PARTNER: Cors = Cors {
+        origins: &["https://partner.test"],
+        headers: &[],
+        credentials: true,
+        max_age: None
+    };
+
+    #[test]
+    fn check_unsafe_methods() {
+        assert!(is_unsafe("POST"));
+        assert!(is_unsafe("delete"));
+        assert!(!is_unsafe("GET"));
+        assert!(!is_unsafe("options"));
+    }
+
+    #[test]
+    fn allow_requests() {
+        let allows = |headers: &[(&'static str, &'static str)], cors: Option<Cors>| {
+            let mut map = HeaderMap::new();
+            for (name, value) in headers {
+                map.insert(*name, HeaderValue::from_static(value));
+            }
+            super::allows(&map, cors)
+        };
+
+        assert!(allows(&[], None));
+        assert!(allows(&[("sec-fetch-site", "same-origin")], None));
+        assert!(allows(&[(CSRF_HEADER, "1")], None));
+        assert!(allows(
+            &[
+                (header::ORIGIN.as_str(), "http://app.test:3000"),
+                (header::HOST.as_str(), "app.test:3000")
+            ],
+            None
+        ));
+        assert!(allows(
+            &[
+                ("sec-fetch-site", "cross-site"),
+                (header::ORIGIN.as_str(), "https://partner.test")
+            ],
+            Some(PARTNER)
+        ));
+
+        assert!(!allows(
+            &[
+                ("sec-fetch-site", "cross-site"),
+                (header::ORIGIN.as_str(), "https://evil.test")
+            ],
+            Some(PARTNER)
+        ));
+        assert!(!allows(
+            &[
+                (header::ORIGIN.as_str(), "https://evil.test"),
+                (header::HOST.as_str(), "app.test")
+            ],
+            None
+        ));
+        assert!(!allows(&[("sec-fetch-site", "same-site")], None));
+    }
+
+    #[tokio::test]
+    async fn reject_forged_requests() {
+        let route = |path: &'static str, csrf: bool| -> &'static RouteInfo {
+            Box::leak(Box::new(RouteInfo {
+                path,
+                methods: &["post"],
+                name: "route",
+                module_path: "csrf::test",
+                package: "server-fns-core",
+                middleware_count: 0,
+                embed: None,
+                args: &[],
+                output: TypeInfo {
+                    codec: Codec::Text,
+                    ty: "String"
+                },
+                cors: None,
+                csrf,
+                rpc: false
+            }))
+        };
+
+        let router = Router::new()
+            .route("/transfer", post(|| async { "sent" }))
+            .route("/webhook", post(|| async { "received" }));
+        let routes = vec![route("/transfer", true), route("/webhook", false)];
+        let server = TestServer::from_router(layer(router, None, routes));
+
+        let forged = |path: &'static str| {
+            server
+                .request(Method::POST, path)
+                .header("sec-fetch-site", "cross-site")
+                .header(header::ORIGIN, "https://evil.test")
+                .send()
+        };
+
+        assert_eq!(forged("/transfer").await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(forged("/webhook").await.text(), "received");
+
+        let response = server
+            .request(Method::POST, "/transfer")
+            .header("sec-fetch-site", "same-origin")
+            .send()
+            .await;
+        assert_eq!(response.text(), "sent");
+    }
+}
@@ -0,0 +1,174 @@
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote_spanned, ToTokens, TokenStreamExt};
+use syn::{parse_quote_spanned, spanned::Spanned, ItemFn};
+
+use crate::{middleware::ServerAttr, parse::Guard};
+
+/// Moves `#[guard]` attributes into the arguments of the server attribute beneath them.
+///
+/// Guards run in the order they're written, top-down and then the server attribute's own
+/// `guards = [..]`, before any of the handler's extractors.
+pub(crate) struct GuardImpl(pub Span, pub ItemFn);
+
+impl GuardImpl {
+    pub fn try_new(args: TokenStream2, mut server_fn: ItemFn) -> Result<Self, syn::Error> {
+        let span = server_fn.span();
+
+        // Attribute macros expand top-down, so collect the guards beneath this one as well.
+        let mut guards = vec![parse_guard(args)?];
+        let mut remaining = Vec::with_capacity(server_fn.attrs.len());
+
+        for attr in server_fn.attrs {
+            if attr.path().is_ident("guard") {
+                guards.push(parse_guard(attr.parse_args()?)?);
+            } else {
+                remaining.push(attr);
+            }
+        }
+        server_fn.attrs = remaining;
+
+        let Some(ServerAttr {
+            index,
+            path: attr_path,
+            args: mut attr_args
+        }) = ServerAttr::find(&server_fn)?
+        else {
+            return Err(syn::Error::new(span, "#[server] attribute not found"));
+        };
+
+        attr_args.guards.splice(0..0, guards);
+
+        server_fn.attrs[index] = parse_quote_spanned! { span => #[#attr_path(#attr_args)] };
+
+        Ok(Self(span, server_fn))
+    }
+}
+
+fn parse_guard(args: TokenStream2) -> Result<Guard, syn::Error> {
+    let arg_span = args.span();
+
+    match syn::parse2(args) {
+        Ok(guard) => Ok(guard),
+        Err(mut err) => {
+            let dbg = syn::Error::new(arg_span, "Error parsing guard attribute arguments");
+            err.combine(dbg);
+
+            Err(err)
+        }
+    }
+}
+
+impl ToTokens for GuardImpl {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        let Self(span, server_fn) = self;
+
+        tokens.append_all(quote_spanned! { *span => #server_fn });
+    }
+}
+
+#[cfg(feature = "server")]
+pub use runtime::*;
+
+#[cfg(feature = "server")]
+mod runtime {
+    use std::future::Future;
+
+    use axum::{
+        async_trait,
+        extract::FromRequestParts,
+        http::{request::Parts, StatusCode},
+        response::{IntoResponse, Response}
+    };
+
+    /// The caller a server function's `#[guard]` authenticated, extracted by its handler.
+    ///
+    /// ```ignore
+    /// async fn require_user(parts: Parts, state: AppState) -> Result<User, StatusCode> {
+    ///     let token = parts.headers.get(AUTHORIZATION).ok_or(StatusCode::UNAUTHORIZED)?;
+    ///     state.sessions.user(token).ok_or(StatusCode::UNAUTHORIZED)
+    /// }
+    ///
+    /// #[guard(require_user)]
+    /// #[get]
+    /// async fn me(Principal(user): Principal<User>) -> Json<User> {
+    ///     Json(user)
+    /// }
+    /// ```
+    ///
+    /// Rejects with `401 Unauthorized` when no guard produced a `P`.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct Principal<P>(pub P);
+
+    #[async_trait]
+    impl<S, P> FromRequestParts<S> for Principal<P>
+    where
+        S: Send + Sync,
+        P: Clone + Send + Sync + 'static
+    {
+        type Rejection = StatusCode;
+
+        async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+            parts
+                .extensions
+                .get::<Self>()
+                .cloned()
+                .ok_or(StatusCode::UNAUTHORIZED)
+        }
+    }
+
+    /// Run `guard` over a copy of the request parts, storing the principal it returns for
+    /// [Principal] or rejecting the request with its error.
+    pub async fn check<G, T, Fut, P, R>(
+        guard: G,
+        parts: &mut Parts,
+        state: T
+    ) -> Result<(), Response>
+    where
+        G: FnOnce(Parts, T) -> Fut,
+        Fut: Future<Output = Result<P, R>>,
+        P: Clone + Send + Sync + 'static,
+        R: IntoResponse
+    {
+        match guard(parts.clone(), state).await {
+            Ok(principal) => {
+                parts.extensions.insert(Principal(principal));
+                Ok(())
+            }
+            Err(rejection) => Err(rejection.into_response())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quote::quote;
+    use syn::parse_quote;
+
+    use super::*;
+
+    #[test]
+    fn new_guard() {
+        let server_fn: ItemFn = parse_quote! {
+            #[middleware(traced)]
+            #[guard(is_admin, state = Sessions)]
+            #[get(guards = [guard(is_local)])]
+            async fn handler() {}
+        };
+
+        let GuardImpl(_, server_fn) = GuardImpl::try_new(quote!(require_user), server_fn).unwrap();
+        let ServerAttr { args, .. } = ServerAttr::find(&server_fn).unwrap().unwrap();
+
+        assert_eq!(server_fn.attrs.len(), 2);
+        assert_eq!(
+            args.guards
+                .iter()
+                .map(|guard| guard.to_token_stream().to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "guard (require_user)",
+                "guard (is_admin , state = Sessions)",
+                "guard (is_local)"
+            ]
+        );
+    }
+}
@@ -1,11 +1,28 @@
+pub mod app;
+pub mod batch;
+pub mod cache;
+pub mod client;
+pub mod cors;
+pub mod csrf;
 pub mod embed_asset;
+pub mod guard;
+pub mod limit;
 mod macro_traits;
+pub mod metrics;
 pub mod middleware;
+pub mod openapi;
 mod parse;
+mod path;
+pub mod rate_limit;
+pub mod rpc;
 mod server_fn;
 pub mod server_router;
 pub mod server_state;
+pub mod stable_path;
+pub mod testing;
+pub mod trace;
 mod transform;
+pub mod typescript;
 
 pub use macro_traits::*;
 pub use transform::*;
@@ -116,6 +133,9 @@ macro_rules! layer_middleware {
     (before_routing($mid:expr) for $router:ident) => {
         let $router = $router.layer($mid);
     };
+    (group($group:path) for $router:ident) => {
+        let $router = $group($router);
+    };
 }
 
 http_methods!(as_enum!());
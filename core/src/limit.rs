@@ -0,0 +1,258 @@
+/// The response header naming the route limit that rejected a request, so generated clients can
+/// tell limit rejections apart from the server function's own error responses.
+pub const LIMIT_HEADER: &str = "x-server-fns-limit";
+
+/// A limit set on a server function's route by its server attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// `timeout = "5s"`, rejected with `503 Service Unavailable`, since the server rather than the
+    /// client was too slow.
+    Timeout,
+    /// `max_body = 1024`, rejected with `413 Payload Too Large`.
+    MaxBody,
+    /// `concurrency = 8`, rejected with `503 Service Unavailable`.
+    Concurrency,
+    /// `rate_limit(..)`, rejected with `429 Too Many Requests`.
+    RateLimit
+}
+
+impl Limit {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Timeout => "timeout",
+            Self::MaxBody => "max-body",
+            Self::Concurrency => "concurrency",
+            Self::RateLimit => "rate-limit"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Self::Timeout,
+            Self::MaxBody,
+            Self::Concurrency,
+            Self::RateLimit
+        ]
+        .into_iter()
+        .find(|limit| limit.as_str() == name)
+    }
+
+    pub const fn status(self) -> u16 {
+        match self {
+            Self::Timeout => 503,
+            Self::MaxBody => 413,
+            Self::Concurrency => 503,
+            Self::RateLimit => 429
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+pub use runtime::*;
+
+#[cfg(feature = "server")]
+mod runtime {
+    use std::{sync::Arc, time::Duration};
+
+    use axum::{
+        body::{self, Body},
+        extract::{Request, State},
+        http::{header, HeaderValue, StatusCode},
+        middleware::Next,
+        response::{IntoResponse, Response}
+    };
+    use tokio::sync::Semaphore;
+
+    use super::*;
+
+    impl Limit {
+        /// The response rejecting a request that went over this limit.
+        pub fn reject(self) -> Response {
+            let status =
+                StatusCode::from_u16(self.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+            (
+                status,
+                [(LIMIT_HEADER, HeaderValue::from_static(self.as_str()))],
+                self.message()
+            )
+                .into_response()
+        }
+
+        const fn message(self) -> &'static str {
+            match self {
+                Self::Timeout => "Server function timed out",
+                Self::MaxBody => "Request body is larger than the server function accepts",
+                Self::Concurrency => "Server function is handling too many requests at once",
+                Self::RateLimit => "Too many requests to the server function"
+            }
+        }
+    }
+
+    /// Middleware cancelling requests that take longer than `timeout`, including reading the body
+    /// when layered outside [max_body].
+    pub async fn timeout(
+        State(timeout): State<Duration>,
+        request: Request,
+        next: Next
+    ) -> Response {
+        tokio::time::timeout(timeout, next.run(request))
+            .await
+            .unwrap_or_else(|_| Limit::Timeout.reject())
+    }
+
+    /// Middleware rejecting requests with a body over `max` bytes.
+    ///
+    /// A body declaring too large a length is rejected before it's read. Otherwise the body is read
+    /// up front and rejected once it goes over `max`, so one without a declared length is only
+    /// rejected after `max` bytes arrive. Reading isn't bounded in time; a route's `timeout` is
+    /// layered outside this one to cover slow uploads.
+    pub async fn max_body(State(max): State<usize>, request: Request, next: Next) -> Response {
+        let length = request
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok()?.parse::<usize>().ok());
+
+        if length.is_some_and(|length| length > max) {
+            return Limit::MaxBody.reject();
+        }
+
+        let (parts, body) = request.into_parts();
+        match body::to_bytes(body, max).await {
+            Ok(bytes) => {
+                next.run(Request::from_parts(parts, Body::from(bytes)))
+                    .await
+            }
+            Err(_) => Limit::MaxBody.reject()
+        }
+    }
+
+    /// The requests a route may handle at once, shared by every request to it.
+    #[derive(Debug, Clone)]
+    pub struct Permits(Arc<Semaphore>);
+
+    impl Permits {
+        pub fn new(concurrency: usize) -> Self {
+            Self(Arc::new(Semaphore::new(concurrency)))
+        }
+    }
+
+    /// Middleware rejecting requests while every permit is held by a request in flight.
+    pub async fn concurrency(
+        State(Permits(permits)): State<Permits>,
+        request: Request,
+        next: Next
+    ) -> Response {
+        match permits.try_acquire_owned() {
+            Ok(_permit) => next.run(request).await,
+            Err(_) => Limit::Concurrency.reject()
+        }
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use axum::{
+        body::Bytes,
+        extract::DefaultBodyLimit,
+        http::{Method, StatusCode},
+        middleware::from_fn_with_state,
+        routing::post,
+        Router
+    };
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::testing::TestServer;
+
+    #[test]
+    fn name_limits() {
+        for limit in [
+            Limit::Timeout,
+            Limit::MaxBody,
+            Limit::Concurrency,
+            Limit::RateLimit
+        ] {
+            assert_eq!(Limit::from_name(limit.as_str()), Some(limit));
+        }
+        assert_eq!(Limit::from_name("other"), None);
+    }
+
+    #[tokio::test]
+    async fn limit_timeout() {
+        let router = Router::new()
+            .route(
+                "/slow",
+                post(|| async {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    "done"
+                })
+            )
+            .route_layer(from_fn_with_state(Duration::from_millis(10), timeout));
+        let server = TestServer::from_router(router);
+
+        let response = server.request(Method::POST, "/slow").send().await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[LIMIT_HEADER], "timeout");
+    }
+
+    #[tokio::test]
+    async fn limit_body() {
+        let router = Router::new()
+            .route(
+                "/upload",
+                post(|body: Bytes| async move { body.len().to_string() })
+            )
+            .route_layer(DefaultBodyLimit::max(4))
+            .route_layer(from_fn_with_state(4, max_body));
+        let server = TestServer::from_router(router);
+
+        let response = server
+            .request(Method::POST, "/upload")
+            .text("four")
+            .send()
+            .await;
+        assert_eq!(response.text(), "4");
+
+        let response = server
+            .request(Method::POST, "/upload")
+            .text("fives")
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(response.headers()[LIMIT_HEADER], "max-body");
+    }
+
+    #[tokio::test]
+    async fn limit_concurrency() {
+        let release = Arc::new(Notify::new());
+        let router = Router::new()
+            .route(
+                "/busy",
+                post({
+                    let release = release.clone();
+                    move || async move {
+                        release.notified().await;
+                        "done"
+                    }
+                })
+            )
+            .route_layer(from_fn_with_state(Permits::new(1), concurrency));
+        let server = TestServer::from_router(router);
+
+        let busy = server.request(Method::POST, "/busy").send();
+        let rejected = async {
+            tokio::task::yield_now().await;
+            let response = server.request(Method::POST, "/busy").send().await;
+            release.notify_one();
+            response
+        };
+
+        let (busy, rejected) = tokio::join!(busy, rejected);
+        assert_eq!(busy.text(), "done");
+        assert_eq!(rejected.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(rejected.headers()[LIMIT_HEADER], "concurrency");
+    }
+}
@@ -0,0 +1,240 @@
+#![cfg(feature = "metrics")]
+
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Instant};
+
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router
+};
+
+use crate::server_router::RouteInfo;
+
+/// Upper bounds in seconds of the request duration histogram's buckets.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0
+];
+
+/// The labels of a route's series: its server function, module, path and request method.
+type SeriesKey = (&'static str, &'static str, &'static str, String);
+
+#[derive(Debug, Default)]
+struct Series {
+    /// Responses by status code.
+    statuses: BTreeMap<u16, u64>,
+    /// Responses with a `5xx` status.
+    errors: u64,
+    /// Non-cumulative counts of durations per bucket, with the last counting the rest.
+    buckets: [u64; BUCKETS.len() + 1],
+    sum: f64,
+    count: u64
+}
+
+static METRICS: Mutex<BTreeMap<SeriesKey, Series>> = Mutex::new(BTreeMap::new());
+
+/// Record the requests to `router`'s routes under the labels of `route`.
+pub fn layer<S>(router: Router<S>, route: &'static RouteInfo) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static
+{
+    router.route_layer(middleware::from_fn_with_state(route, record))
+}
+
+async fn record(State(route): State<&'static RouteInfo>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let seconds = start.elapsed().as_secs_f64();
+    let status = response.status();
+
+    let mut metrics = METRICS.lock().unwrap_or_else(|err| err.into_inner());
+    let series = metrics
+        .entry((route.name, route.module_path, route.path, method))
+        .or_default();
+
+    *series.statuses.entry(status.as_u16()).or_default() += 1;
+    if status.is_server_error() {
+        series.errors += 1;
+    }
+    let bucket = BUCKETS
+        .iter()
+        .position(|bound| seconds <= *bound)
+        .unwrap_or(BUCKETS.len());
+    series.buckets[bucket] += 1;
+    series.sum += seconds;
+    series.count += 1;
+
+    response
+}
+
+/// Every recorded series in the Prometheus text exposition format.
+pub fn render() -> String {
+    let metrics = METRICS.lock().unwrap_or_else(|err| err.into_inner());
+    let mut text = String::new();
+
+    let labels = |(name, module, path, method): &SeriesKey| {
+        format!(
+            "server_fn=\"{}\",module=\"{}\",route=\"{}\",method=\"{}\"",
+            escape(name),
+            escape(module),
+            escape(path),
+            escape(method)
+        )
+    };
+
+    text.push_str(
+        "# HELP server_fn_requests_total Requests handled by each server function.\n# TYPE \
+         server_fn_requests_total counter\n"
+    );
+    for (key, series) in metrics.iter() {
+        for (status, count) in &series.statuses {
+            let _ = writeln!(
+                text,
+                "server_fn_requests_total{{{},status=\"{status}\"}} {count}",
+                labels(key)
+            );
+        }
+    }
+
+    text.push_str(
+        "# HELP server_fn_errors_total Requests each server function answered with a 5xx \
+         status.\n# TYPE server_fn_errors_total counter\n"
+    );
+    for (key, series) in metrics.iter() {
+        let _ = writeln!(
+            text,
+            "server_fn_errors_total{{{}}} {}",
+            labels(key),
+            series.errors
+        );
+    }
+
+    text.push_str(
+        "# HELP server_fn_request_duration_seconds Time each server function took to respond.\n# \
+         TYPE server_fn_request_duration_seconds histogram\n"
+    );
+    for (key, series) in metrics.iter() {
+        let labels = labels(key);
+        let mut cumulative = 0;
+
+        for (bound, count) in BUCKETS.iter().zip(&series.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                text,
+                "server_fn_request_duration_seconds_bucket{{{labels},le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            text,
+            "server_fn_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+            series.count
+        );
+        let _ = writeln!(
+            text,
+            "server_fn_request_duration_seconds_sum{{{labels}}} {}",
+            series.sum
+        );
+        let _ = writeln!(
+            text,
+            "server_fn_request_duration_seconds_count{{{labels}}} {}",
+            series.count
+        );
+    }
+
+    text
+}
+
+/// The handler of the route registered by `#[server_state(metrics = "/metrics")]`.
+pub async fn handler() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render()
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        http::{Method, StatusCode},
+        routing::post
+    };
+
+    use super::*;
+    use crate::{
+        server_router::{Codec, TypeInfo},
+        testing::TestServer
+    };
+
+    const ROUTE: RouteInfo = RouteInfo {
+        path: "/api/metered",
+        methods: &["post"],
+        name: "metered",
+        module_path: "metrics::test",
+        package: "server-fns-core",
+        middleware_count: 0,
+        embed: None,
+        args: &[],
+        output: TypeInfo {
+            codec: Codec::Text,
+            ty: "String"
+        },
+        cors: None,
+        csrf: true,
+        rpc: false
+    };
+
+    #[tokio::test]
+    async fn record_requests() {
+        let router = Router::new().route(
+            "/api/metered",
+            post(|fail: String| async move {
+                match fail.as_str() {
+                    "fail" => Err(StatusCode::INTERNAL_SERVER_ERROR),
+                    _ => Ok("ok")
+                }
+            })
+        );
+        let server = TestServer::from_router(layer(router, &ROUTE));
+
+        for body in ["ok", "ok", "fail"] {
+            server
+                .request(Method::POST, "/api/metered")
+                .text(body)
+                .send()
+                .await;
+        }
+
+        let text = render();
+        let labels =
+            r#"server_fn="metered",module="metrics::test",route="/api/metered",method="POST""#;
+
+        assert!(text.contains(&format!(
+            "server_fn_requests_total{{{labels},status=\"200\"}} 2\n"
+        )));
+        assert!(text.contains(&format!(
+            "server_fn_requests_total{{{labels},status=\"500\"}} 1\n"
+        )));
+        assert!(text.contains(&format!("server_fn_errors_total{{{labels}}} 1\n")));
+        assert!(text.contains(&format!(
+            "server_fn_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} 3\n"
+        )));
+        assert!(text.contains(&format!(
+            "server_fn_request_duration_seconds_count{{{labels}}} 3\n"
+        )));
+        assert!(text.contains("# TYPE server_fn_request_duration_seconds histogram\n"));
+    }
+
+    #[test]
+    fn escape_labels() {
+        assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+}
@@ -1,59 +1,85 @@
 use proc_macro2::{Span, TokenStream as TokenStream2};
-use quote::{quote_spanned, ToTokens, TokenStreamExt};
-use syn::{parse_quote_spanned, spanned::Spanned, ItemFn, Meta};
+use quote::{format_ident, quote_spanned, ToTokens, TokenStreamExt};
+use syn::{parse_quote_spanned, spanned::Spanned, Ident, ItemFn, ItemMacro, Meta, Path};
 
-use crate::{http_methods, parse::ServerFnArgs};
+use crate::{
+    current_package, http_methods, make_server_state,
+    parse::{GlobalMiddlewareArgs, Middleware, MiddlewareGroup, ServerFnArgs}
+};
 
+/// Moves `#[middleware]` attributes into the arguments of the server attribute beneath them.
+///
+/// Middlewares are layered outermost-first: the first one written, reading attributes top-down
+/// and then the server attribute's own `middlewares = [..]`, is the outermost layer. It sees the
+/// request first and the response last. `before_routing` and `after_routing` only choose between
+/// [Router::layer](axum::Router::layer) and [Router::route_layer](axum::Router::route_layer);
+/// they don't change a middleware's position.
 pub(crate) struct MiddlewareImpl(pub Span, pub ItemFn);
 
 impl MiddlewareImpl {
     pub fn try_new(args: TokenStream2, mut server_fn: ItemFn) -> Result<Self, syn::Error> {
         let span = server_fn.span();
 
-        let mut server_attr = Ok(None);
-        server_fn
-            .attrs
-            .retain(|attr| match (attr.path().get_ident(), &server_attr) {
-                // Server attr has already error; just fast track out of the loop.
-                (_, Err(_)) => true,
-                // Next ident matches expected value, but server attr already has a value.
-                (Some(ident), Ok(Some(first)))
-                    if ident == "server"
-                        || http_methods!(contains!(&ident.to_string().as_str())) =>
-                {
-                    server_attr = Err(syn::Error::new(
-                        span,
-                        format!(
-                            "Multiple server function attributes matched; found {:?}, then {:?}",
-                            first, attr
-                        )
-                    ));
-                    false
-                }
-                // Next ident matches expected value and the server attr isn't set.
-                (Some(ident), Ok(None))
-                    if ident == "server"
-                        || http_methods!(contains!(&ident.to_string().as_str())) =>
-                {
-                    server_attr = Ok(Some(attr.clone()));
-                    false
-                }
-                // Next ident isn't an expected value.
-                (_, _) => true
-            });
-
-        let Some(server_attr) = server_attr? else {
+        // Attribute macros expand top-down, so this is the topmost middleware left on the fn.
+        // Collect the ones beneath it too, which keeps them in the order they were written.
+        let mut middlewares = vec![parse_middleware(args)?];
+        let mut remaining = Vec::with_capacity(server_fn.attrs.len());
+
+        for attr in server_fn.attrs {
+            if attr.path().is_ident("middleware") {
+                middlewares.push(parse_middleware(attr.parse_args()?)?);
+            } else {
+                remaining.push(attr);
+            }
+        }
+        server_fn.attrs = remaining;
+
+        let Some(ServerAttr {
+            index,
+            path: attr_path,
+            args: mut attr_args
+        }) = ServerAttr::find(&server_fn)?
+        else {
             return Err(syn::Error::new(span, "#[server] attribute not found"));
         };
 
-        // Parse out the server function arguments so the middleware expression can be added back in.
+        // Middlewares written above the server attribute wrap the ones given in its arguments.
+        attr_args.middlewares.splice(0..0, middlewares);
+
+        // Write out the same server function attribute name that was found, in the same place.
+        server_fn.attrs[index] = parse_quote_spanned! { span => #[#attr_path(#attr_args)] };
+
+        Ok(Self(span, server_fn))
+    }
+}
+
+/// The outermost server function attribute (`#[server]` or a method attribute) left on a fn.
+pub(crate) struct ServerAttr {
+    pub index: usize,
+    pub path: Path,
+    pub args: ServerFnArgs
+}
+
+impl ServerAttr {
+    pub fn find(server_fn: &ItemFn) -> Result<Option<Self>, syn::Error> {
+        let found = server_fn.attrs.iter().enumerate().find(|(_, attr)| {
+            attr.path().get_ident().is_some_and(|ident| {
+                ident == "server" || http_methods!(contains!(&ident.to_string().as_str()))
+            })
+        });
+
+        let Some((index, server_attr)) = found else {
+            return Ok(None);
+        };
+
+        // Parse out the server function arguments so they can be modified and written back in.
         let server_attr_span = server_attr.span();
         let attr_args = if let Meta::Path(_) = &server_attr.meta {
             Ok(Default::default())
         } else {
             server_attr.parse_args::<ServerFnArgs>()
         };
-        let mut attr_args = match attr_args {
+        let args = match attr_args {
             Ok(attr_args) => attr_args,
             Err(mut err) => {
                 let dbg = syn::Error::new(
@@ -66,29 +92,168 @@ impl MiddlewareImpl {
             }
         };
 
-        let arg_span = args.span();
-        // Parse out the middleware expression.
-        let middleware = match syn::parse2(args) {
-            Ok(m) => m,
-            Err(mut err) => {
-                let dbg = syn::Error::new(arg_span, "Error parsing middleware attribute arguments");
-                err.combine(dbg);
+        Ok(Some(Self {
+            index,
+            path: server_attr.path().clone(),
+            args
+        }))
+    }
+}
 
-                return Err(err);
+/// A function returning a layer, applied to every route of a state by
+/// [ServerState::load_routes](crate::server_state::ServerState::load_routes).
+pub(crate) struct GlobalMiddlewareImpl {
+    pub span: Span,
+    pub layer_fn: ItemFn,
+    pub apply_mod: Ident,
+    pub apply_fn: ItemFn,
+    pub register: ItemMacro
+}
+
+impl GlobalMiddlewareImpl {
+    pub fn try_new(args: GlobalMiddlewareArgs, layer_fn: ItemFn) -> Result<Self, syn::Error> {
+        let span = layer_fn.span();
+        let GlobalMiddlewareArgs { state, order } = args;
+
+        if let Some(input) = layer_fn.sig.inputs.first() {
+            return Err(syn::Error::new(
+                input.span(),
+                "Global middleware functions take no arguments and return the layer to apply."
+            ));
+        }
+
+        let state = match state {
+            Some(state) => state.to_token_stream(),
+            None => make_server_state(current_package(span)?).to_token_stream()
+        };
+
+        let fn_ident = &layer_fn.sig.ident;
+        let fn_name = fn_ident.to_string();
+        let apply_ident = format_ident!("{fn_ident}_apply");
+        let apply_mod = format_ident!("__{fn_ident}_global_middleware");
+
+        let apply_fn = parse_quote_spanned! { span =>
+            pub(super) fn #apply_ident(
+                router: ::server_fns::axum::Router<#state>
+            ) -> ::server_fns::axum::Router<#state> {
+                router.layer(#fn_ident())
             }
         };
 
-        // Add this middleware to the server function args so it can be rewritten back onto the fn.
-        attr_args.middlewares.push(middleware);
+        let register = parse_quote_spanned! { span =>
+            ::server_fns::inventory::submit! {
+                <
+                    #state
+                    as
+                    ::server_fns::server_state::ServerState
+                >
+                ::Middleware::register(
+                    ::server_fns::server_router::MiddlewareInfo {
+                        name: #fn_name,
+                        module_path: ::std::module_path!(),
+                        order: #order
+                    },
+                    #apply_mod::#apply_ident
+                )
+            }
+        };
+
+        Ok(Self {
+            span,
+            layer_fn,
+            apply_mod,
+            apply_fn,
+            register
+        })
+    }
+}
 
-        // Write out the same server function attribute name that was found.
-        let attr_path = server_attr.path();
+impl ToTokens for GlobalMiddlewareImpl {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        let Self {
+            span,
+            layer_fn,
+            apply_mod,
+            apply_fn,
+            register
+        } = self;
 
-        server_fn
-            .attrs
-            .push(parse_quote_spanned! { span => #[#attr_path(#attr_args)] });
+        tokens.append_all(quote_spanned! { *span =>
+            #[cfg(feature = "server")]
+            #layer_fn
 
-        Ok(Self(span, server_fn))
+            #[cfg(feature = "server")]
+            mod #apply_mod {
+                use super::*;
+
+                #apply_fn
+            }
+
+            #[cfg(feature = "server")]
+            #register
+        });
+    }
+}
+
+/// A generic function layering a [MiddlewareGroup] onto a server function's router, referenced
+/// with `#[middleware(group = name)]`.
+pub(crate) struct MiddlewareGroupImpl {
+    pub span: Span,
+    pub group_fn: ItemFn
+}
+
+impl MiddlewareGroupImpl {
+    pub fn new(group: MiddlewareGroup) -> Self {
+        let MiddlewareGroup {
+            vis,
+            ident,
+            middlewares
+        } = group;
+        let span = ident.span();
+        // Layers wrap the ones applied before them, so the outermost is applied last.
+        let middlewares = middlewares.iter().rev();
+
+        let group_fn = parse_quote_spanned! { span =>
+            #vis fn #ident<State>(
+                router: ::server_fns::axum::Router<State>
+            ) -> ::server_fns::axum::Router<State>
+            where
+                State: ::std::clone::Clone + ::std::marker::Send + ::std::marker::Sync + 'static
+            {
+                #(
+                    ::server_fns::layer_middleware!(#middlewares for router);
+                )*
+
+                router
+            }
+        };
+
+        Self { span, group_fn }
+    }
+}
+
+impl ToTokens for MiddlewareGroupImpl {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        let Self { span, group_fn } = self;
+
+        tokens.append_all(quote_spanned! { *span =>
+            #[cfg(feature = "server")]
+            #group_fn
+        });
+    }
+}
+
+fn parse_middleware(args: TokenStream2) -> Result<Middleware, syn::Error> {
+    let arg_span = args.span();
+
+    match syn::parse2(args) {
+        Ok(m) => Ok(m),
+        Err(mut err) => {
+            let dbg = syn::Error::new(arg_span, "Error parsing middleware attribute arguments");
+            err.combine(dbg);
+
+            Err(err)
+        }
     }
 }
 
@@ -102,8 +267,63 @@ impl ToTokens for MiddlewareImpl {
 
 #[cfg(test)]
 mod test {
+    use quote::quote;
+    use syn::parse_quote;
+
     use super::*;
 
+    fn middlewares(server_fn: &ItemFn) -> Vec<String> {
+        let ServerAttr { args, .. } = ServerAttr::find(server_fn).unwrap().unwrap();
+
+        args.middlewares
+            .iter()
+            .map(|middleware| middleware.to_token_stream().to_string())
+            .collect()
+    }
+
     #[test]
-    fn new_middleware() {}
+    fn new_middleware() {
+        let server_fn: ItemFn = parse_quote! {
+            #[middleware(second)]
+            #[middleware(before_routing(third))]
+            #[get(middlewares = [fourth])]
+            async fn handler() {}
+        };
+
+        let MiddlewareImpl(_, server_fn) =
+            MiddlewareImpl::try_new(quote!(first), server_fn).unwrap();
+
+        assert_eq!(server_fn.attrs.len(), 1);
+        assert_eq!(
+            middlewares(&server_fn),
+            vec![
+                "after_routing (first)",
+                "after_routing (second)",
+                "before_routing (third)",
+                "after_routing (fourth)"
+            ]
+        );
+    }
+
+    #[test]
+    fn layer_group_outermost_first() {
+        let group: MiddlewareGroup = parse_quote!(traced = [outer, before_routing(inner)]);
+        let MiddlewareGroupImpl { group_fn, .. } = MiddlewareGroupImpl::new(group);
+
+        let layers = group_fn
+            .block
+            .stmts
+            .iter()
+            .map(|stmt| stmt.to_token_stream().to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            layers,
+            vec![
+                ":: server_fns :: layer_middleware ! (before_routing (inner) for router) ;",
+                ":: server_fns :: layer_middleware ! (after_routing (outer) for router) ;",
+                "router"
+            ]
+        );
+    }
 }
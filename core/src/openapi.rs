@@ -0,0 +1,412 @@
+#![cfg(feature = "openapi")]
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Map, Value};
+
+use crate::server_router::{Codec, RouteInfo, TypeInfo};
+
+/// A named json schema, referenced from generated documents as `#/components/schemas/{name}`.
+///
+/// Types carried by server functions are matched to fragments by their name (the last path
+/// segment), so `Json<models::User>` resolves to the fragment registered as `User`.
+pub struct SchemaFragment {
+    pub name: &'static str,
+    pub schema: fn() -> Value
+}
+
+impl SchemaFragment {
+    pub const fn new(name: &'static str, schema: fn() -> Value) -> Self {
+        Self { name, schema }
+    }
+}
+
+inventory::collect!(SchemaFragment);
+
+/// Register a [SchemaFragment] for the named type.
+///
+/// ```ignore
+/// schema_fragment!(User, server_fns::serde_json::json!({
+///     "type": "object",
+///     "properties": { "name": { "type": "string" } }
+/// }));
+/// ```
+#[macro_export]
+macro_rules! schema_fragment {
+    ($ty:ident, $schema:expr) => {
+        ::server_fns::inventory::submit! {
+            ::server_fns::openapi::SchemaFragment::new(::std::stringify!($ty), || $schema)
+        }
+    };
+}
+
+/// Every registered [SchemaFragment], keyed by name.
+pub fn schema_fragments() -> BTreeMap<&'static str, Value> {
+    inventory::iter::<SchemaFragment>
+        .into_iter()
+        .map(|fragment| (fragment.name, (fragment.schema)()))
+        .collect()
+}
+
+/// Assemble an OpenAPI 3.1 document describing the given routes.
+pub fn document<'a>(
+    title: &str,
+    version: &str,
+    routes: impl IntoIterator<Item = &'a RouteInfo>
+) -> Value {
+    let schemas = schema_fragments();
+    let mut paths = Map::new();
+
+    for route in routes {
+        let item = paths
+            .entry(openapi_path(route.path))
+            .or_insert_with(|| json!({}));
+
+        // Operation ids are unique per document, so they're qualified by module path, and
+        // routes with several methods are suffixed.
+        let id = format!("{}_{}", route.module_path.replace("::", "_"), route.name);
+
+        for &method in route.methods {
+            let mut operation = operation(route, &schemas);
+
+            if method == "any" {
+                for method in [
+                    "get", "put", "post", "delete", "options", "head", "patch", "trace"
+                ] {
+                    operation["operationId"] = json!(format!("{id}_{method}"));
+                    item[method] = operation.clone();
+                }
+            } else {
+                operation["operationId"] = match route.methods.len() {
+                    1 => json!(id),
+                    _ => json!(format!("{id}_{method}"))
+                };
+                item[method] = operation;
+            }
+        }
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": title,
+            "version": version
+        },
+        "paths": paths,
+        "components": {
+            "schemas": schemas
+        }
+    })
+}
+
+/// Convert an axum path (`/users/:id/*rest`) to an OpenAPI path template (`/users/{id}/{rest}`).
+pub fn openapi_path(path: &str) -> String {
+    path.split('/')
+        .map(|part| match part.strip_prefix([':', '*']) {
+            Some(param) => format!("{{{param}}}"),
+            None => part.to_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The names of the parameters captured by an axum path.
+pub fn path_params(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter_map(|part| part.strip_prefix([':', '*']))
+        .collect()
+}
+
+fn operation(route: &RouteInfo, schemas: &BTreeMap<&str, Value>) -> Value {
+    let mut parameters = Vec::new();
+    let mut request_body = None;
+
+    let param_names = path_params(route.path);
+    let mut param_schemas = vec![json!({ "type": "string" }); param_names.len()];
+
+    for arg in route.args {
+        let TypeInfo { codec, ty } = arg.ty;
+
+        match codec {
+            Codec::Path => match split_tuple(ty) {
+                Some(elems) if elems.len() == param_names.len() => {
+                    param_schemas = elems.iter().map(|ty| schema_for(ty, schemas)).collect();
+                }
+                None if param_names.len() == 1 => {
+                    param_schemas = vec![schema_for(ty, schemas)];
+                }
+                _ => {}
+            },
+            Codec::Query => parameters.push(json!({
+                "name": arg.name,
+                "in": "query",
+                "style": "form",
+                "explode": true,
+                "schema": schema_for(ty, schemas)
+            })),
+            Codec::Json | Codec::Form | Codec::Text => {
+                request_body = Some(json!({
+                    "required": true,
+                    "content": content(arg.ty, schemas)
+                }));
+            }
+            Codec::State | Codec::Principal | Codec::Html | Codec::Other => {}
+        }
+    }
+
+    let path_parameters = param_names
+        .into_iter()
+        .zip(param_schemas)
+        .map(|(name, schema)| {
+            json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": schema
+            })
+        });
+    parameters.splice(0..0, path_parameters);
+
+    let mut response = json!({ "description": "Success" });
+    if route.output.codec != Codec::Other {
+        response["content"] = content(route.output, schemas);
+    }
+
+    let mut operation = json!({
+        "tags": [route.package],
+        "responses": {
+            "200": response
+        }
+    });
+
+    if !parameters.is_empty() {
+        operation["parameters"] = Value::Array(parameters);
+    }
+
+    if let Some(request_body) = request_body {
+        operation["requestBody"] = request_body;
+    }
+
+    operation
+}
+
+fn content(info: TypeInfo, schemas: &BTreeMap<&str, Value>) -> Value {
+    let mime = match info.codec {
+        Codec::Json => "application/json",
+        Codec::Form => "application/x-www-form-urlencoded",
+        Codec::Html => "text/html",
+        _ => "text/plain"
+    };
+
+    json!({
+        mime: {
+            "schema": schema_for(info.ty, schemas)
+        }
+    })
+}
+
+/// Build a json schema for a type name, referencing registered fragments where possible.
+pub fn schema_for(ty: &str, schemas: &BTreeMap<&str, Value>) -> Value {
+    if ty == "()" {
+        return json!({ "type": "null" });
+    }
+
+    if let Some(elems) = split_tuple(ty) {
+        let items = elems
+            .iter()
+            .map(|ty| schema_for(ty, schemas))
+            .collect::<Vec<_>>();
+
+        return json!({
+            "type": "array",
+            "prefixItems": items,
+            "items": false
+        });
+    }
+
+    let (name, generics) = split_generics(ty);
+
+    match (name, generics.as_slice()) {
+        ("String" | "str" | "char", _) => json!({ "type": "string" }),
+        ("bool", _) => json!({ "type": "boolean" }),
+        (
+            "u8" | "u16" | "u32" | "u64" | "u128" | "usize" | "i8" | "i16" | "i32" | "i64" | "i128"
+            | "isize",
+            _
+        ) => json!({ "type": "integer" }),
+        ("f32" | "f64", _) => json!({ "type": "number" }),
+        ("Box" | "Arc" | "Rc" | "Cow", [.., inner]) => schema_for(inner, schemas),
+        ("Option", [inner]) => json!({
+            "anyOf": [schema_for(inner, schemas), { "type": "null" }]
+        }),
+        ("Vec" | "VecDeque" | "HashSet" | "BTreeSet", [inner]) => json!({
+            "type": "array",
+            "items": schema_for(inner, schemas)
+        }),
+        ("HashMap" | "BTreeMap", [_, value]) => json!({
+            "type": "object",
+            "additionalProperties": schema_for(value, schemas)
+        }),
+        (name, _) if schemas.contains_key(name) => json!({
+            "$ref": format!("#/components/schemas/{name}")
+        }),
+        _ => json!({})
+    }
+}
+
+/// Split a type into its unqualified name and top level generic arguments.
+fn split_generics(ty: &str) -> (&str, Vec<&str>) {
+    let (head, generics) = match (ty.find('<'), ty.ends_with('>')) {
+        (Some(start), true) => (&ty[..start], split_top_level(&ty[start + 1..ty.len() - 1])),
+        _ => (ty, Vec::new())
+    };
+
+    let name = head.rsplit("::").next().unwrap_or(head);
+    let generics = generics
+        .into_iter()
+        .filter(|arg| !arg.starts_with('\''))
+        .collect();
+
+    (name, generics)
+}
+
+/// The element types of a tuple type, or [None] if the type isn't a tuple.
+fn split_tuple(ty: &str) -> Option<Vec<&str>> {
+    let inner = ty.strip_prefix('(')?.strip_suffix(')')?;
+
+    Some(split_top_level(inner))
+}
+
+/// Split a comma separated list of types, ignoring commas nested in brackets.
+pub(crate) fn split_top_level(list: &str) -> Vec<&str> {
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut parts = Vec::new();
+
+    for (i, char_) in list.char_indices() {
+        match char_ {
+            '<' | '(' | '[' | '{' => depth += 1,
+            '>' | ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&list[start..]);
+    parts.retain(|part| !part.is_empty());
+
+    parts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server_router::ArgInfo;
+
+    const fn type_info(codec: Codec, ty: &'static str) -> TypeInfo {
+        TypeInfo { codec, ty }
+    }
+
+    #[test]
+    fn convert_path() {
+        assert_eq!(openapi_path("/users/:id/*rest"), "/users/{id}/{rest}");
+        assert_eq!(path_params("/users/:id/*rest"), vec!["id", "rest"]);
+    }
+
+    #[test]
+    fn schema_for_types() {
+        let schemas = BTreeMap::from([("User", json!({ "type": "object" }))]);
+
+        assert_eq!(
+            schema_for("Vec<Option<models::User>>", &schemas),
+            json!({
+                "type": "array",
+                "items": {
+                    "anyOf": [{ "$ref": "#/components/schemas/User" }, { "type": "null" }]
+                }
+            })
+        );
+        assert_eq!(
+            schema_for("HashMap<String,(u32,bool)>", &schemas),
+            json!({
+                "type": "object",
+                "additionalProperties": {
+                    "type": "array",
+                    "prefixItems": [{ "type": "integer" }, { "type": "boolean" }],
+                    "items": false
+                }
+            })
+        );
+        assert_eq!(schema_for("Unknown", &schemas), json!({}));
+    }
+
+    #[test]
+    fn build_document() {
+        const ARGS: &[ArgInfo] = &[
+            ArgInfo {
+                name: "state",
+                ty: type_info(Codec::State, "AppState")
+            },
+            ArgInfo {
+                name: "id",
+                ty: type_info(Codec::Path, "u64")
+            },
+            ArgInfo {
+                name: "arg2",
+                ty: type_info(Codec::Json, "String")
+            }
+        ];
+
+        let route = RouteInfo {
+            path: "/api/users/:id",
+            methods: &["post"],
+            name: "update_user",
+            module_path: "app",
+            package: "app",
+            middleware_count: 0,
+            embed: None,
+            args: ARGS,
+            output: type_info(Codec::Html, "String"),
+            cors: None,
+            csrf: true,
+            rpc: false
+        };
+
+        let other = RouteInfo {
+            path: "/api/admin/users/:id",
+            module_path: "app::admin",
+            methods: &["any"],
+            ..route
+        };
+
+        let document = document("app", "0.1.0", [&route, &other]);
+        let operation = &document["paths"]["/api/users/{id}"]["post"];
+
+        assert_eq!(document["openapi"], "3.1.0");
+        assert_eq!(operation["operationId"], "app_update_user");
+        assert_eq!(
+            document["paths"]["/api/admin/users/{id}"]["put"]["operationId"],
+            "app_admin_update_user_put"
+        );
+        assert_eq!(
+            operation["parameters"],
+            json!([{
+                "name": "id",
+                "in": "path",
+                "required": true,
+                "schema": { "type": "integer" }
+            }])
+        );
+        assert_eq!(
+            operation["requestBody"]["content"]["application/json"]["schema"],
+            json!({ "type": "string" })
+        );
+        assert_eq!(
+            operation["responses"]["200"]["content"]["text/html"]["schema"],
+            json!({ "type": "string" })
+        );
+    }
+}
@@ -1,13 +1,14 @@
 use proc_macro2::{Span, TokenStream as TokenStream2};
-use quote::{quote, quote_spanned, ToTokens, TokenStreamExt};
+use quote::{quote, ToTokens, TokenStreamExt};
 use syn::{
-    parenthesized,
+    bracketed,
     parse::{Parse, ParseStream},
     parse_quote, parse_quote_spanned,
     punctuated::Punctuated,
     spanned::Spanned,
     token::Comma,
-    Expr, ExprLit, ExprPath, Ident, Lit, LitStr, MetaNameValue, Token
+    Expr, ExprAssign, ExprCall, ExprLit, ExprPath, ExprUnary, Ident, Lit, LitBool, LitInt, LitStr,
+    Meta, MetaNameValue, Path, Token, UnOp, Visibility
 };
 
 use crate::http_methods;
@@ -15,9 +16,36 @@ use crate::http_methods;
 #[derive(Debug, Default, PartialEq)]
 pub struct ServerFnArgs {
     pub path: Option<LitStr>,
-    pub method: Option<Ident>,
+    /// A stable identifier the default path is hashed from, kept when the function is renamed or
+    /// moved.
+    pub id: Option<LitStr>,
+    /// Hash the default path from the module path and function name, instead of `/api/<name>`.
+    pub hashed: bool,
+    pub methods: Vec<Ident>,
     pub embed: Option<LitStr>,
-    pub middlewares: Vec<Middleware>
+    /// The [ServerState](crate::server_state::ServerState) to register with, instead of the
+    /// package's `use_server_state!` alias.
+    pub state: Option<Path>,
+    /// Leave registration to each state listing the function in `#[server_state(shared = [..])]`.
+    pub shared: bool,
+    pub middlewares: Vec<Middleware>,
+    pub guards: Vec<Guard>,
+    pub rate_limit: Option<RateLimitArgs>,
+    /// How long the server function may take, written like `per` in [RateLimitArgs].
+    pub timeout: Option<LitStr>,
+    /// The largest request body accepted, in bytes.
+    pub max_body: Option<LitInt>,
+    /// How many requests the server function handles at once.
+    pub concurrency: Option<LitInt>,
+    /// Cross-origin access replacing the state's for this server function.
+    pub cors: Option<CorsArgs>,
+    /// `csrf = false` opts out of cross-site request forgery checks, which are on by default.
+    pub csrf: Option<bool>,
+    /// Record the arguments on the server function's span when the `tracing` feature is on.
+    pub trace_args: bool,
+    pub cache: Option<CacheArgs>,
+    /// Expose the server function on JSON-RPC endpoints that only expose tagged functions.
+    pub rpc: bool
 }
 
 #[derive(Debug, PartialEq)]
@@ -25,17 +53,112 @@ pub struct Middleware {
     pub expr: Expr
 }
 
+/// An async check run over the request parts and state before a server function's handler.
+///
+/// Written `#[guard(expr)]` or `#[guard(expr, state = Type)]`, and carried in the server
+/// attribute's arguments as `guards = [guard(expr, state = Type)]`.
+#[derive(Debug, PartialEq)]
+pub struct Guard {
+    pub expr: Expr,
+    /// The state the guard is given, instead of the server function's own state.
+    pub state: Option<Path>
+}
+
+/// A server function's `rate_limit(requests = 10, per = "1m", key = ip)` option.
+#[derive(Debug, PartialEq)]
+pub struct RateLimitArgs {
+    /// The number of requests allowed per window.
+    pub requests: LitInt,
+    /// The window, written as a number followed by `ms`, `s`, `m` or `h`.
+    pub per: LitStr,
+    pub key: RateLimitKeyArg
+}
+
+/// A `cors(origins = ["https://app.test"], headers = ["x-api-key"], credentials, max_age = "1h")`
+/// option, on a server function or a `#[server_state]`.
+#[derive(Debug, Default, PartialEq, deluxe::ParseMetaItem)]
+#[deluxe(default)]
+pub struct CorsArgs {
+    /// Origins allowed to call the routes, or `["*"]` for any origin.
+    pub origins: Vec<LitStr>,
+    /// Request headers allowed beyond the CORS-safelisted ones and the CSRF header.
+    pub headers: Vec<LitStr>,
+    pub credentials: bool,
+    /// How long browsers may cache preflight responses, written like `per` in [RateLimitArgs].
+    pub max_age: Option<LitStr>
+}
+
+/// A `cache(ttl = "30s", key = ["accept-language"])` option, caching a `get` server function's
+/// responses by path, query and the listed request headers.
+#[derive(Debug, PartialEq, deluxe::ParseMetaItem)]
+pub struct CacheArgs {
+    /// How long responses are served from the cache, written like `per` in [RateLimitArgs].
+    pub ttl: LitStr,
+    /// Request headers that responses vary by.
+    #[deluxe(default)]
+    pub key: Vec<LitStr>
+}
+
+/// What requests are counted by, defaulting to the client's ip.
+#[derive(Debug, Default, PartialEq)]
+pub enum RateLimitKeyArg {
+    #[default]
+    Ip,
+    /// `header("x-api-key")`
+    Header(LitStr),
+    /// The path of a `fn(&Request) -> String`.
+    Custom(Path)
+}
+
+/// The input of `middleware_group!`, a named list of middlewares layered in declared order.
+#[derive(Debug, PartialEq)]
+pub struct MiddlewareGroup {
+    pub vis: Visibility,
+    pub ident: Ident,
+    pub middlewares: Vec<Middleware>
+}
+
+impl Parse for MiddlewareGroup {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let vis = input.parse()?;
+        let ident = input.parse()?;
+        input.parse::<Token![=]>()?;
+
+        let content;
+        bracketed!(content in input);
+        let middlewares = Punctuated::<Middleware, Token![,]>::parse_terminated(&content)?
+            .into_iter()
+            .collect();
+
+        Ok(Self {
+            vis,
+            ident,
+            middlewares
+        })
+    }
+}
+
+/// Arguments of the `#[global_middleware(...)]` attribute.
+#[derive(Debug, Default, PartialEq)]
+pub struct GlobalMiddlewareArgs {
+    /// The [ServerState](crate::server_state::ServerState) to layer onto, instead of the
+    /// package's `use_server_state!` alias.
+    pub state: Option<Path>,
+    /// Where the middleware sits among the state's middlewares, lowest outermost.
+    pub order: i32
+}
+
 impl Parse for ServerFnArgs {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let span = input.span();
 
-        let metas = input.parse_terminated(MetaNameValue::parse, Token![,]);
+        let metas = input.parse_terminated(Meta::parse, Token![,]);
         let metas = match metas {
             Ok(m) => m,
             Err(mut err) => {
                 let dbg = syn::Error::new(
                     span,
-                    format!("Error parsing MetaNameValue punctuated sequence; found ({input})")
+                    format!("Error parsing Meta punctuated sequence; found ({input})")
                 );
                 err.combine(dbg);
 
@@ -46,6 +169,35 @@ impl Parse for ServerFnArgs {
         metas
             .into_iter()
             .try_fold(Self::default(), |mut args, next| {
+                let next = match next {
+                    Meta::NameValue(next) => next,
+                    Meta::List(list) if list.path.is_ident("rate_limit") => {
+                        args.rate_limit = Some(list.parse_args()?);
+                        return Ok(args);
+                    }
+                    Meta::List(list) if list.path.is_ident("cors") => {
+                        let cors: CorsArgs = deluxe::parse2(list.tokens)?;
+                        cors.check()?;
+                        args.cors = Some(cors);
+                        return Ok(args);
+                    }
+                    Meta::List(list) if list.path.is_ident("cache") => {
+                        let cache: CacheArgs = deluxe::parse2(list.tokens)?;
+                        parse_duration_millis(&cache.ttl)?;
+                        args.cache = Some(cache);
+                        return Ok(args);
+                    }
+                    unexpected => {
+                        return Err(syn::Error::new(
+                            unexpected.span(),
+                            format!(
+                                "Unexpected server attribute argument: {:?}",
+                                unexpected.path().get_ident()
+                            )
+                        ));
+                    }
+                };
+
                 if next.path.is_ident("path") {
                     match next.value {
                         Expr::Lit(ExprLit {
@@ -59,34 +211,43 @@ impl Parse for ServerFnArgs {
                             ));
                         }
                     }
-                } else if next.path.is_ident("method") {
+                } else if next.path.is_ident("id") {
                     match next.value {
                         Expr::Lit(ExprLit {
                             lit: Lit::Str(litstr),
                             ..
-                        }) if http_methods!(contains!(&litstr.value().to_lowercase().as_ref())) => {
-                            args.method = Some(Ident::new(&litstr.value(), litstr.span()))
-                        }
-                        Expr::Lit(ExprLit {
-                            lit: Lit::Str(litstr),
-                            ..
-                        }) => {
+                        }) if !litstr.value().is_empty() => args.id = Some(litstr),
+                        unexpected => {
                             return Err(syn::Error::new(
-                                litstr.span(),
+                                unexpected.span(),
                                 format!(
-                                    "Method not supported; found ({:?}), expected one of{:?}",
-                                    litstr,
-                                    http_methods!(as_slice!())
+                                    "Id must be a non-empty string literal; found ({unexpected:?})"
                                 )
                             ));
                         }
+                    }
+                } else if next.path.is_ident("hashed") {
+                    match next.value {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Bool(LitBool { value, .. }),
+                            ..
+                        }) => args.hashed = value,
                         unexpected => {
                             return Err(syn::Error::new(
                                 unexpected.span(),
-                                format!("Method must be a string literal; found ({unexpected:?})")
+                                format!("Hashed must be a bool literal; found ({unexpected:?})")
                             ));
                         }
                     }
+                } else if next.path.is_ident("method") {
+                    args.push_method(parse_method(next.value)?);
+                } else if next.path.is_ident("methods") {
+                    let Expr::Array(methods) = next.value else {
+                        return Err(syn::Error::new(span, "Unexpected methods array value."));
+                    };
+                    for method in methods.elems {
+                        args.push_method(parse_method(method)?);
+                    }
                 } else if next.path.is_ident("embed") {
                     match next.value {
                         Expr::Lit(ExprLit {
@@ -100,6 +261,70 @@ impl Parse for ServerFnArgs {
                             ));
                         }
                     }
+                } else if next.path.is_ident("state") {
+                    match next.value {
+                        Expr::Path(ExprPath { path, .. }) => args.state = Some(path),
+                        unexpected => {
+                            return Err(syn::Error::new(
+                                unexpected.span(),
+                                format!("State must be a type path; found ({unexpected:?})")
+                            ));
+                        }
+                    }
+                } else if next.path.is_ident("shared") {
+                    match next.value {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Bool(LitBool { value, .. }),
+                            ..
+                        }) => args.shared = value,
+                        unexpected => {
+                            return Err(syn::Error::new(
+                                unexpected.span(),
+                                format!("Shared must be a bool literal; found ({unexpected:?})")
+                            ));
+                        }
+                    }
+                } else if next.path.is_ident("trace_args") {
+                    match next.value {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Bool(LitBool { value, .. }),
+                            ..
+                        }) => args.trace_args = value,
+                        unexpected => {
+                            return Err(syn::Error::new(
+                                unexpected.span(),
+                                format!(
+                                    "Trace args must be a bool literal; found ({unexpected:?})"
+                                )
+                            ));
+                        }
+                    }
+                } else if next.path.is_ident("rpc") {
+                    match next.value {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Bool(LitBool { value, .. }),
+                            ..
+                        }) => args.rpc = value,
+                        unexpected => {
+                            return Err(syn::Error::new(
+                                unexpected.span(),
+                                format!("Rpc must be a bool literal; found ({unexpected:?})")
+                            ));
+                        }
+                    }
+                } else if next.path.is_ident("csrf") {
+                    match next.value {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Bool(LitBool { value, .. }),
+                            ..
+                        }) => args.csrf = Some(value),
+                        unexpected => {
+                            return Err(syn::Error::new(
+                                unexpected.span(),
+                                format!("Csrf must be a bool literal; found ({unexpected:?})")
+                            ));
+                        }
+                    }
                 } else if next.path.is_ident("middlewares") {
                     let Expr::Array(mids) = next.value else {
                         return Err(syn::Error::new(span, "Unexpected middlewares array value."));
@@ -109,6 +334,35 @@ impl Parse for ServerFnArgs {
                         .into_iter()
                         .map(|mid| syn::parse2(mid.into_token_stream()))
                         .collect::<Result<Vec<_>, _>>()?;
+                } else if next.path.is_ident("timeout") {
+                    match next.value {
+                        Expr::Lit(ExprLit {
+                            lit: Lit::Str(litstr),
+                            ..
+                        }) => {
+                            parse_duration_millis(&litstr)?;
+                            args.timeout = Some(litstr);
+                        }
+                        unexpected => {
+                            return Err(syn::Error::new(
+                                unexpected.span(),
+                                format!("Timeout must be a string literal; found ({unexpected:?})")
+                            ));
+                        }
+                    }
+                } else if next.path.is_ident("max_body") {
+                    args.max_body = Some(parse_positive_int("Max body", next.value)?);
+                } else if next.path.is_ident("concurrency") {
+                    args.concurrency = Some(parse_positive_int("Concurrency", next.value)?);
+                } else if next.path.is_ident("guards") {
+                    let Expr::Array(guards) = next.value else {
+                        return Err(syn::Error::new(span, "Unexpected guards array value."));
+                    };
+                    args.guards = guards
+                        .elems
+                        .into_iter()
+                        .map(Guard::from_list_item)
+                        .collect::<Result<Vec<_>, _>>()?;
                 } else {
                     return Err(syn::Error::new(
                         next.span(),
@@ -124,6 +378,374 @@ impl Parse for ServerFnArgs {
     }
 }
 
+impl Parse for GlobalMiddlewareArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let metas = Punctuated::<MetaNameValue, Token![,]>::parse_terminated(input)?;
+
+        metas
+            .into_iter()
+            .try_fold(Self::default(), |mut args, next| {
+                if next.path.is_ident("state") {
+                    match next.value {
+                        Expr::Path(ExprPath { path, .. }) => args.state = Some(path),
+                        unexpected => {
+                            return Err(syn::Error::new(
+                                unexpected.span(),
+                                format!("State must be a type path; found ({unexpected:?})")
+                            ));
+                        }
+                    }
+                } else if next.path.is_ident("order") {
+                    args.order = parse_order(next.value)?;
+                } else {
+                    return Err(syn::Error::new(
+                        next.span(),
+                        format!(
+                            "Unexpected global middleware argument: {:?}",
+                            next.path.get_ident()
+                        )
+                    ));
+                }
+
+                Ok(args)
+            })
+    }
+}
+
+impl Parse for RateLimitArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let span = input.span();
+        let metas = Punctuated::<MetaNameValue, Token![,]>::parse_terminated(input)?;
+
+        let mut requests = None;
+        let mut per = None;
+        let mut key = RateLimitKeyArg::default();
+
+        for next in metas {
+            if next.path.is_ident("requests") {
+                requests = Some(parse_positive_int("Requests", next.value)?);
+            } else if next.path.is_ident("per") {
+                match next.value {
+                    Expr::Lit(ExprLit {
+                        lit: Lit::Str(litstr),
+                        ..
+                    }) => {
+                        parse_duration_millis(&litstr)?;
+                        per = Some(litstr);
+                    }
+                    unexpected => {
+                        return Err(syn::Error::new(
+                            unexpected.span(),
+                            format!("Per must be a string literal; found ({unexpected:?})")
+                        ));
+                    }
+                }
+            } else if next.path.is_ident("key") {
+                key = match next.value {
+                    Expr::Path(ExprPath { path, .. }) if path.is_ident("ip") => RateLimitKeyArg::Ip,
+                    Expr::Path(ExprPath { path, .. }) => RateLimitKeyArg::Custom(path),
+                    Expr::Call(ExprCall { func, args, .. })
+                        if matches!(
+                            func.as_ref(),
+                            Expr::Path(ExprPath { path, .. }) if path.is_ident("header")
+                        ) =>
+                    {
+                        RateLimitKeyArg::Header(syn::parse2(args.into_token_stream())?)
+                    }
+                    unexpected => {
+                        return Err(syn::Error::new(
+                            unexpected.span(),
+                            format!(
+                                "Key must be `ip`, `header(\"name\")` or the path of a key \
+                                 function; found ({unexpected:?})"
+                            )
+                        ));
+                    }
+                };
+            } else {
+                return Err(syn::Error::new(
+                    next.span(),
+                    format!(
+                        "Unexpected rate limit argument: {:?}",
+                        next.path.get_ident()
+                    )
+                ));
+            }
+        }
+
+        let (Some(requests), Some(per)) = (requests, per) else {
+            return Err(syn::Error::new(
+                span,
+                "Rate limits need both `requests` and `per` arguments."
+            ));
+        };
+
+        Ok(Self { requests, per, key })
+    }
+}
+
+/// Parse a positive integer literal that fits a `u32`.
+fn parse_positive_int(name: &str, value: Expr) -> syn::Result<LitInt> {
+    match value {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(int), ..
+        }) if int.base10_parse::<u32>().is_ok_and(|int| int > 0) => Ok(int),
+        unexpected => Err(syn::Error::new(
+            unexpected.span(),
+            format!("{name} must be a positive integer literal; found ({unexpected:?})")
+        ))
+    }
+}
+
+/// Parse a duration like `"500ms"`, `"30s"`, `"1m"` or `"1h"` into milliseconds.
+pub fn parse_duration_millis(duration: &LitStr) -> syn::Result<u64> {
+    let value = duration.value();
+    let unit_at = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(unit_at);
+
+    let scale = match unit {
+        "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        _ => 0
+    };
+
+    match amount.parse::<u64>() {
+        Ok(amount) if amount > 0 && scale > 0 => amount.checked_mul(scale).ok_or_else(|| {
+            syn::Error::new(
+                duration.span(),
+                format!("Duration is too long to count in milliseconds; found ({value:?})")
+            )
+        }),
+        _ => Err(syn::Error::new(
+            duration.span(),
+            format!(
+                "Durations must be a positive number followed by `ms`, `s`, `m` or `h`; found \
+                 ({value:?})"
+            )
+        ))
+    }
+}
+
+/// Parse an `order = N` value, allowing negative orders.
+fn parse_order(value: Expr) -> syn::Result<i32> {
+    let span = value.span();
+    let (negative, value) = match value {
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_),
+            expr,
+            ..
+        }) => (true, *expr),
+        value => (false, value)
+    };
+
+    match value {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(int), ..
+        }) => {
+            let order = int.base10_parse::<i32>()?;
+            Ok(if negative { -order } else { order })
+        }
+        unexpected => Err(syn::Error::new(
+            span,
+            format!("Order must be an integer literal; found ({unexpected:?})")
+        ))
+    }
+}
+
+fn parse_method(value: Expr) -> syn::Result<Ident> {
+    match value {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(litstr),
+            ..
+        }) if http_methods!(contains!(&litstr.value().to_lowercase().as_ref())) => {
+            Ok(Ident::new(&litstr.value().to_lowercase(), litstr.span()))
+        }
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(litstr),
+            ..
+        }) => Err(syn::Error::new(
+            litstr.span(),
+            format!(
+                "Method not supported; found ({:?}), expected one of{:?}",
+                litstr,
+                http_methods!(as_slice!())
+            )
+        )),
+        unexpected => Err(syn::Error::new(
+            unexpected.span(),
+            format!("Method must be a string literal; found ({unexpected:?})")
+        ))
+    }
+}
+
+impl CorsArgs {
+    /// Check the `max_age` duration, and that any origin isn't allowed along with credentials,
+    /// which would let every site make credentialed reads and pass the forgery check.
+    pub fn check(&self) -> syn::Result<()> {
+        self.max_age_secs()?;
+
+        match self.origins.iter().find(|origin| origin.value() == "*") {
+            Some(wildcard) if self.credentials => Err(syn::Error::new(
+                wildcard.span(),
+                "CORS can't allow any origin (\"*\") along with `credentials`; list the origins \
+                 instead."
+            )),
+            _ => Ok(())
+        }
+    }
+
+    /// The preflight cache duration in whole seconds, rounded up.
+    pub fn max_age_secs(&self) -> syn::Result<Option<u64>> {
+        self.max_age
+            .as_ref()
+            .map(|max_age| Ok(parse_duration_millis(max_age)?.div_ceil(1_000)))
+            .transpose()
+    }
+}
+
+impl ServerFnArgs {
+    /// Add a method to the server function, ignoring methods it already responds to.
+    pub fn push_method(&mut self, method: Ident) {
+        if !self.methods.contains(&method) {
+            self.methods.push(method);
+        }
+    }
+
+    /// Merge the arguments of an outer server attribute into the attribute beneath it.
+    ///
+    /// Methods are unioned and the outer attribute's middlewares and guards are placed before the
+    /// inner attribute's, preserving the order they were written in.
+    pub fn merge(self, inner: Self) -> syn::Result<Self> {
+        fn merge_lit(
+            name: &str,
+            outer: Option<LitStr>,
+            inner: Option<LitStr>
+        ) -> syn::Result<Option<LitStr>> {
+            match (outer, inner) {
+                (Some(outer), Some(inner)) if outer.value() != inner.value() => {
+                    Err(syn::Error::new(
+                        inner.span(),
+                        format!(
+                            "Conflicting `{name}` arguments on stacked server attributes; found \
+                             ({:?}) and ({:?})",
+                            outer.value(),
+                            inner.value()
+                        )
+                    ))
+                }
+                (outer, inner) => Ok(outer.or(inner))
+            }
+        }
+
+        fn merge_int(
+            name: &str,
+            outer: Option<LitInt>,
+            inner: Option<LitInt>
+        ) -> syn::Result<Option<LitInt>> {
+            match (outer, inner) {
+                (Some(outer), Some(inner)) if outer.base10_digits() != inner.base10_digits() => {
+                    Err(syn::Error::new(
+                        inner.span(),
+                        format!(
+                            "Conflicting `{name}` arguments on stacked server attributes; found \
+                             ({outer}) and ({inner})"
+                        )
+                    ))
+                }
+                (outer, inner) => Ok(outer.or(inner))
+            }
+        }
+
+        let state = match (self.state, inner.state) {
+            (Some(outer), Some(inner)) if outer != inner => {
+                return Err(syn::Error::new(
+                    inner.span(),
+                    format!(
+                        "Conflicting `state` arguments on stacked server attributes; found ({}) \
+                         and ({})",
+                        outer.to_token_stream(),
+                        inner.to_token_stream()
+                    )
+                ));
+            }
+            (outer, inner) => outer.or(inner)
+        };
+
+        let cors = match (self.cors, inner.cors) {
+            (Some(outer), Some(inner)) if outer != inner => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "Conflicting `cors` arguments on stacked server attributes."
+                ));
+            }
+            (outer, inner) => outer.or(inner)
+        };
+
+        let csrf = match (self.csrf, inner.csrf) {
+            (Some(outer), Some(inner)) if outer != inner => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "Conflicting `csrf` arguments on stacked server attributes."
+                ));
+            }
+            (outer, inner) => outer.or(inner)
+        };
+
+        let rate_limit = match (self.rate_limit, inner.rate_limit) {
+            (Some(outer), Some(inner)) if outer != inner => {
+                return Err(syn::Error::new(
+                    inner.per.span(),
+                    "Conflicting `rate_limit` arguments on stacked server attributes."
+                ));
+            }
+            (outer, inner) => outer.or(inner)
+        };
+
+        let cache = match (self.cache, inner.cache) {
+            (Some(outer), Some(inner)) if outer != inner => {
+                return Err(syn::Error::new(
+                    inner.ttl.span(),
+                    "Conflicting `cache` arguments on stacked server attributes."
+                ));
+            }
+            (outer, inner) => outer.or(inner)
+        };
+
+        let mut merged = Self {
+            path: merge_lit("path", self.path, inner.path)?,
+            id: merge_lit("id", self.id, inner.id)?,
+            hashed: self.hashed || inner.hashed,
+            methods: self.methods,
+            embed: merge_lit("embed", self.embed, inner.embed)?,
+            state,
+            shared: self.shared || inner.shared,
+            middlewares: self.middlewares,
+            guards: self.guards,
+            rate_limit,
+            timeout: merge_lit("timeout", self.timeout, inner.timeout)?,
+            max_body: merge_int("max_body", self.max_body, inner.max_body)?,
+            concurrency: merge_int("concurrency", self.concurrency, inner.concurrency)?,
+            cors,
+            csrf,
+            trace_args: self.trace_args || inner.trace_args,
+            cache,
+            rpc: self.rpc || inner.rpc
+        };
+
+        for method in inner.methods {
+            merged.push_method(method);
+        }
+        merged.middlewares.extend(inner.middlewares);
+        merged.guards.extend(inner.guards);
+
+        Ok(merged)
+    }
+}
+
 impl Parse for Middleware {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         fn after_routing<T: ToTokens + Spanned>(expr: T) -> Expr {
@@ -145,10 +767,21 @@ impl Parse for Middleware {
             }
         };
 
-        let expr = if let Expr::Call(call) = expr {
+        let expr = if let Expr::Assign(ExprAssign { left, right, .. }) = &expr {
+            // A `group = name` reference to a `middleware_group!`.
+            match left.as_ref() {
+                Expr::Path(ExprPath { path, .. }) if path.is_ident("group") => {
+                    parse_quote_spanned! { right.span() => group(#right) }
+                }
+                _ => after_routing(expr)
+            }
+        } else if let Expr::Call(call) = expr {
             // Check that the given middleware expr is configured for routing.
             if let Expr::Path(ExprPath { path, .. }) = call.func.as_ref() {
-                if path.is_ident("after_routing") || path.is_ident("before_routing") {
+                if path.is_ident("after_routing")
+                    || path.is_ident("before_routing")
+                    || path.is_ident("group")
+                {
                     Expr::Call(call)
                 }
                 // Call expr isn't a routing function.
@@ -170,38 +803,237 @@ impl Parse for Middleware {
     }
 }
 
+impl Parse for Guard {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = Punctuated::<Expr, Token![,]>::parse_terminated(input)?.into_iter();
+
+        let Some(expr) = args.next() else {
+            return Err(syn::Error::new(input.span(), "Guard expression not found."));
+        };
+
+        let mut guard = Self { expr, state: None };
+
+        for arg in args {
+            match arg {
+                Expr::Assign(ExprAssign { left, right, .. })
+                    if matches!(
+                        left.as_ref(),
+                        Expr::Path(ExprPath { path, .. }) if path.is_ident("state")
+                    ) =>
+                {
+                    match *right {
+                        Expr::Path(ExprPath { path, .. }) => guard.state = Some(path),
+                        unexpected => {
+                            return Err(syn::Error::new(
+                                unexpected.span(),
+                                format!("State must be a type path; found ({unexpected:?})")
+                            ));
+                        }
+                    }
+                }
+                unexpected => {
+                    return Err(syn::Error::new(
+                        unexpected.span(),
+                        format!("Unexpected guard argument; found ({unexpected:?})")
+                    ));
+                }
+            }
+        }
+
+        Ok(guard)
+    }
+}
+
+impl Guard {
+    /// Parse a `guard(..)` item of a `guards = [..]` list.
+    fn from_list_item(item: Expr) -> syn::Result<Self> {
+        match item {
+            Expr::Call(call)
+                if matches!(
+                    call.func.as_ref(),
+                    Expr::Path(ExprPath { path, .. }) if path.is_ident("guard")
+                ) =>
+            {
+                syn::parse2(call.args.into_token_stream())
+            }
+            unexpected => Err(syn::Error::new(
+                unexpected.span(),
+                format!("Guards must be written as `guard(..)`; found ({unexpected:?})")
+            ))
+        }
+    }
+}
+
 impl ToTokens for ServerFnArgs {
     fn to_tokens(&self, tokens: &mut TokenStream2) {
         let Self {
             path,
-            method,
+            id,
+            hashed,
+            methods,
             embed,
-            middlewares
+            state,
+            shared,
+            middlewares,
+            guards,
+            rate_limit,
+            timeout,
+            max_body,
+            concurrency,
+            cors,
+            csrf,
+            trace_args,
+            cache,
+            rpc
         } = self;
 
-        let mut args = Punctuated::<MetaNameValue, Comma>::new();
+        let mut args = Punctuated::<Meta, Comma>::new();
 
         if let Some(path) = path {
             args.push(parse_quote! { path = #path });
         }
 
-        if let Some(method) = method {
-            let method = LitStr::new(&method.to_string(), method.span());
-            args.push(parse_quote! { method = #method });
+        if let Some(id) = id {
+            args.push(parse_quote! { id = #id });
+        }
+
+        if *hashed {
+            args.push(parse_quote! { hashed = true });
+        }
+
+        let methods = methods
+            .iter()
+            .map(|method| LitStr::new(&method.to_string(), method.span()))
+            .collect::<Vec<_>>();
+
+        match methods.as_slice() {
+            [] => {}
+            [method] => args.push(parse_quote! { method = #method }),
+            methods => args.push(parse_quote! { methods = [#(#methods),*] })
         }
 
         if let Some(embed) = embed {
             args.push(parse_quote! { embed = #embed });
         }
 
+        if let Some(state) = state {
+            args.push(parse_quote! { state = #state });
+        }
+
+        if *shared {
+            args.push(parse_quote! { shared = true });
+        }
+
         if !middlewares.is_empty() {
             args.push(parse_quote! { middlewares = [#(#middlewares),*] });
         }
 
+        if !guards.is_empty() {
+            args.push(parse_quote! { guards = [#(#guards),*] });
+        }
+
+        if let Some(rate_limit) = rate_limit {
+            args.push(parse_quote! { rate_limit(#rate_limit) });
+        }
+
+        if let Some(timeout) = timeout {
+            args.push(parse_quote! { timeout = #timeout });
+        }
+
+        if let Some(max_body) = max_body {
+            args.push(parse_quote! { max_body = #max_body });
+        }
+
+        if let Some(concurrency) = concurrency {
+            args.push(parse_quote! { concurrency = #concurrency });
+        }
+
+        if let Some(cors) = cors {
+            args.push(parse_quote! { cors(#cors) });
+        }
+
+        if let Some(csrf) = csrf {
+            args.push(parse_quote! { csrf = #csrf });
+        }
+
+        if *trace_args {
+            args.push(parse_quote! { trace_args = true });
+        }
+
+        if let Some(cache) = cache {
+            args.push(parse_quote! { cache(#cache) });
+        }
+
+        if *rpc {
+            args.push(parse_quote! { rpc = true });
+        }
+
         tokens.append_all(args.into_pairs());
     }
 }
 
+impl ToTokens for RateLimitArgs {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        let Self { requests, per, key } = self;
+
+        tokens.append_all(quote! { requests = #requests, per = #per });
+
+        match key {
+            RateLimitKeyArg::Ip => {}
+            RateLimitKeyArg::Header(name) => tokens.append_all(quote! { , key = header(#name) }),
+            RateLimitKeyArg::Custom(path) => tokens.append_all(quote! { , key = #path })
+        }
+    }
+}
+
+impl ToTokens for CorsArgs {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        let Self {
+            origins,
+            headers,
+            credentials,
+            max_age
+        } = self;
+
+        let mut args = vec![quote! { origins = [#(#origins),*] }];
+
+        if !headers.is_empty() {
+            args.push(quote! { headers = [#(#headers),*] });
+        }
+
+        if *credentials {
+            args.push(quote! { credentials = true });
+        }
+
+        if let Some(max_age) = max_age {
+            args.push(quote! { max_age = #max_age });
+        }
+
+        tokens.append_all(quote! { #(#args),* });
+    }
+}
+
+impl ToTokens for CacheArgs {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        let Self { ttl, key } = self;
+
+        tokens.append_all(quote! { ttl = #ttl });
+
+        if !key.is_empty() {
+            tokens.append_all(quote! { , key = [#(#key),*] });
+        }
+    }
+}
+
+impl ToTokens for Guard {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        let Self { expr, state } = self;
+        let state = state.iter();
+
+        tokens.append_all(quote! { guard(#expr #(, state = #state)*) });
+    }
+}
+
 impl ToTokens for Middleware {
     fn to_tokens(&self, tokens: &mut TokenStream2) {
         let Self { expr } = self;
@@ -234,12 +1066,26 @@ mod test {
 
             let expected = ServerFnArgs {
                 path: parse_quote!("/test"),
-                method: Some(Ident::new(method, Span::call_site())),
+                id: None,
+                hashed: false,
+                methods: vec![Ident::new(method, Span::call_site())],
                 embed: parse_quote!("/test"),
+                state: None,
+                shared: false,
                 middlewares: vec![
                     parse_quote!(after_routing(fn_after)),
                     parse_quote!(before_routing(fn_before)),
-                ]
+                ],
+                guards: vec![],
+                rate_limit: None,
+                timeout: None,
+                max_body: None,
+                concurrency: None,
+                cors: None,
+                csrf: None,
+                trace_args: false,
+                cache: None,
+                rpc: false
             };
             assert_eq!(server_fn_args, expected);
             assert_eq!(
@@ -264,6 +1110,308 @@ mod test {
         http_methods!(foreach!(test_parse_method!));
     }
 
+    #[test]
+    fn parse_methods() {
+        let tokens = quote! {
+            methods = ["get", "HEAD", "get"]
+        };
+
+        let server_fn_args: ServerFnArgs = syn::parse2(tokens).unwrap();
+        let expected = ServerFnArgs {
+            methods: vec![
+                Ident::new("get", Span::call_site()),
+                Ident::new("head", Span::call_site()),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(server_fn_args, expected);
+        assert_eq!(
+            server_fn_args.to_token_stream().to_string(),
+            quote!(methods = ["get", "head"]).to_string()
+        );
+    }
+
+    #[test]
+    fn merge_stacked_args() {
+        let outer: ServerFnArgs = syn::parse2(quote! {
+            method = "get",
+            middlewares = [outer_mid]
+        })
+        .unwrap();
+        let inner: ServerFnArgs = syn::parse2(quote! {
+            path = "/test",
+            method = "post",
+            middlewares = [inner_mid]
+        })
+        .unwrap();
+
+        let merged = outer.merge(inner).unwrap();
+        let expected: ServerFnArgs = syn::parse2(quote! {
+            path = "/test",
+            methods = ["get", "post"],
+            middlewares = [outer_mid, inner_mid]
+        })
+        .unwrap();
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn merge_stacked_state() {
+        let outer: ServerFnArgs = syn::parse2(quote! {
+            method = "get",
+            state = admin::AdminState
+        })
+        .unwrap();
+        let inner: ServerFnArgs = syn::parse2(quote! {
+            method = "head"
+        })
+        .unwrap();
+
+        let merged = outer.merge(inner).unwrap();
+        assert_eq!(merged.state, Some(parse_quote!(admin::AdminState)));
+        assert_eq!(
+            merged.to_token_stream().to_string(),
+            quote!(methods = ["get", "head"], state = admin::AdminState).to_string()
+        );
+
+        let conflicting: ServerFnArgs = syn::parse2(quote!(state = AppState)).unwrap();
+        assert!(conflicting.merge(merged).is_err());
+    }
+
+    #[test]
+    fn parse_guards() {
+        let guard: Guard = syn::parse2(quote!(require_user, state = Sessions)).unwrap();
+        assert_eq!(
+            guard,
+            Guard {
+                expr: parse_quote!(require_user),
+                state: Some(parse_quote!(Sessions))
+            }
+        );
+
+        let outer: ServerFnArgs = syn::parse2(quote! {
+            guards = [guard(require_user, state = Sessions)]
+        })
+        .unwrap();
+        let inner: ServerFnArgs = syn::parse2(quote! {
+            method = "get",
+            guards = [guard(auth::is_admin)]
+        })
+        .unwrap();
+
+        let merged = outer.merge(inner).unwrap();
+        assert_eq!(
+            merged.to_token_stream().to_string(),
+            quote! {
+                method = "get",
+                guards = [guard(require_user, state = Sessions), guard(auth::is_admin)]
+            }
+            .to_string()
+        );
+
+        assert!(syn::parse2::<ServerFnArgs>(quote!(guards = [require_user])).is_err());
+        assert!(syn::parse2::<Guard>(quote!(require_user, order = 1)).is_err());
+    }
+
+    #[test]
+    fn parse_rate_limit() {
+        let args: ServerFnArgs = syn::parse2(quote! {
+            method = "post",
+            rate_limit(requests = 10, per = "1m", key = header("x-api-key"))
+        })
+        .unwrap();
+
+        let rate_limit = args.rate_limit.as_ref().unwrap();
+        assert_eq!(rate_limit.requests.base10_digits(), "10");
+        assert_eq!(parse_duration_millis(&rate_limit.per).unwrap(), 60_000);
+        assert_eq!(
+            rate_limit.key,
+            RateLimitKeyArg::Header(parse_quote!("x-api-key"))
+        );
+        assert_eq!(
+            args.to_token_stream().to_string(),
+            quote! {
+                method = "post",
+                rate_limit(requests = 10, per = "1m", key = header("x-api-key"))
+            }
+            .to_string()
+        );
+
+        let args: RateLimitArgs = syn::parse2(quote!(requests = 1, per = "500ms")).unwrap();
+        assert_eq!(args.key, RateLimitKeyArg::Ip);
+        let args: RateLimitArgs =
+            syn::parse2(quote!(requests = 1, per = "1h", key = keys::tenant)).unwrap();
+        assert_eq!(
+            args.key,
+            RateLimitKeyArg::Custom(parse_quote!(keys::tenant))
+        );
+
+        assert!(syn::parse2::<RateLimitArgs>(quote!(requests = 10)).is_err());
+        assert!(syn::parse2::<RateLimitArgs>(quote!(requests = 0, per = "1s")).is_err());
+        assert!(syn::parse2::<RateLimitArgs>(quote!(requests = 5, per = "1d")).is_err());
+        assert!(syn::parse2::<RateLimitArgs>(quote!(requests = 5, per = "s")).is_err());
+    }
+
+    #[test]
+    fn parse_route_limits() {
+        let tokens = quote! {
+            method = "post",
+            timeout = "5s",
+            max_body = 1024,
+            concurrency = 8
+        };
+
+        let args: ServerFnArgs = syn::parse2(tokens.clone()).unwrap();
+        assert_eq!(args.timeout, Some(parse_quote!("5s")));
+        assert_eq!(args.max_body.as_ref().unwrap().base10_digits(), "1024");
+        assert_eq!(args.concurrency.as_ref().unwrap().base10_digits(), "8");
+        assert_eq!(args.to_token_stream().to_string(), tokens.to_string());
+
+        let inner: ServerFnArgs = syn::parse2(quote!(concurrency = 8)).unwrap();
+        assert!(args.merge(inner).is_ok());
+
+        let outer: ServerFnArgs = syn::parse2(quote!(max_body = 10)).unwrap();
+        let inner: ServerFnArgs = syn::parse2(quote!(max_body = 20)).unwrap();
+        assert!(outer.merge(inner).is_err());
+
+        assert!(syn::parse2::<ServerFnArgs>(quote!(timeout = "5 seconds")).is_err());
+        let err = syn::parse2::<ServerFnArgs>(quote!(timeout = "9999999999999999h"))
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("Duration is too long"));
+        assert!(syn::parse2::<ServerFnArgs>(quote!(max_body = "1mb")).is_err());
+        assert!(syn::parse2::<ServerFnArgs>(quote!(concurrency = 0)).is_err());
+    }
+
+    #[test]
+    fn parse_cors() {
+        let tokens = quote! {
+            method = "get",
+            cors(
+                origins = ["https://app.test"],
+                headers = ["x-api-key"],
+                credentials = true,
+                max_age = "1h"
+            )
+        };
+
+        let args: ServerFnArgs = syn::parse2(tokens.clone()).unwrap();
+        let cors = args.cors.as_ref().unwrap();
+        assert_eq!(cors.origins, vec![parse_quote!("https://app.test")]);
+        assert_eq!(cors.headers, vec![parse_quote!("x-api-key")]);
+        assert!(cors.credentials);
+        assert_eq!(cors.max_age, Some(parse_quote!("1h")));
+        assert_eq!(args.to_token_stream().to_string(), tokens.to_string());
+
+        let args: ServerFnArgs = syn::parse2(quote!(cors(origins = ["*"]))).unwrap();
+        assert_eq!(
+            args.cors,
+            Some(CorsArgs {
+                origins: vec![parse_quote!("*")],
+                ..Default::default()
+            })
+        );
+
+        assert!(syn::parse2::<ServerFnArgs>(quote!(cors(max_age = "a day"))).is_err());
+        assert!(syn::parse2::<ServerFnArgs>(quote!(cors(methods = ["get"]))).is_err());
+        assert!(
+            syn::parse2::<ServerFnArgs>(quote!(cors(origins = ["*"], credentials = true))).is_err()
+        );
+    }
+
+    #[test]
+    fn parse_cache() {
+        let tokens = quote!(
+            method = "get",
+            cache(ttl = "30s", key = ["accept-language"])
+        );
+
+        let args: ServerFnArgs = syn::parse2(tokens.clone()).unwrap();
+        let cache = args.cache.as_ref().unwrap();
+        assert_eq!(parse_duration_millis(&cache.ttl).unwrap(), 30_000);
+        assert_eq!(cache.key, vec![parse_quote!("accept-language")]);
+        assert_eq!(args.to_token_stream().to_string(), tokens.to_string());
+
+        let args: ServerFnArgs = syn::parse2(quote!(cache(ttl = "1m"))).unwrap();
+        assert!(args.cache.unwrap().key.is_empty());
+
+        assert!(syn::parse2::<ServerFnArgs>(quote!(cache(key = ["x-tenant"]))).is_err());
+        assert!(syn::parse2::<ServerFnArgs>(quote!(cache(ttl = "forever"))).is_err());
+    }
+
+    #[test]
+    fn parse_csrf() {
+        let tokens = quote!(method = "post", csrf = false, trace_args = true, rpc = true);
+
+        let args: ServerFnArgs = syn::parse2(tokens.clone()).unwrap();
+        assert_eq!(args.csrf, Some(false));
+        assert!(args.trace_args);
+        assert!(args.rpc);
+        assert_eq!(args.to_token_stream().to_string(), tokens.to_string());
+
+        let outer: ServerFnArgs = syn::parse2(quote!(csrf = false)).unwrap();
+        let inner: ServerFnArgs = syn::parse2(quote!(csrf = true)).unwrap();
+        assert!(outer.merge(inner).is_err());
+
+        assert!(syn::parse2::<ServerFnArgs>(quote!(csrf = "off")).is_err());
+        assert!(syn::parse2::<ServerFnArgs>(quote!(rpc = "yes")).is_err());
+    }
+
+    #[test]
+    fn parse_stable_ids() {
+        let tokens = quote!(id = "billing.charge", hashed = true);
+
+        let args: ServerFnArgs = syn::parse2(tokens.clone()).unwrap();
+        assert_eq!(args.id.as_ref().unwrap().value(), "billing.charge");
+        assert!(args.hashed);
+        assert_eq!(args.to_token_stream().to_string(), tokens.to_string());
+
+        let outer: ServerFnArgs = syn::parse2(quote!(id = "a")).unwrap();
+        let inner: ServerFnArgs = syn::parse2(quote!(id = "b")).unwrap();
+        assert!(outer.merge(inner).is_err());
+
+        assert!(syn::parse2::<ServerFnArgs>(quote!(id = "")).is_err());
+        assert!(syn::parse2::<ServerFnArgs>(quote!(hashed = "yes")).is_err());
+    }
+
+    #[test]
+    fn parse_middleware_group() {
+        let group: MiddlewareGroup = syn::parse2(quote! {
+            pub authenticated = [from_fn(require_user), before_routing(trace_layer())]
+        })
+        .unwrap();
+
+        assert_eq!(group.ident, "authenticated");
+        assert_eq!(
+            group.middlewares,
+            vec![
+                parse_quote!(after_routing(from_fn(require_user))),
+                parse_quote!(before_routing(trace_layer())),
+            ]
+        );
+
+        let reference: Middleware = syn::parse2(quote!(group = auth::authenticated)).unwrap();
+        assert_eq!(reference, parse_quote!(group(auth::authenticated)));
+    }
+
+    #[test]
+    fn parse_global_middleware_args() {
+        let args: GlobalMiddlewareArgs =
+            syn::parse2(quote!(state = AppState, order = -10)).unwrap();
+        assert_eq!(
+            args,
+            GlobalMiddlewareArgs {
+                state: Some(parse_quote!(AppState)),
+                order: -10
+            }
+        );
+
+        let args: GlobalMiddlewareArgs = syn::parse2(quote!()).unwrap();
+        assert_eq!(args, GlobalMiddlewareArgs::default());
+
+        assert!(syn::parse2::<GlobalMiddlewareArgs>(quote!(order = "first")).is_err());
+    }
+
     #[test]
     fn parse_middleware() {
         let tokens = quote! {
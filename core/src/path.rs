@@ -0,0 +1,34 @@
+#![cfg(any(feature = "server", feature = "web"))]
+
+/// Whether `path` matches an axum path `pattern`, where `:param` matches one segment and
+/// `*rest` matches the remainder.
+pub(crate) fn path_matches(pattern: &str, path: &str) -> bool {
+    let mut path_parts = path.split('/');
+
+    for part in pattern.split('/') {
+        if part.starts_with('*') {
+            return true;
+        }
+
+        match path_parts.next() {
+            Some(segment) if part.starts_with(':') && !segment.is_empty() => {}
+            Some(segment) if segment == part => {}
+            _ => return false
+        }
+    }
+
+    path_parts.next().is_none()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn match_paths() {
+        assert!(path_matches("/api/users/:id", "/api/users/42"));
+        assert!(path_matches("/assets/*rest", "/assets/css/site.css"));
+        assert!(!path_matches("/api/users/:id", "/api/users/"));
+        assert!(!path_matches("/api/users/:id", "/api/users/42/posts"));
+    }
+}
@@ -0,0 +1,384 @@
+#![cfg(feature = "server")]
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex, OnceLock, RwLock},
+    time::{Duration, Instant}
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response
+};
+
+use crate::limit::Limit;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// How many requests a key may make per window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub requests: u32,
+    pub per: Duration
+}
+
+/// What a rate limited route counts requests by.
+#[derive(Debug, Clone, Copy)]
+pub enum RateLimitKey {
+    /// The client's ip, from the [ConnectInfo] that
+    /// [ServerApp::serve](crate::app::ServerApp::serve) provides.
+    ///
+    /// Routers served any other way, like through `axum::serve` without
+    /// `into_make_service_with_connect_info`, have no [ConnectInfo], so every client shares one
+    /// bucket and the whole route is limited to the quota. Use [RateLimitKey::Header] or
+    /// [RateLimitKey::Custom] there, like with a proxy's forwarded-for header.
+    Ip,
+    /// The value of a request header. Requests without it share a single bucket.
+    Header(&'static str),
+    /// A key computed from the request.
+    Custom(fn(&Request) -> String)
+}
+
+impl RateLimitKey {
+    pub fn of(&self, request: &Request) -> String {
+        match self {
+            Self::Ip => request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string())
+                .unwrap_or_default(),
+            Self::Header(name) => request
+                .headers()
+                .get(*name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_owned(),
+            Self::Custom(key) => key(request)
+        }
+    }
+}
+
+/// The rate limit of a single route, generated from its `rate_limit(..)` server argument.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// Keeps the buckets of different routes apart within a shared store.
+    pub scope: &'static str,
+    pub quota: Quota,
+    pub key: RateLimitKey
+}
+
+/// Token buckets shared by every rate limited route, installed with [set_rate_limit_store].
+///
+/// Implement it over a shared cache to limit across server instances; the default is a
+/// [MemoryStore] local to the process.
+pub trait RateLimitStore: Send + Sync + 'static {
+    /// Take a token from the bucket of `key`, or return how long until one is available.
+    fn acquire(&self, key: String, quota: Quota) -> BoxFuture<'_, Result<(), Duration>>;
+}
+
+static STORE: RwLock<Option<Arc<dyn RateLimitStore>>> = RwLock::new(None);
+
+/// Replace the store every rate limited route takes its tokens from.
+pub fn set_rate_limit_store(store: impl RateLimitStore) {
+    let mut global = STORE.write().unwrap_or_else(|err| err.into_inner());
+    *global = Some(Arc::new(store));
+}
+
+fn store() -> Arc<dyn RateLimitStore> {
+    static DEFAULT: OnceLock<Arc<MemoryStore>> = OnceLock::new();
+
+    let global = STORE.read().unwrap_or_else(|err| err.into_inner());
+    match global.as_ref() {
+        Some(store) => store.clone(),
+        None => DEFAULT.get_or_init(Default::default).clone()
+    }
+}
+
+/// In-memory token buckets, each holding up to [Quota::requests] tokens and refilling them
+/// evenly over [Quota::per].
+///
+/// Buckets are dropped once they've refilled, since a new bucket starts full, and at most
+/// [MemoryStore::capacity] are held, dropping those closest to full once it's reached.
+#[derive(Debug)]
+pub struct MemoryStore {
+    capacity: usize,
+    buckets: Mutex<Buckets>
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    /// The number of buckets that triggers the next sweep.
+    sweep_at: usize
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket will have refilled, after which it can be dropped.
+    full_at: Instant
+}
+
+impl MemoryStore {
+    pub const DEFAULT_CAPACITY: usize = 100_000;
+    /// Buckets held before the first sweep for refilled ones.
+    const MIN_SWEEP: usize = 1024;
+    /// The fraction of [Self::capacity] left after dropping buckets at capacity.
+    const LOW_WATER: (usize, usize) = (9, 10);
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            buckets: Default::default()
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of buckets held.
+    pub fn len(&self) -> usize {
+        self.lock().buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Buckets> {
+        self.buckets.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn try_acquire(&self, key: String, quota: Quota, now: Instant) -> Result<(), Duration> {
+        let capacity = f64::from(quota.requests);
+        let per_token = quota.per.as_secs_f64() / capacity;
+
+        let mut buckets = self.lock();
+        if !buckets.buckets.contains_key(&key) {
+            buckets.make_room(self.capacity, now);
+        }
+
+        let bucket = buckets.buckets.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+            full_at: now
+        });
+
+        let refilled = now.saturating_duration_since(bucket.updated).as_secs_f64() / per_token;
+        bucket.tokens = (bucket.tokens + refilled).min(capacity);
+        bucket.updated = now;
+
+        let acquired = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) * per_token))
+        };
+        bucket.full_at = now + Duration::from_secs_f64((capacity - bucket.tokens) * per_token);
+
+        acquired
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl Buckets {
+    /// Make room for a new bucket, sweeping refilled buckets once their number has doubled since
+    /// the last sweep.
+    ///
+    /// At `capacity`, the buckets closest to full are dropped in one batch down to
+    /// [MemoryStore::LOW_WATER] of it, so a stream of new keys pays for an eviction once every
+    /// tenth of `capacity` keys rather than on each one.
+    fn make_room(&mut self, capacity: usize, now: Instant) {
+        if self.buckets.len() >= self.sweep_at.max(MemoryStore::MIN_SWEEP).min(capacity) {
+            self.buckets.retain(|_, bucket| bucket.full_at > now);
+            self.sweep_at = self.buckets.len() * 2;
+        }
+
+        if self.buckets.len() >= capacity {
+            let low_water =
+                (capacity * MemoryStore::LOW_WATER.0 / MemoryStore::LOW_WATER.1).min(capacity - 1);
+            let excess = self.buckets.len() - low_water;
+
+            let mut full_at = self
+                .buckets
+                .iter()
+                .map(|(key, bucket)| (bucket.full_at, key.as_str()))
+                .collect::<Vec<_>>();
+            full_at.select_nth_unstable(excess - 1);
+
+            let evicted = full_at[..excess]
+                .iter()
+                .map(|(_, key)| (*key).to_owned())
+                .collect::<Vec<_>>();
+            for key in evicted {
+                self.buckets.remove(&key);
+            }
+        }
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn acquire(&self, key: String, quota: Quota) -> BoxFuture<'_, Result<(), Duration>> {
+        let acquired = self.try_acquire(key, quota, Instant::now());
+
+        Box::pin(async move { acquired })
+    }
+}
+
+/// Middleware passing a request on while its key has tokens left, rejecting it with
+/// [Limit::RateLimit] and a `Retry-After` header otherwise.
+pub async fn limit(State(limit): State<RateLimit>, request: Request, next: Next) -> Response {
+    let key = format!("{} {}", limit.scope, limit.key.of(&request));
+
+    match store().acquire(key, limit.quota).await {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            // Retry-After is in whole seconds, so round up rather than invite an early retry.
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+            let mut response = Limit::RateLimit.reject();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds.max(1)));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{Method, StatusCode},
+        middleware::from_fn_with_state,
+        routing::get,
+        Router
+    };
+
+    use super::*;
+    use crate::testing::TestServer;
+
+    const QUOTA: Quota = Quota {
+        requests: 2,
+        per: Duration::from_secs(10)
+    };
+
+    #[test]
+    fn refill_tokens() {
+        let store = MemoryStore::default();
+        let start = Instant::now();
+        let key = || "route 127.0.0.1".to_owned();
+
+        assert_eq!(store.try_acquire(key(), QUOTA, start), Ok(()));
+        assert_eq!(store.try_acquire(key(), QUOTA, start), Ok(()));
+        assert_eq!(
+            store.try_acquire(key(), QUOTA, start),
+            Err(Duration::from_secs(5))
+        );
+        assert_eq!(
+            store.try_acquire("route other".into(), QUOTA, start),
+            Ok(())
+        );
+
+        let later = start + Duration::from_secs(5);
+        assert_eq!(store.try_acquire(key(), QUOTA, later), Ok(()));
+        assert!(store.try_acquire(key(), QUOTA, later).is_err());
+    }
+
+    #[test]
+    fn drop_buckets() {
+        let store = MemoryStore::new(MemoryStore::MIN_SWEEP * 2);
+        let start = Instant::now();
+
+        for key in 0..MemoryStore::MIN_SWEEP {
+            store.try_acquire(key.to_string(), QUOTA, start).unwrap();
+        }
+        assert_eq!(store.len(), MemoryStore::MIN_SWEEP);
+
+        // Once every bucket has refilled, the next new key sweeps them away.
+        let refilled = start + QUOTA.per;
+        store.try_acquire("new".into(), QUOTA, refilled).unwrap();
+        assert_eq!(store.len(), 1);
+
+        let store = MemoryStore::new(2);
+        store.try_acquire("a".into(), QUOTA, start).unwrap();
+        store.try_acquire("b".into(), QUOTA, start).unwrap();
+        store.try_acquire("b".into(), QUOTA, start).unwrap();
+        store.try_acquire("c".into(), QUOTA, start).unwrap();
+        assert_eq!(store.len(), 2);
+        // The emptier bucket is kept, so its key stays limited.
+        assert!(store.try_acquire("b".into(), QUOTA, start).is_err());
+
+        // At capacity, buckets are dropped in a batch rather than one per new key.
+        let store = MemoryStore::new(100);
+        for key in 0..100 {
+            store.try_acquire(key.to_string(), QUOTA, start).unwrap();
+        }
+        store.try_acquire("new".into(), QUOTA, start).unwrap();
+        assert_eq!(store.len(), 91);
+        store.try_acquire("newer".into(), QUOTA, start).unwrap();
+        assert_eq!(store.len(), 92);
+    }
+
+    #[tokio::test]
+    async fn limit_requests() {
+        let limited = RateLimit {
+            scope: "rate_limit::test::limit_requests",
+            quota: QUOTA,
+            key: RateLimitKey::Header("x-api-key")
+        };
+        let router = Router::new()
+            .route("/limited", get(|| async { "ok" }))
+            .route_layer(from_fn_with_state(limited, limit));
+        let server = TestServer::from_router(router);
+
+        let send = |key: &'static str| {
+            server
+                .request(Method::GET, "/limited")
+                .header("x-api-key", key)
+                .send()
+        };
+
+        assert_eq!(send("first").await.status(), StatusCode::OK);
+        assert_eq!(send("first").await.status(), StatusCode::OK);
+
+        let response = send("first").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "5");
+        assert_eq!(response.headers()[crate::limit::LIMIT_HEADER], "rate-limit");
+
+        assert_eq!(send("second").await.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn key_requests() {
+        let mut request = Request::new(Body::empty());
+        assert_eq!(RateLimitKey::Ip.of(&request), "");
+
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 7], 4000))));
+        request
+            .headers_mut()
+            .insert("x-api-key", HeaderValue::from_static("secret"));
+
+        assert_eq!(RateLimitKey::Ip.of(&request), "10.0.0.7");
+        assert_eq!(RateLimitKey::Header("X-Api-Key").of(&request), "secret");
+        assert_eq!(
+            RateLimitKey::Custom(|request| request.uri().path().to_owned()).of(&request),
+            "/"
+        );
+    }
+}
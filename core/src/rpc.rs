@@ -0,0 +1,651 @@
+#![cfg(feature = "server")]
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    body::{self, Body},
+    extract::{Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Router
+};
+use serde_json::{json, Map, Value};
+use tower::ServiceExt;
+
+use crate::{
+    batch::{Inherited, MAX_BATCH_CALLS},
+    limit::LIMIT_HEADER,
+    server_router::{Codec, RouteInfo}
+};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// The code of errors for calls their server function answered with an error status.
+pub const SERVER_ERROR: i64 = -32000;
+
+/// The largest request body accepted, matching axum's default body limit.
+const MAX_RPC_BYTES: usize = 2 * 1024 * 1024;
+
+/// A state's JSON-RPC 2.0 endpoint, from `#[server_state(rpc = "/rpc")]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RpcEndpoint {
+    pub path: &'static str,
+    /// Only expose server functions tagged `rpc = true`, from `#[server_state(rpc_tagged)]`.
+    pub tagged_only: bool
+}
+
+/// The server functions an endpoint exposes by name, and the router serving them.
+struct Methods {
+    router: Router,
+    routes: HashMap<String, &'static RouteInfo>
+}
+
+/// Serve a JSON-RPC 2.0 endpoint calling the server functions of `routes` by name.
+///
+/// Params are mapped onto the function's path, query and body arguments, by name or position,
+/// and calls are dispatched through `router` with the endpoint request's headers, so they pass
+/// through every layer of the route they call. Functions sharing a name are each exposed under
+/// their module path instead, e.g. `app::users::list`.
+pub fn route(router: Router, endpoint: RpcEndpoint, routes: Vec<&'static RouteInfo>) -> Router {
+    let methods = Arc::new(Methods {
+        router: router.clone(),
+        routes: method_names(endpoint, routes)
+    });
+    let rpc = Router::new()
+        .route(endpoint.path, post(handle))
+        .with_state(methods);
+
+    router.merge(rpc)
+}
+
+/// The routes `endpoint` exposes by method name, qualified by module path when they share a name.
+fn method_names(
+    endpoint: RpcEndpoint,
+    routes: Vec<&'static RouteInfo>
+) -> HashMap<String, &'static RouteInfo> {
+    let exposed = routes
+        .into_iter()
+        .filter(|route| route.rpc || !endpoint.tagged_only)
+        .collect::<Vec<_>>();
+
+    let mut name_counts = HashMap::<_, usize>::new();
+    for route in &exposed {
+        *name_counts.entry(route.name).or_default() += 1;
+    }
+
+    exposed
+        .into_iter()
+        .map(|route| match name_counts[route.name] {
+            1 => (route.name.to_owned(), route),
+            _ => (format!("{}::{}", route.module_path, route.name), route)
+        })
+        .collect()
+}
+
+async fn handle(State(methods): State<Arc<Methods>>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    let inherited = Inherited::of(&parts);
+
+    let parsed = body::to_bytes(body, MAX_RPC_BYTES)
+        .await
+        .ok()
+        .and_then(|body| serde_json::from_slice::<Value>(&body).ok());
+
+    let response = match parsed {
+        None => Some(reply(
+            Value::Null,
+            Err(error(PARSE_ERROR, "Parse error", None))
+        )),
+        Some(Value::Array(calls)) if calls.is_empty() => Some(reply(
+            Value::Null,
+            Err(error(INVALID_REQUEST, "Invalid Request", None))
+        )),
+        Some(Value::Array(calls)) if calls.len() > MAX_BATCH_CALLS => Some(reply(
+            Value::Null,
+            Err(error(
+                INVALID_REQUEST,
+                format!("Batches may carry at most ({MAX_BATCH_CALLS}) calls"),
+                None
+            ))
+        )),
+        Some(Value::Array(calls)) => {
+            let handles = calls
+                .into_iter()
+                .map(|call| tokio::spawn(dispatch(methods.clone(), inherited.clone(), call)))
+                .collect::<Vec<_>>();
+
+            let mut replies = Vec::new();
+            for handle in handles {
+                match handle.await {
+                    Ok(Some(reply)) => replies.push(reply),
+                    Ok(None) => {}
+                    Err(_) => replies.push(reply(
+                        Value::Null,
+                        Err(error(INTERNAL_ERROR, "Internal error", None))
+                    ))
+                }
+            }
+
+            (!replies.is_empty()).then_some(Value::Array(replies))
+        }
+        Some(call) => dispatch(methods, inherited, call).await
+    };
+
+    match response {
+        Some(response) => (
+            [(header::CONTENT_TYPE, "application/json")],
+            response.to_string()
+        )
+            .into_response(),
+        // Notifications get no reply.
+        None => StatusCode::NO_CONTENT.into_response()
+    }
+}
+
+fn error(code: i64, message: impl Into<String>, data: Option<Value>) -> Value {
+    let mut error = json!({ "code": code, "message": message.into() });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+
+    error
+}
+
+fn reply(id: Value, outcome: Result<Value, Value>) -> Value {
+    match outcome {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err(error) => json!({ "jsonrpc": "2.0", "error": error, "id": id })
+    }
+}
+
+/// Call the server function a single request names, returning its reply unless it's a
+/// notification.
+async fn dispatch(methods: Arc<Methods>, inherited: Inherited, call: Value) -> Option<Value> {
+    let invalid = || {
+        Some(reply(
+            Value::Null,
+            Err(error(INVALID_REQUEST, "Invalid Request", None))
+        ))
+    };
+
+    let Value::Object(call) = call else {
+        return invalid();
+    };
+    let id = call.get("id").cloned();
+    let params = call.get("params");
+
+    let valid = call.get("jsonrpc").and_then(Value::as_str) == Some("2.0")
+        && matches!(
+            id,
+            None | Some(Value::Null | Value::String(_) | Value::Number(_))
+        )
+        && matches!(params, None | Some(Value::Array(_) | Value::Object(_)));
+    let (true, Some(method)) = (valid, call.get("method").and_then(Value::as_str)) else {
+        return invalid();
+    };
+
+    let outcome = match methods.routes.get(method) {
+        None => Err(error(METHOD_NOT_FOUND, "Method not found", None)),
+        Some(route) => match call_request(route, params, &inherited) {
+            Err(message) => Err(error(INVALID_PARAMS, message, None)),
+            Ok(request) => {
+                let response = methods
+                    .router
+                    .clone()
+                    .oneshot(request)
+                    .await
+                    .unwrap_or_else(|never| match never {});
+                outcome_of(route, response).await
+            }
+        }
+    };
+
+    id.map(|id| reply(id, outcome))
+}
+
+/// The request calling `route` with `params`.
+fn call_request(
+    route: &RouteInfo,
+    params: Option<&Value>,
+    inherited: &Inherited
+) -> Result<Request, String> {
+    let args = route
+        .args
+        .iter()
+        .filter(|arg| {
+            matches!(
+                arg.ty.codec,
+                Codec::Path | Codec::Query | Codec::Json | Codec::Form | Codec::Text
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let values = match params {
+        None => vec![Value::Null; args.len()],
+        Some(Value::Array(values)) if values.len() > args.len() => {
+            return Err(format!(
+                "({}) takes at most ({}) params; found ({})",
+                route.name,
+                args.len(),
+                values.len()
+            ));
+        }
+        Some(Value::Array(values)) => (0..args.len())
+            .map(|i| values.get(i).cloned().unwrap_or(Value::Null))
+            .collect(),
+        Some(Value::Object(values)) => args
+            .iter()
+            .map(|arg| values.get(arg.name).cloned().unwrap_or(Value::Null))
+            .collect(),
+        Some(_) => return Err("Params must be an array or object".into())
+    };
+
+    let mut path_params = PathParams::default();
+    let mut query = None;
+    let mut body = None;
+
+    for (arg, value) in args.into_iter().zip(values) {
+        let encode_err = |err: serde_urlencoded::ser::Error| format!("({}) {err}", arg.name);
+
+        match (arg.ty.codec, value) {
+            (Codec::Path, value) => path_params.push(value),
+            (Codec::Query | Codec::Form, Value::Null) => {}
+            (Codec::Query, value) => {
+                query = Some(serde_urlencoded::to_string(&value).map_err(encode_err)?);
+            }
+            (Codec::Form, value) => {
+                let encoded = serde_urlencoded::to_string(&value).map_err(encode_err)?;
+                body = Some(("application/x-www-form-urlencoded", encoded));
+            }
+            (Codec::Json, value) => body = Some(("application/json", value.to_string())),
+            (Codec::Text, Value::String(text)) => body = Some(("text/plain; charset=utf-8", text)),
+            (_, value) => return Err(format!("({}) must be a string; found ({value})", arg.name))
+        }
+    }
+
+    let mut url = path_params.fill(route.path)?;
+    if let Some(query) = query.filter(|query| !query.is_empty()) {
+        url = format!("{url}?{query}");
+    }
+
+    let method = match route.methods.first() {
+        Some(&"any") | None => "POST".to_owned(),
+        Some(method) => method.to_uppercase()
+    };
+
+    let (content_type, body) = body.unzip();
+    let mut request = Request::builder()
+        .method(method.as_str())
+        .uri(url)
+        .body(Body::from(body.unwrap_or_default()))
+        .map_err(|err| err.to_string())?;
+
+    inherited.apply(&mut request);
+    if let Some(content_type) = content_type {
+        request.headers_mut().insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static(content_type)
+        );
+    }
+
+    Ok(request)
+}
+
+/// The values of a route's path params, given positionally or by name.
+#[derive(Debug, Default)]
+struct PathParams {
+    positional: Vec<Value>,
+    named: Map<String, Value>
+}
+
+impl PathParams {
+    fn push(&mut self, value: Value) {
+        match value {
+            Value::Array(values) => self.positional.extend(values),
+            Value::Object(values) => self.named.extend(values),
+            value => self.positional.push(value)
+        }
+    }
+
+    /// The url of the axum path `pattern` with its params filled in.
+    fn fill(mut self, pattern: &str) -> Result<String, String> {
+        let mut positional = std::mem::take(&mut self.positional).into_iter();
+
+        pattern
+            .split('/')
+            .map(|part| {
+                let (name, wildcard) = match (part.strip_prefix(':'), part.strip_prefix('*')) {
+                    (Some(name), _) => (name, false),
+                    (_, Some(name)) => (name, true),
+                    _ => return Ok(part.to_owned())
+                };
+
+                match self.named.remove(name).or_else(|| positional.next()) {
+                    // A wildcard matches the rest of the path, so its `/` separators are kept.
+                    Some(Value::String(value)) if wildcard => Ok(value
+                        .split('/')
+                        .map(encode_segment)
+                        .collect::<Vec<_>>()
+                        .join("/")),
+                    Some(Value::String(value)) => Ok(encode_segment(&value)),
+                    Some(value @ (Value::Number(_) | Value::Bool(_))) => Ok(value.to_string()),
+                    _ => Err(format!("Missing path param ({name})"))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|parts| parts.join("/"))
+    }
+}
+
+/// Percent encode every byte of `segment` outside the unreserved characters.
+fn encode_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            byte => format!("%{byte:02X}")
+        })
+        .collect()
+}
+
+/// The result of a call its server function answered with `response`, or its error object.
+async fn outcome_of(route: &RouteInfo, response: Response) -> Result<Value, Value> {
+    let (parts, body) = response.into_parts();
+    let body = body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|err| error(INTERNAL_ERROR, err.to_string(), None))?;
+
+    if !parts.status.is_success() {
+        let code = match parts.status {
+            StatusCode::BAD_REQUEST
+            | StatusCode::UNSUPPORTED_MEDIA_TYPE
+            | StatusCode::UNPROCESSABLE_ENTITY => INVALID_PARAMS,
+            _ => SERVER_ERROR
+        };
+        let message = match String::from_utf8_lossy(&body) {
+            message if message.is_empty() => parts
+                .status
+                .canonical_reason()
+                .unwrap_or("Server error")
+                .to_owned(),
+            message => message.into_owned()
+        };
+
+        let mut data = json!({ "status": parts.status.as_u16() });
+        if let Some(limit) = parts
+            .headers
+            .get(LIMIT_HEADER)
+            .and_then(|limit| limit.to_str().ok())
+        {
+            data["limit"] = limit.into();
+        }
+
+        return Err(error(code, message, Some(data)));
+    }
+
+    match route.output.codec {
+        Codec::Html | Codec::Text => Ok(String::from_utf8_lossy(&body).into()),
+        _ if body.is_empty() => Ok(Value::Null),
+        Codec::Json => serde_json::from_slice(&body)
+            .map_err(|err| error(INTERNAL_ERROR, err.to_string(), None)),
+        _ => {
+            Ok(serde_json::from_slice(&body)
+                .unwrap_or_else(|_| String::from_utf8_lossy(&body).into()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        extract::{Path, RawQuery},
+        http::Method,
+        routing::get
+    };
+
+    use super::*;
+    use crate::{
+        server_router::{ArgInfo, TypeInfo},
+        testing::TestServer
+    };
+
+    const fn type_info(codec: Codec, ty: &'static str) -> TypeInfo {
+        TypeInfo { codec, ty }
+    }
+
+    const fn route_info(
+        path: &'static str,
+        methods: &'static [&'static str],
+        name: &'static str,
+        args: &'static [ArgInfo],
+        output: TypeInfo,
+        rpc: bool
+    ) -> RouteInfo {
+        RouteInfo {
+            path,
+            methods,
+            name,
+            module_path: "rpc::test",
+            package: "server-fns-core",
+            middleware_count: 0,
+            embed: None,
+            args,
+            output,
+            cors: None,
+            csrf: true,
+            rpc
+        }
+    }
+
+    static ADD: RouteInfo = route_info(
+        "/api/add",
+        &["post"],
+        "add",
+        &[ArgInfo {
+            name: "numbers",
+            ty: type_info(Codec::Json, "Vec<i64>")
+        }],
+        type_info(Codec::Json, "i64"),
+        true
+    );
+
+    static GREET: RouteInfo = route_info(
+        "/api/greet/:name",
+        &["get"],
+        "greet",
+        &[
+            ArgInfo {
+                name: "arg0",
+                ty: type_info(Codec::Path, "String")
+            },
+            ArgInfo {
+                name: "options",
+                ty: type_info(Codec::Query, "HashMap<String,String>")
+            }
+        ],
+        type_info(Codec::Text, "String"),
+        false
+    );
+
+    fn server(tagged_only: bool) -> TestServer {
+        let router = Router::new()
+            .route(
+                "/api/add",
+                post(|numbers: String| async move {
+                    match serde_json::from_str::<Vec<i64>>(&numbers) {
+                        Ok(numbers) => Ok(numbers.iter().sum::<i64>().to_string()),
+                        Err(err) => Err((StatusCode::UNPROCESSABLE_ENTITY, err.to_string()))
+                    }
+                })
+            )
+            .route(
+                "/api/greet/:name",
+                get(
+                    |Path(name): Path<String>, RawQuery(query): RawQuery| async move {
+                        let options: HashMap<String, String> =
+                            serde_urlencoded::from_str(&query.unwrap_or_default()).unwrap();
+                        let greeting = options.get("greeting").map_or("hello", String::as_str);
+                        format!("{greeting} {name}")
+                    }
+                )
+            );
+        let endpoint = RpcEndpoint {
+            path: "/rpc",
+            tagged_only
+        };
+
+        TestServer::from_router(route(router, endpoint, vec![&ADD, &GREET]))
+    }
+
+    async fn call(server: &TestServer, body: Value) -> Value {
+        let response = server
+            .request(Method::POST, "/rpc")
+            .json(&body)
+            .send()
+            .await;
+
+        serde_json::from_slice(response.body()).unwrap_or(Value::Null)
+    }
+
+    #[test]
+    fn qualify_shared_names() {
+        static OTHER_ADD: RouteInfo = RouteInfo {
+            path: "/api/other/add",
+            module_path: "other",
+            ..ADD
+        };
+
+        let endpoint = RpcEndpoint {
+            path: "/rpc",
+            tagged_only: false
+        };
+        let names = method_names(endpoint, vec![&ADD, &GREET, &OTHER_ADD]);
+
+        let mut listed = names.keys().map(String::as_str).collect::<Vec<_>>();
+        listed.sort_unstable();
+        assert_eq!(listed, ["greet", "other::add", "rpc::test::add"]);
+        assert_eq!(names["other::add"].path, "/api/other/add");
+    }
+
+    #[tokio::test]
+    async fn call_by_name() {
+        let server = server(false);
+
+        let reply = call(
+            &server,
+            json!({ "jsonrpc": "2.0", "method": "add", "params": [[1, 2, 3]], "id": 1 })
+        )
+        .await;
+        assert_eq!(reply, json!({ "jsonrpc": "2.0", "result": 6, "id": 1 }));
+
+        let reply = call(
+            &server,
+            json!({
+                "jsonrpc": "2.0",
+                "method": "greet",
+                "params": { "arg0": "a b", "options": { "greeting": "hi" } },
+                "id": "greeting"
+            })
+        )
+        .await;
+        assert_eq!(reply["result"], "hi a b");
+
+        let reply = call(
+            &server,
+            json!({ "jsonrpc": "2.0", "method": "add", "params": [["one"]], "id": 2 })
+        )
+        .await;
+        assert_eq!(reply["error"]["code"], INVALID_PARAMS);
+        assert_eq!(reply["error"]["data"]["status"], 422);
+    }
+
+    #[tokio::test]
+    async fn reject_invalid_requests() {
+        let server = server(true);
+
+        let reply = call(
+            &server,
+            json!({ "jsonrpc": "2.0", "method": "greet", "params": ["you"], "id": 1 })
+        )
+        .await;
+        assert_eq!(reply["error"]["code"], METHOD_NOT_FOUND);
+
+        let reply = call(&server, json!({ "method": "add", "id": 1 })).await;
+        assert_eq!(reply["error"]["code"], INVALID_REQUEST);
+        assert_eq!(reply["id"], Value::Null);
+
+        let reply = call(&server, json!([])).await;
+        assert_eq!(reply["error"]["code"], INVALID_REQUEST);
+
+        let calls = vec![json!({ "jsonrpc": "2.0", "method": "add" }); MAX_BATCH_CALLS + 1];
+        let reply = call(&server, Value::Array(calls)).await;
+        assert_eq!(reply["error"]["code"], INVALID_REQUEST);
+
+        let response = server
+            .request(Method::POST, "/rpc")
+            .text("{ not json")
+            .send()
+            .await;
+        let reply: Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(reply["error"]["code"], PARSE_ERROR);
+    }
+
+    #[tokio::test]
+    async fn batch_and_notify() {
+        let server = server(false);
+
+        let reply = call(
+            &server,
+            json!([
+                { "jsonrpc": "2.0", "method": "add", "params": [[1, 1]], "id": 1 },
+                { "jsonrpc": "2.0", "method": "add", "params": [[5]] },
+                { "jsonrpc": "2.0", "method": "missing", "id": 2 },
+                { "jsonrpc": "2.0", "method": "greet", "params": ["rpc"], "id": 3 }
+            ])
+        )
+        .await;
+        assert_eq!(
+            reply,
+            json!([
+                { "jsonrpc": "2.0", "result": 2, "id": 1 },
+                {
+                    "jsonrpc": "2.0",
+                    "error": { "code": METHOD_NOT_FOUND, "message": "Method not found" },
+                    "id": 2
+                },
+                { "jsonrpc": "2.0", "result": "hello rpc", "id": 3 }
+            ])
+        );
+
+        let response = server
+            .request(Method::POST, "/rpc")
+            .json(&json!({ "jsonrpc": "2.0", "method": "add", "params": [[1]] }))
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(response.body().is_empty());
+    }
+
+    #[test]
+    fn fill_path_params() {
+        let mut params = PathParams::default();
+        params.push(json!(["a/b", 7]));
+        assert_eq!(params.fill("/api/:name/:id").unwrap(), "/api/a%2Fb/7");
+
+        let mut params = PathParams::default();
+        params.push(json!({ "id": 7 }));
+        assert_eq!(params.fill("/api/:id").unwrap(), "/api/7");
+
+        let mut params = PathParams::default();
+        params.push(json!(["a b/c?d"]));
+        assert_eq!(params.fill("/files/*path").unwrap(), "/files/a%20b/c%3Fd");
+
+        assert!(PathParams::default().fill("/api/:id").is_err());
+    }
+}
@@ -2,20 +2,28 @@ use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::{format_ident, quote_spanned, ToTokens, TokenStreamExt};
 use syn::{
     self, parse_quote, parse_quote_spanned, punctuated::Punctuated, spanned::Spanned, token::Comma,
-    Attribute, Block, Expr, ExprMacro, FnArg, Generics, Ident, ItemConst, ItemFn, LitStr, PatType,
-    Receiver, ReturnType, Token, Type, WherePredicate
+    Attribute, Block, Expr, ExprMacro, FnArg, GenericArgument, Generics, Ident, ItemConst, ItemFn,
+    ItemImpl, ItemStruct, LitInt, LitStr, Pat, PatIdent, PatType, PathArguments, Receiver,
+    ReturnType, Token, Type, TypeParen, TypePath, TypeReference, WherePredicate
 };
 
-use crate::parse::ServerFnArgs;
+use crate::parse::{CacheArgs, CorsArgs, RateLimitArgs, ServerFnArgs};
 
 pub struct ServerFn {
     pub span: Span,
     pub route_const: ItemConst,
-    pub format_url_fn: ItemFn,
+    pub format_url_fns: Vec<ItemFn>,
     pub router_mod: Ident,
     pub router_fn: RouterFn,
+    /// Drops the server function's cached responses, when it has a `cache(..)` option.
+    pub invalidate_cache_fn: Option<ItemFn>,
     pub stateful_handler: StatefulHandler,
-    pub inner_handler: InnerHandler
+    pub guards: Guards,
+    pub inner_handler: InnerHandler,
+    pub direct_call: DirectCall,
+    pub test_calls: TestCalls,
+    pub client_calls: ClientCalls,
+    pub shared_route: Option<SharedRoute>
 }
 
 pub struct RouterFn {
@@ -24,7 +32,11 @@ pub struct RouterFn {
     pub gens: Generics,
     pub output: ReturnType,
     pub block: Block,
-    pub register_route: Expr
+    pub route_info: Expr,
+    /// The route's [Cache](crate::cache::Cache), emitted beside the router function as `CACHE`.
+    pub cache: Option<ItemConst>,
+    /// Submits the route to its state's inventory, or [None] for `shared` routes.
+    pub register_route: Option<Expr>
 }
 
 pub struct StatefulHandler {
@@ -40,6 +52,71 @@ pub struct InnerHandler {
     pub handler_fn: ItemFn
 }
 
+/// One extractor per `#[guard]`, taken first by the stateful handler so guards run before the
+/// server function's own extractors.
+pub struct Guards {
+    pub span: Span,
+    pub idents: Vec<Ident>,
+    pub structs: Vec<ItemStruct>,
+    pub impls: Vec<ItemImpl>,
+    /// Bounds on the router's `State` providing each guard's state.
+    pub predicates: Vec<WherePredicate>
+}
+
+/// Typed `#[cfg(test)]` helpers that call a server function through a
+/// [TestServer](crate::testing::TestServer), one per method.
+pub struct TestCalls {
+    pub span: Span,
+    pub call_fns: Vec<ItemFn>
+}
+
+/// Public entry points for a `shared` server function, registered by any
+/// [ServerState](crate::server_state::ServerState) that lists it in `#[server_state(shared = [..])]`.
+pub struct SharedRoute {
+    pub span: Span,
+    pub route_fn: ItemFn,
+    pub router_fn: ItemFn
+}
+
+/// Typed `web` clients that call a server function through the current
+/// [Transport](crate::client::Transport), one per method.
+pub struct ClientCalls {
+    pub span: Span,
+    pub call_fns: Vec<ItemFn>
+}
+
+/// Server-side entry points for calling a server function in process, without an http round trip.
+pub struct DirectCall {
+    pub span: Span,
+    pub call_fn: ItemFn,
+    pub oneshot_fn: ItemFn
+}
+
+/// Descriptive details about a server function, recorded in its registered route.
+pub struct RouteMeta<'a> {
+    pub fn_ident: &'a Ident,
+    pub embed: Option<&'a LitStr>,
+    /// The state to register with, when it isn't the package's `use_server_state!` alias.
+    pub state: Option<&'a syn::Path>,
+    pub shared: bool,
+    pub output: &'a ReturnType,
+    pub limits: RouteLimits<'a>,
+    /// Cross-origin access replacing the state's for this route.
+    pub cors: Option<&'a CorsArgs>,
+    pub csrf: bool,
+    pub cache: Option<&'a CacheArgs>,
+    pub rpc: bool
+}
+
+/// The limits set on a server function's route, layered outside its middlewares so rejected
+/// requests skip them.
+pub struct RouteLimits<'a> {
+    pub rate_limit: Option<&'a RateLimitArgs>,
+    pub timeout: Option<&'a LitStr>,
+    pub max_body: Option<&'a LitInt>,
+    pub concurrency: Option<&'a LitInt>
+}
+
 pub(crate) fn reciever_error(rec: &Receiver) -> syn::Error {
     syn::Error::new(
         rec.span(),
@@ -57,6 +134,120 @@ fn make_where_predicate(span: Span, arg_type: &Type) -> WherePredicate {
     }
 }
 
+/// Split a type into the extractor or response wrapper it's carried in and the carried type.
+///
+/// Returns the name of the matching [Codec](crate::server_router::Codec) variant and the
+/// carried type with the wrapper stripped off.
+fn split_codec(ty: &Type) -> (&'static str, &Type) {
+    let path = match ty {
+        Type::Reference(TypeReference { elem, .. }) | Type::Paren(TypeParen { elem, .. }) => {
+            return split_codec(elem);
+        }
+        Type::Path(TypePath { path, .. }) => path,
+        _ => return ("Other", ty)
+    };
+
+    let Some(last) = path.segments.last() else {
+        return ("Other", ty);
+    };
+
+    let inner = match &last.arguments {
+        PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None
+        }),
+        _ => None
+    };
+
+    match (last.ident.to_string().as_str(), inner) {
+        ("State", Some(inner)) => ("State", inner),
+        ("Principal", Some(inner)) => ("Principal", inner),
+        ("Path", Some(inner)) => ("Path", inner),
+        ("Query", Some(inner)) => ("Query", inner),
+        ("Json", Some(inner)) => ("Json", inner),
+        ("Form", Some(inner)) => ("Form", inner),
+        ("Result", Some(inner)) => split_codec(inner),
+        ("Html", _) => ("Html", ty),
+        ("String" | "str", _) => ("Text", ty),
+        _ => ("Other", ty)
+    }
+}
+
+/// A server function argument the generated test and client calls encode into the request.
+struct EncodedArg<'a> {
+    span: Span,
+    /// The helper's parameter, `arg<index>`.
+    ident: Ident,
+    /// The argument's name, as reported in client errors.
+    name: String,
+    /// The request builder method encoding the argument: `query`, `json`, `form` or `text`.
+    encode: Ident,
+    ty: &'a Type
+}
+
+/// The arguments of a server function its test and client calls encode, skipping state and
+/// principals, and path params which are passed to the url helper instead.
+///
+/// Returns [None] when an argument has no codec a typed call could encode, like a `HeaderMap` or
+/// a custom extractor, in which case the helpers are left out and the function is only callable
+/// over http.
+fn encoded_args<'a>(inputs: impl IntoIterator<Item = &'a PatType>) -> Option<Vec<EncodedArg<'a>>> {
+    let state_attr = state_attr();
+    let mut encoded = Vec::new();
+
+    for (i, next) in inputs.into_iter().enumerate() {
+        if next.attrs.contains(&state_attr) {
+            continue;
+        }
+
+        let (codec, ty) = split_codec(&next.ty);
+        let encode = match codec {
+            "State" | "Principal" | "Path" => continue,
+            "Query" => "query",
+            "Json" => "json",
+            "Form" => "form",
+            "Text" => "text",
+            _ => return None
+        };
+
+        encoded.push(EncodedArg {
+            span: next.span(),
+            ident: format_ident!("arg{i}"),
+            name: match next.pat.as_ref() {
+                Pat::Ident(PatIdent { ident, .. }) => ident.to_string(),
+                _ => format!("arg{i}")
+            },
+            encode: format_ident!("{encode}"),
+            ty
+        });
+    }
+
+    Some(encoded)
+}
+
+/// Classify a type with [split_codec], rendering the carried type as it's recorded in a route.
+fn classify_type(ty: &Type) -> (&'static str, String) {
+    match split_codec(ty) {
+        (codec @ ("Html" | "Text"), _) => (codec, "String".into()),
+        (codec, inner) => (codec, inner.to_token_stream().to_string().replace(' ', ""))
+    }
+}
+
+fn make_type_info(span: Span, ty: &Type, is_state: bool) -> TokenStream2 {
+    let (codec, ty) = match classify_type(ty) {
+        (_, ty) if is_state => ("State", ty),
+        classified => classified
+    };
+    let codec = Ident::new(codec, span);
+
+    quote_spanned! { span =>
+        ::server_fns::server_router::TypeInfo {
+            codec: ::server_fns::server_router::Codec::#codec,
+            ty: #ty
+        }
+    }
+}
+
 mod server_fn_impl {
     use convert_case::{Case, Casing};
     use itertools::Itertools;
@@ -70,76 +261,188 @@ mod server_fn_impl {
 
             let ServerFnArgs {
                 path,
-                method,
+                id,
+                hashed,
+                methods,
                 embed,
-                middlewares
+                state,
+                shared,
+                middlewares,
+                guards,
+                rate_limit,
+                timeout,
+                max_body,
+                concurrency,
+                cors,
+                csrf,
+                trace_args,
+                cache,
+                rpc
             } = fn_args;
 
-            let http_method = method
-                .map_or(Some("post".into()), |method| {
-                    Some(method.to_string().to_lowercase())
-                })
-                .map(|method| Ident::new(&method, Span::mixed_site()))
-                .unwrap();
-
-            let http_path = path.unwrap_or_else(|| {
-                LitStr::new(
-                    &format!("/api/{}", fn_ident.to_string().to_case(Case::Kebab)),
-                    fn_ident.span()
-                )
-            });
+            if shared && state.is_some() {
+                return Err(syn::Error::new(
+                    span,
+                    "Shared server functions are registered by each state that uses them, so \
+                     they can't name a `state`."
+                ));
+            }
 
-            let http_path_str = http_path.value();
-            let mut route_parts = http_path_str.split('/').collect_vec();
+            let http_methods = if methods.is_empty() {
+                vec![Ident::new("post", Span::mixed_site())]
+            } else {
+                methods
+                    .iter()
+                    .map(|method| Ident::new(&method.to_string(), Span::mixed_site()))
+                    .collect()
+            };
 
-            let format_url_params = route_parts
-                .iter()
-                .enumerate()
-                .filter_map(|(i, p)| {
-                    p.strip_prefix(':').map(|param| {
-                        let param = format_ident!("{param}");
-                        let pat_type = parse_quote_spanned! { http_path.span() =>
-                            #param: impl ::std::fmt::Display
-                        };
-                        (i, param, pat_type)
-                    })
-                })
-                .collect::<Vec<(_, _, PatType)>>();
+            if http_methods.len() > 1 && http_methods.iter().any(|method| method == "any") {
+                return Err(syn::Error::new(
+                    span,
+                    "The `any` method can't be combined with other methods."
+                ));
+            }
 
-            for (index, _, _) in &format_url_params {
-                route_parts[*index] = "{}";
+            if cache.is_some() && http_methods.iter().any(|method| method != "get") {
+                return Err(syn::Error::new(
+                    span,
+                    "Only `get` server functions can be cached."
+                ));
             }
 
-            let format_url_param_count = format_url_params.len();
+            if cache.is_some() && !guards.is_empty() {
+                return Err(syn::Error::new(
+                    span,
+                    "Server functions with guards can't be cached, since cached responses are \
+                     served without running the handler or its guards."
+                ));
+            }
 
-            let (format_url_param_names, format_url_params): (Vec<_>, Vec<_>) = format_url_params
-                .into_iter()
-                .map(|(_, n, p)| (n, p))
-                .unzip();
-            let format_url_fmt_str = route_parts.join("/");
+            let method_prefix = http_methods.iter().join("_");
 
             let router_fn_ident = format_ident!("{fn_ident}_router");
             let router_mod_ident = format_ident!("__{router_fn_ident}");
-            let stateful_fn_ident = format_ident!("{http_method}_{fn_ident}");
+            let stateful_fn_ident = format_ident!("{method_prefix}_{fn_ident}");
             let route_const_ident = format_ident!(
                 "{}",
                 stateful_fn_ident.to_string().to_case(Case::UpperSnake)
             );
-            let format_url_fn_ident = format_ident!("{stateful_fn_ident}_url");
 
-            let route_const = parse_quote_spanned! { fn_ident.span() =>
-                pub const #route_const_ident: &'static str = #http_path;
+            let hash_seed = match (&path, id, hashed) {
+                (Some(path), Some(_), _) | (Some(path), None, true) => {
+                    return Err(syn::Error::new(
+                        path.span(),
+                        "A server function with an explicit `path` can't hash it from an `id` or \
+                         `hashed = true`."
+                    ));
+                }
+                (None, Some(id), _) => Some(id.to_token_stream()),
+                (None, None, true) => {
+                    let fn_name = fn_ident.to_string();
+                    Some(quote_spanned! { fn_ident.span() =>
+                        ::std::concat!(::std::module_path!(), "::", #fn_name)
+                    })
+                }
+                (_, None, false) => None
             };
 
-            let format_url_fn: ItemFn = parse_quote_spanned! { http_path.span() =>
-                pub fn #format_url_fn_ident(#(#format_url_params),*) -> String {
-                    if #format_url_param_count > 0 {
-                        ::std::format!(#format_url_fmt_str, #(#format_url_param_names),*)
-                    } else {
-                        #format_url_fmt_str.into()
+            let hashed_path = hash_seed.is_some();
+
+            // Hashed paths are computed by a const fn, since the module path is only known to
+            // the compiler, so everything else refers to the route's const.
+            let (http_path, route_const, url_body, format_url_params): (Expr, ItemConst, _, _) =
+                match hash_seed {
+                    Some(seed) => {
+                        let route_const = parse_quote_spanned! { fn_ident.span() =>
+                            pub const #route_const_ident: &'static str = {
+                                const PATH: [u8; ::server_fns::stable_path::HASHED_PATH_LEN] =
+                                    ::server_fns::stable_path::hashed_path(#seed);
+                                ::server_fns::stable_path::as_str(&PATH)
+                            };
+                        };
+                        let url_body = quote_spanned! { fn_ident.span() =>
+                            #route_const_ident.into()
+                        };
+
+                        (
+                            parse_quote_spanned! { fn_ident.span() => #route_const_ident },
+                            route_const,
+                            url_body,
+                            Vec::new()
+                        )
                     }
-                }
-            };
+                    None => {
+                        let http_path = path.unwrap_or_else(|| {
+                            LitStr::new(
+                                &format!("/api/{}", fn_ident.to_string().to_case(Case::Kebab)),
+                                fn_ident.span()
+                            )
+                        });
+
+                        let http_path_str = http_path.value();
+                        let mut route_parts = http_path_str.split('/').collect_vec();
+
+                        let format_url_params = route_parts
+                            .iter()
+                            .enumerate()
+                            .filter_map(|(i, p)| {
+                                p.strip_prefix(':').map(|param| {
+                                    let param = format_ident!("{param}");
+                                    let pat_type = parse_quote_spanned! { http_path.span() =>
+                                        #param: impl ::std::fmt::Display
+                                    };
+                                    (i, param, pat_type)
+                                })
+                            })
+                            .collect::<Vec<(_, _, PatType)>>();
+
+                        for (index, _, _) in &format_url_params {
+                            route_parts[*index] = "{}";
+                        }
+
+                        let format_url_param_count = format_url_params.len();
+
+                        let (format_url_param_names, format_url_params): (Vec<_>, Vec<_>) =
+                            format_url_params
+                                .into_iter()
+                                .map(|(_, n, p)| (n, p))
+                                .unzip();
+                        let format_url_fmt_str = route_parts.join("/");
+
+                        let route_const = parse_quote_spanned! { fn_ident.span() =>
+                            pub const #route_const_ident: &'static str = #http_path;
+                        };
+                        let url_body = quote_spanned! { http_path.span() =>
+                            if #format_url_param_count > 0 {
+                                ::std::format!(#format_url_fmt_str, #(#format_url_param_names),*)
+                            } else {
+                                #format_url_fmt_str.into()
+                            }
+                        };
+
+                        (
+                            parse_quote_spanned! { http_path.span() => #http_path },
+                            route_const,
+                            url_body,
+                            format_url_params
+                        )
+                    }
+                };
+
+            // One url helper per method, so callers name the method they're requesting with.
+            let format_url_fns = http_methods
+                .iter()
+                .map(|method| {
+                    let format_url_fn_ident = format_ident!("{method}_{fn_ident}_url");
+
+                    parse_quote_spanned! { http_path.span() =>
+                        pub fn #format_url_fn_ident(#(#format_url_params),*) -> String {
+                            #url_body
+                        }
+                    }
+                })
+                .collect();
 
             let args_span = server_fn.sig.inputs.span();
 
@@ -153,34 +456,129 @@ mod server_fn_impl {
                 })
                 .collect::<Result<Vec<_>, _>>()?;
 
-            let router_fn = RouterFn::try_new(
+            if let Some(principal) = input_args
+                .iter()
+                .find(|arg| cache.is_some() && split_codec(&arg.ty).0 == "Principal")
+            {
+                return Err(syn::Error::new(
+                    principal.span(),
+                    "Server functions taking a `Principal` can't be cached, since one user's \
+                     response would be served to others."
+                ));
+            }
+
+            if let Some(path_arg) = input_args
+                .iter()
+                .find(|arg| hashed_path && split_codec(&arg.ty).0 == "Path")
+            {
+                return Err(syn::Error::new(
+                    path_arg.span(),
+                    "Server functions with a hashed path from an `id` or `hashed = true` have no \
+                     path params, so they can't take a `Path`."
+                ));
+            }
+
+            let guards = Guards::try_new(args_span, guards, state.as_ref(), shared)?;
+
+            let mut router_fn = RouterFn::try_new(
                 args_span,
                 router_fn_ident,
                 input_args.clone(),
                 http_path,
-                http_method,
-                &stateful_fn_ident,
-                middlewares
+                http_methods.clone(),
+                middlewares,
+                RouteMeta {
+                    fn_ident,
+                    embed: embed.as_ref(),
+                    state: state.as_ref(),
+                    shared,
+                    output: &server_fn.sig.output,
+                    limits: RouteLimits {
+                        rate_limit: rate_limit.as_ref(),
+                        timeout: timeout.as_ref(),
+                        max_body: max_body.as_ref(),
+                        concurrency: concurrency.as_ref()
+                    },
+                    cors: cors.as_ref(),
+                    csrf: csrf.unwrap_or(true),
+                    cache: cache.as_ref(),
+                    rpc
+                }
             )?;
 
+            // Built before the guards' bounds are added to the router, since direct calls skip
+            // the guards.
+            let direct_call = DirectCall::try_new(
+                args_span,
+                &server_fn,
+                input_args.clone(),
+                &router_mod_ident,
+                &router_fn,
+                &guards.predicates
+            )?;
+
+            router_fn
+                .gens
+                .make_where_clause()
+                .predicates
+                .extend(guards.predicates.iter().cloned());
+
+            let invalidate_cache_fn = router_fn.cache.is_some().then(|| {
+                let invalidate_fn_ident = format_ident!("invalidate_{fn_ident}_cache");
+
+                parse_quote_spanned! { span =>
+                    /// Drop every cached response of the server function.
+                    pub async fn #invalidate_fn_ident() {
+                        ::server_fns::cache::invalidate(#router_mod_ident::CACHE.scope).await
+                    }
+                }
+            });
+
             let stateful_handler = StatefulHandler::try_new(
                 args_span,
                 stateful_fn_ident,
-                input_args,
+                input_args.clone(),
                 &server_fn.sig.output,
-                fn_ident
+                fn_ident,
+                &guards.idents,
+                trace_args
             )?;
 
+            let test_calls = TestCalls::new(
+                args_span,
+                &server_fn,
+                &http_methods,
+                &format_url_params,
+                input_args.clone()
+            );
+
+            let client_calls = ClientCalls::new(
+                args_span,
+                &server_fn,
+                &http_methods,
+                &format_url_params,
+                input_args
+            );
+
+            let shared_route = shared
+                .then(|| SharedRoute::new(args_span, &server_fn, &router_mod_ident, &router_fn));
+
             let inner_handler = InnerHandler::try_new(embed, server_fn)?;
 
             Ok(Self {
                 span,
                 route_const,
-                format_url_fn,
+                format_url_fns,
                 router_mod: router_mod_ident,
                 router_fn,
+                invalidate_cache_fn,
                 stateful_handler,
-                inner_handler
+                guards,
+                inner_handler,
+                direct_call,
+                test_calls,
+                client_calls,
+                shared_route
             })
         }
     }
@@ -190,19 +588,29 @@ mod server_fn_impl {
             let Self {
                 span,
                 route_const,
-                format_url_fn,
+                format_url_fns,
                 router_mod,
                 router_fn,
+                invalidate_cache_fn,
                 stateful_handler,
-                inner_handler
+                guards,
+                inner_handler,
+                direct_call,
+                test_calls,
+                client_calls,
+                shared_route
             } = self;
+            let register_route = router_fn.register_route.iter();
+            let invalidate_cache_fn = invalidate_cache_fn.iter();
 
             tokens.append_all(quote_spanned! { *span =>
                 #[allow(unused, clippy::redundant_static_lifetimes)]
                 #route_const
 
-                #[allow(unused, clippy::useless_format)]
-                #format_url_fn
+                #(
+                    #[allow(unused, clippy::useless_format)]
+                    #format_url_fns
+                )*
 
                 #[cfg(feature = "server")]
                 mod #router_mod {
@@ -210,29 +618,69 @@ mod server_fn_impl {
 
                     #router_fn
                     #stateful_handler
+                    #guards
                 }
 
+                #(
+                    #[cfg(feature = "server")]
+                    #register_route
+                )*
+
+                #(
+                    #[cfg(feature = "server")]
+                    #[allow(unused)]
+                    #invalidate_cache_fn
+                )*
+
+                #shared_route
+
                 #[cfg(feature = "server")]
                 #inner_handler
+
+                #direct_call
+
+                #test_calls
+
+                #client_calls
             });
         }
     }
 }
 
 mod router_fn {
+    use itertools::Itertools;
+
     use super::*;
-    use crate::{current_package, make_server_state, parse::Middleware};
+    use crate::{
+        current_package, make_cors, make_server_state,
+        parse::{parse_duration_millis, CacheArgs, Middleware, RateLimitKeyArg}
+    };
 
     impl RouterFn {
         pub fn try_new<'a>(
             span: Span,
             ident: Ident,
             inputs: impl IntoIterator<Item = &'a PatType>,
-            http_path: LitStr,
-            http_method: Ident,
-            handler_ident: &Ident,
-            middlewares: Vec<Middleware>
+            http_path: Expr,
+            http_methods: Vec<Ident>,
+            middlewares: Vec<Middleware>,
+            meta: RouteMeta
         ) -> Result<Self, syn::Error> {
+            let RouteMeta {
+                fn_ident,
+                embed,
+                state,
+                shared,
+                output: fn_output,
+                limits,
+                cors,
+                csrf,
+                cache,
+                rpc
+            } = meta;
+            let handler_ident = format_ident!("{}_{fn_ident}", http_methods.iter().join("_"));
+            let router_mod = format_ident!("__{ident}");
+
             let state_attr = state_attr();
 
             let mut gens = Generics {
@@ -249,59 +697,256 @@ mod router_fn {
                 }
             };
 
-            for next in inputs {
-                if next.attrs.contains(&state_attr) {
+            let mut arg_infos = Vec::new();
+
+            for (i, next) in inputs.into_iter().enumerate() {
+                let is_state = next.attrs.contains(&state_attr);
+
+                if is_state {
                     let next_span = next.span();
                     let next_pred = make_where_predicate(next_span, &next.ty);
 
                     gens.make_where_clause().predicates.push(next_pred);
                 }
+
+                let name = match next.pat.as_ref() {
+                    Pat::Ident(PatIdent { ident, .. }) => ident.to_string(),
+                    _ => format!("arg{i}")
+                };
+                let ty = make_type_info(span, &next.ty, is_state);
+
+                arg_infos.push(quote_spanned! { span =>
+                    ::server_fns::server_router::ArgInfo { name: #name, ty: #ty }
+                });
             }
 
+            let output_info = match fn_output {
+                ReturnType::Default => make_type_info(span, &parse_quote!(()), false),
+                ReturnType::Type(_, ty) => make_type_info(span, ty, false)
+            };
+
             let output = parse_quote_spanned! { span =>
                 -> ::server_fns::axum::Router<State>
             };
 
+            // Layers wrap the ones applied before them, so middlewares are applied last to first,
+            // leaving the first one written outermost.
+            let layered = middlewares.iter().rev();
+
+            let fn_name = fn_ident.to_string();
+
+            let limit_layers = make_limit_layers(span, &fn_name, limits)?;
+
+            let cache = cache
+                .map(|CacheArgs { ttl, key }| {
+                    let ttl = parse_duration_millis(ttl)?;
+
+                    Ok::<ItemConst, syn::Error>(parse_quote_spanned! { span =>
+                        pub(super) const CACHE: ::server_fns::cache::Cache =
+                            ::server_fns::cache::Cache {
+                                scope: ::std::concat!(::std::module_path!(), "::", #fn_name),
+                                ttl: ::std::time::Duration::from_millis(#ttl),
+                                key: &[#(#key),*]
+                            };
+                    })
+                })
+                .transpose()?;
+
+            // The cache is innermost, so middlewares still run for requests it answers.
+            let cache_layer = cache.is_some().then(|| {
+                quote_spanned! { span =>
+                    let router = router.route_layer(
+                        ::server_fns::axum::middleware::from_fn_with_state(
+                            CACHE,
+                            ::server_fns::cache::cache
+                        )
+                    );
+                }
+            });
+
+            // The span is outermost, so it records requests every other layer rejects.
+            let trace_layer = cfg!(feature = "tracing-macros").then(|| {
+                quote_spanned! { span =>
+                    let router = router.route_layer(
+                        ::server_fns::axum::middleware::from_fn_with_state(
+                            ::server_fns::trace::TracedRoute { name: #fn_name, path: #http_path },
+                            ::server_fns::trace::layer
+                        )
+                    );
+                }
+            });
+
+            let (first_method, other_methods) = http_methods
+                .split_first()
+                .ok_or_else(|| syn::Error::new(span, "Server function has no http methods."))?;
+
             let block = parse_quote_spanned! { span =>
                 {
                     use ::server_fns::axum::Router;
                     use ::server_fns::axum::routing;
 
                     #[allow(clippy::let_and_return)]
-                    let router = Router::new()
-                        .route(#http_path, routing::#http_method(#handler_ident));
+                    let router = Router::new().route(
+                        #http_path,
+                        routing::#first_method(#handler_ident)
+                            #(.#other_methods(#handler_ident))*
+                    );
+
+                    #cache_layer
 
                     #(
-                        ::server_fns::layer_middleware!(#middlewares for router);
+                        ::server_fns::layer_middleware!(#layered for router);
                     )*
 
+                    #(#limit_layers)*
+
+                    #trace_layer
+
                     router
                 }
             };
 
-            let pkg_server_state = make_server_state(current_package(span)?);
-            let register_route = parse_quote_spanned! { span =>
-                ::server_fns::inventory::submit! {
-                    <
-                        #pkg_server_state
-                        as
-                        ::server_fns::server_state::ServerState
-                    >
-                    ::Router::register(#http_path, #ident)
+            let package = current_package(span)?;
+            let server_state = match state {
+                Some(state) => state.to_token_stream(),
+                None => make_server_state(&package).to_token_stream()
+            };
+            let methods = http_methods.iter().map(ToString::to_string);
+            let middleware_count = middlewares.len();
+            let embed = match embed {
+                Some(embed) => quote_spanned! { span => ::std::option::Option::Some(#embed) },
+                None => quote_spanned! { span => ::std::option::Option::None }
+            };
+            let cors = make_cors(span, cors)?;
+
+            let route_info: Expr = parse_quote_spanned! { span =>
+                ::server_fns::server_router::RouteInfo {
+                    path: #http_path,
+                    methods: &[#(#methods),*],
+                    name: #fn_name,
+                    module_path: ::std::module_path!(),
+                    package: #package,
+                    middleware_count: #middleware_count,
+                    embed: #embed,
+                    args: &[#(#arg_infos),*],
+                    output: #output_info,
+                    cors: #cors,
+                    csrf: #csrf,
+                    rpc: #rpc
                 }
             };
 
+            let register_route = (!shared).then(|| {
+                parse_quote_spanned! { span =>
+                    ::server_fns::inventory::submit! {
+                        <
+                            #server_state
+                            as
+                            ::server_fns::server_state::ServerState
+                        >
+                        ::Router::register(#route_info, #router_mod::#ident)
+                    }
+                }
+            });
+
             Ok(Self {
                 span,
                 ident,
                 gens,
                 output,
                 block,
+                route_info,
+                cache,
                 register_route
             })
         }
     }
 
+    /// Route layers enforcing `limits`, innermost first: the concurrency limit, body size limit,
+    /// timeout and then the rate limit.
+    fn make_limit_layers(
+        span: Span,
+        fn_name: &str,
+        limits: RouteLimits
+    ) -> Result<Vec<TokenStream2>, syn::Error> {
+        let RouteLimits {
+            rate_limit,
+            timeout,
+            max_body,
+            concurrency
+        } = limits;
+
+        let from_fn = |state: TokenStream2, middleware: TokenStream2| {
+            quote_spanned! { span =>
+                let router = router.route_layer(
+                    ::server_fns::axum::middleware::from_fn_with_state(#state, #middleware)
+                );
+            }
+        };
+
+        let mut layers = Vec::new();
+
+        if let Some(concurrency) = concurrency {
+            layers.push(from_fn(
+                quote_spanned! { span => ::server_fns::limit::Permits::new(#concurrency) },
+                quote_spanned! { span => ::server_fns::limit::concurrency }
+            ));
+        }
+
+        if let Some(max_body) = max_body {
+            layers.push(quote_spanned! { span =>
+                let router = router.route_layer(
+                    ::server_fns::axum::extract::DefaultBodyLimit::max(#max_body)
+                );
+            });
+            layers.push(from_fn(
+                quote_spanned! { span => #max_body },
+                quote_spanned! { span => ::server_fns::limit::max_body }
+            ));
+        }
+
+        // Outside the body size limit, so the timeout also bounds reading a slow upload.
+        if let Some(timeout) = timeout {
+            let millis = parse_duration_millis(timeout)?;
+
+            layers.push(from_fn(
+                quote_spanned! { span => ::std::time::Duration::from_millis(#millis) },
+                quote_spanned! { span => ::server_fns::limit::timeout }
+            ));
+        }
+
+        if let Some(RateLimitArgs { requests, per, key }) = rate_limit {
+            let per = parse_duration_millis(per)?;
+            let key = match key {
+                RateLimitKeyArg::Ip => quote_spanned! { span =>
+                    ::server_fns::rate_limit::RateLimitKey::Ip
+                },
+                RateLimitKeyArg::Header(name) => quote_spanned! { span =>
+                    ::server_fns::rate_limit::RateLimitKey::Header(#name)
+                },
+                RateLimitKeyArg::Custom(path) => quote_spanned! { span =>
+                    ::server_fns::rate_limit::RateLimitKey::Custom(#path)
+                }
+            };
+
+            layers.push(from_fn(
+                quote_spanned! { span =>
+                    ::server_fns::rate_limit::RateLimit {
+                        scope: ::std::concat!(::std::module_path!(), "::", #fn_name),
+                        quota: ::server_fns::rate_limit::Quota {
+                            requests: #requests,
+                            per: ::std::time::Duration::from_millis(#per)
+                        },
+                        key: #key
+                    }
+                },
+                quote_spanned! { span => ::server_fns::rate_limit::limit }
+            ));
+        }
+
+        Ok(layers)
+    }
+
     impl ToTokens for RouterFn {
         fn to_tokens(&self, tokens: &mut TokenStream2) {
             let Self {
@@ -310,17 +955,18 @@ mod router_fn {
                 gens,
                 output,
                 block,
-                register_route
+                cache,
+                ..
             } = self;
 
             let (_, gen_types, where_clause) = gens.split_for_impl();
 
             tokens.append_all(quote_spanned! { *span =>
-                fn #ident #gen_types () #output
+                pub(super) fn #ident #gen_types () #output
                 #where_clause
                 #block
 
-                #register_route
+                #cache
             });
         }
     }
@@ -335,17 +981,28 @@ mod stateful_handler {
             ident: Ident,
             inputs: impl IntoIterator<Item = &'a PatType>,
             output: &ReturnType,
-            handler_fn_ident: &Ident
+            handler_fn_ident: &Ident,
+            guards: &[Ident],
+            trace_args: bool
         ) -> Result<Self, syn::Error> {
             #[derive(Default)]
             struct BuildArgs {
                 args: Punctuated<FnArg, Comma>,
-                handler_args: Punctuated<Expr, Comma>
+                handler_args: Punctuated<Expr, Comma>,
+                traced_args: Vec<TokenStream2>
             }
 
             let state_attr = state_attr();
             let mut build_args = BuildArgs::default();
 
+            for (i, guard) in guards.iter().enumerate() {
+                let guard_ident = format_ident!("guard{i}");
+
+                build_args
+                    .args
+                    .push(parse_quote_spanned! { span => #guard_ident: #guard });
+            }
+
             let inputs = inputs.into_iter().enumerate();
 
             for (i, next) in inputs {
@@ -355,10 +1012,23 @@ mod stateful_handler {
 
                 let BuildArgs {
                     ref mut args,
-                    ref mut handler_args
+                    ref mut handler_args,
+                    ref mut traced_args
                 } = build_args;
+                let is_state = next.attrs.contains(&state_attr);
+
+                if trace_args && !is_state {
+                    let name = match next.pat.as_ref() {
+                        Pat::Ident(PatIdent { ident, .. }) => ident.to_string(),
+                        _ => format!("arg{i}")
+                    };
+
+                    traced_args.push(quote_spanned! { next_span =>
+                        (#name, &#arg_ident as &dyn ::std::fmt::Debug)
+                    });
+                }
 
-                args.push(if next.attrs.contains(&state_attr) {
+                args.push(if is_state {
                     parse_quote_spanned! { next_span =>
                         ::server_fns::axum::extract::State(#arg_ident):
                             ::server_fns::axum::extract::State<#next_type>
@@ -370,9 +1040,20 @@ mod stateful_handler {
                 handler_args.push(parse_quote_spanned! { next_span => #arg_ident });
             }
 
-            let BuildArgs { args, handler_args } = build_args;
+            let BuildArgs {
+                args,
+                handler_args,
+                traced_args
+            } = build_args;
+
+            let record_args = (trace_args && cfg!(feature = "tracing-macros")).then(|| {
+                quote_spanned! { span =>
+                    ::server_fns::trace::record_args(&[#(#traced_args),*]);
+                }
+            });
 
             let block = parse_quote_spanned! { span => {
+                #record_args
                 #handler_fn_ident(#handler_args).await
             }};
 
@@ -403,6 +1084,509 @@ mod stateful_handler {
     }
 }
 
+mod guards {
+    use super::*;
+    use crate::{current_package, make_server_state, parse::Guard};
+
+    impl Guards {
+        pub fn try_new(
+            span: Span,
+            guards: Vec<Guard>,
+            fn_state: Option<&syn::Path>,
+            shared: bool
+        ) -> Result<Self, syn::Error> {
+            let mut this = Self {
+                span,
+                idents: Vec::new(),
+                structs: Vec::new(),
+                impls: Vec::new(),
+                predicates: Vec::new()
+            };
+
+            for (i, Guard { expr, state }) in guards.into_iter().enumerate() {
+                let guard_span = expr.span();
+
+                // Guards are given the server function's state unless they name their own.
+                let state = match (state, fn_state) {
+                    (Some(state), _) => state.to_token_stream(),
+                    (None, _) if shared => {
+                        return Err(syn::Error::new(
+                            guard_span,
+                            "Guards on shared server functions must name the `state` they're \
+                             given."
+                        ));
+                    }
+                    (None, Some(state)) => state.to_token_stream(),
+                    (None, None) => make_server_state(current_package(span)?).to_token_stream()
+                };
+                let state: Type = parse_quote_spanned! { guard_span => #state };
+
+                let ident = format_ident!("__Guard{i}");
+
+                this.structs.push(parse_quote_spanned! { guard_span =>
+                    pub(super) struct #ident;
+                });
+
+                this.impls.push(parse_quote_spanned! { guard_span =>
+                    #[::server_fns::axum::async_trait]
+                    impl<S> ::server_fns::axum::extract::FromRequestParts<S> for #ident
+                    where
+                        S: ::std::marker::Send + ::std::marker::Sync,
+                        #state: ::server_fns::axum::extract::FromRef<S>
+                    {
+                        type Rejection = ::server_fns::axum::response::Response;
+
+                        async fn from_request_parts(
+                            parts: &mut ::server_fns::axum::http::request::Parts,
+                            state: &S
+                        ) -> ::std::result::Result<Self, Self::Rejection> {
+                            let state =
+                                <#state as ::server_fns::axum::extract::FromRef<S>>::from_ref(state);
+
+                            ::server_fns::guard::check(#expr, parts, state)
+                                .await
+                                .map(|()| Self)
+                        }
+                    }
+                });
+
+                this.predicates
+                    .push(make_where_predicate(guard_span, &state));
+                this.idents.push(ident);
+            }
+
+            Ok(this)
+        }
+    }
+
+    impl ToTokens for Guards {
+        fn to_tokens(&self, tokens: &mut TokenStream2) {
+            let Self {
+                span,
+                structs,
+                impls,
+                ..
+            } = self;
+
+            tokens.append_all(quote_spanned! { *span =>
+                #(#structs)*
+                #(#impls)*
+            });
+        }
+    }
+}
+
+mod direct_call {
+    use super::*;
+
+    impl DirectCall {
+        pub fn try_new<'a>(
+            span: Span,
+            server_fn: &ItemFn,
+            inputs: impl IntoIterator<Item = &'a PatType>,
+            router_mod: &Ident,
+            router_fn: &RouterFn,
+            guard_predicates: &[WherePredicate]
+        ) -> Result<Self, syn::Error> {
+            let state_attr = state_attr();
+
+            let ItemFn { vis, sig, .. } = server_fn;
+            let fn_ident = &sig.ident;
+            let output = &sig.output;
+            let router_fn_ident = &router_fn.ident;
+            let (impl_gens, _, where_clause) = router_fn.gens.split_for_impl();
+
+            // The oneshot call goes through the route, so it needs the states of its guards too.
+            let mut oneshot_gens = router_fn.gens.clone();
+            oneshot_gens
+                .make_where_clause()
+                .predicates
+                .extend(guard_predicates.iter().cloned());
+            let (oneshot_impl_gens, _, oneshot_where_clause) = oneshot_gens.split_for_impl();
+
+            let mut args = Punctuated::<FnArg, Comma>::new();
+            let mut handler_args = Punctuated::<Expr, Comma>::new();
+
+            for (i, next) in inputs.into_iter().enumerate() {
+                let next_span = next.span();
+                let next_type = &next.ty;
+                let arg_ident = format_ident!("arg{i}");
+
+                if next.attrs.contains(&state_attr) {
+                    handler_args.push(parse_quote_spanned! { next_span =>
+                        <#next_type as ::server_fns::axum::extract::FromRef<State>>::from_ref(state)
+                    });
+                } else {
+                    args.push(parse_quote_spanned! { next_span => #arg_ident: #next_type });
+                    handler_args.push(parse_quote_spanned! { next_span => #arg_ident });
+                }
+            }
+
+            let call_ident = format_ident!("{fn_ident}_with_state");
+            let call_fn = parse_quote_spanned! { span =>
+                /// Call the server function in process, resolving its `#[state]` arguments from
+                /// `state`.
+                ///
+                /// This bypasses the route: its middlewares, limits and `#[guard]`s are not run,
+                /// so callers must check access themselves. Use the `_oneshot` call to go through
+                /// them.
+                #vis async fn #call_ident #impl_gens (state: &State, #args) #output
+                #where_clause
+                {
+                    #fn_ident(#handler_args).await
+                }
+            };
+
+            let oneshot_ident = format_ident!("{fn_ident}_oneshot");
+            let oneshot_fn = parse_quote_spanned! { span =>
+                /// Send `request` through the server function's route, including its middlewares,
+                /// in process.
+                #vis async fn #oneshot_ident #oneshot_impl_gens (
+                    state: State,
+                    request: ::server_fns::axum::extract::Request
+                ) -> ::server_fns::axum::response::Response
+                #oneshot_where_clause
+                {
+                    let router: ::server_fns::axum::Router =
+                        #router_mod::#router_fn_ident::<State>().with_state(state);
+
+                    match ::server_fns::tower::ServiceExt::oneshot(router, request).await {
+                        Ok(response) => response,
+                        Err(infallible) => match infallible {}
+                    }
+                }
+            };
+
+            Ok(Self {
+                span,
+                call_fn,
+                oneshot_fn
+            })
+        }
+    }
+
+    impl ToTokens for DirectCall {
+        fn to_tokens(&self, tokens: &mut TokenStream2) {
+            let Self {
+                span,
+                call_fn,
+                oneshot_fn
+            } = self;
+
+            tokens.append_all(quote_spanned! { *span =>
+                #[cfg(feature = "server")]
+                #[allow(unused)]
+                #call_fn
+
+                #[cfg(feature = "server")]
+                #[allow(unused)]
+                #oneshot_fn
+            });
+        }
+    }
+}
+
+mod test_calls {
+    use super::*;
+
+    impl TestCalls {
+        pub fn new<'a>(
+            span: Span,
+            server_fn: &ItemFn,
+            http_methods: &[Ident],
+            url_params: &[PatType],
+            inputs: impl IntoIterator<Item = &'a PatType>
+        ) -> Self {
+            let ItemFn { vis, sig, .. } = server_fn;
+            let fn_ident = &sig.ident;
+
+            let Some(encoded_args) = encoded_args(inputs) else {
+                return Self {
+                    span,
+                    call_fns: Vec::new()
+                };
+            };
+            let (args, encode_args): (Vec<_>, Vec<_>) = encoded_args
+                .into_iter()
+                .map(
+                    |EncodedArg {
+                         span,
+                         ident,
+                         encode,
+                         ty,
+                         ..
+                     }| {
+                        let encode_arg = if encode == "text" {
+                            quote_spanned! { span => .text(#ident) }
+                        } else {
+                            quote_spanned! { span => .#encode(&#ident) }
+                        };
+
+                        (quote_spanned! { span => #ident: #ty }, encode_arg)
+                    }
+                )
+                .unzip();
+
+            let output = match &sig.output {
+                ReturnType::Default => ("Other", None),
+                ReturnType::Type(_, ty) => {
+                    let (codec, inner) = split_codec(ty);
+                    (codec, Some(inner))
+                }
+            };
+
+            let (value_type, decode) = match output {
+                ("Json", Some(inner)) => (
+                    inner.to_token_stream(),
+                    quote_spanned! { span => decode_json::<#inner> }
+                ),
+                ("Html" | "Text", _) => (
+                    quote_spanned! { span => ::std::string::String },
+                    quote_spanned! { span => decode_text }
+                ),
+                _ => (
+                    quote_spanned! { span => ::server_fns::axum::body::Bytes },
+                    quote_spanned! { span => decode_bytes }
+                )
+            };
+
+            let url_param_names = url_params.iter().map(|param| &param.pat);
+
+            let call_fns = http_methods
+                .iter()
+                .map(|method| {
+                    let request_method = match method.to_string().as_str() {
+                        "any" => format_ident!("POST"),
+                        method => format_ident!("{}", method.to_uppercase())
+                    };
+                    let url_fn_ident = format_ident!("{method}_{fn_ident}_url");
+                    let test_fn_ident = format_ident!("{method}_{fn_ident}_test");
+                    let url_param_names = url_param_names.clone();
+
+                    parse_quote_spanned! { span =>
+                        #vis async fn #test_fn_ident(
+                            server: &::server_fns::testing::TestServer,
+                            #(#url_params,)*
+                            #(#args),*
+                        ) -> ::server_fns::testing::TestResponse<#value_type> {
+                            server
+                                .request(
+                                    ::server_fns::axum::http::Method::#request_method,
+                                    #url_fn_ident(#(#url_param_names),*)
+                                )
+                                #(#encode_args)*
+                                .send_decoded(::server_fns::testing::#decode)
+                                .await
+                        }
+                    }
+                })
+                .collect();
+
+            Self { span, call_fns }
+        }
+    }
+
+    impl ToTokens for TestCalls {
+        fn to_tokens(&self, tokens: &mut TokenStream2) {
+            let Self { span, call_fns } = self;
+
+            tokens.append_all(quote_spanned! { *span =>
+                #(
+                    #[cfg(all(test, feature = "server"))]
+                    #[allow(unused)]
+                    #call_fns
+                )*
+            });
+        }
+    }
+}
+
+mod shared_route {
+    use super::*;
+
+    impl SharedRoute {
+        pub fn new(
+            span: Span,
+            server_fn: &ItemFn,
+            router_mod: &Ident,
+            router_fn: &RouterFn
+        ) -> Self {
+            let ItemFn { vis, sig, .. } = server_fn;
+            let fn_ident = &sig.ident;
+            let RouterFn {
+                ident: router_fn_ident,
+                gens,
+                output,
+                route_info,
+                ..
+            } = router_fn;
+            let (impl_gens, _, where_clause) = gens.split_for_impl();
+
+            let route_fn_ident = format_ident!("{fn_ident}_route");
+            let route_fn = parse_quote_spanned! { span =>
+                /// The metadata of the shared route, registered by states that use it.
+                #vis const fn #route_fn_ident() -> ::server_fns::server_router::RouteInfo {
+                    #route_info
+                }
+            };
+
+            let shared_router_ident = format_ident!("{fn_ident}_router");
+            let router_fn = parse_quote_spanned! { span =>
+                /// The shared route's router, for any `State` providing its `#[state]` arguments
+                /// through [FromRef](::server_fns::axum::extract::FromRef).
+                #vis fn #shared_router_ident #impl_gens () #output
+                #where_clause
+                {
+                    #router_mod::#router_fn_ident::<State>()
+                }
+            };
+
+            Self {
+                span,
+                route_fn,
+                router_fn
+            }
+        }
+    }
+
+    impl ToTokens for SharedRoute {
+        fn to_tokens(&self, tokens: &mut TokenStream2) {
+            let Self {
+                span,
+                route_fn,
+                router_fn
+            } = self;
+
+            tokens.append_all(quote_spanned! { *span =>
+                #[cfg(feature = "server")]
+                #route_fn
+
+                #[cfg(feature = "server")]
+                #router_fn
+            });
+        }
+    }
+}
+
+mod client_calls {
+    use super::*;
+
+    impl ClientCalls {
+        pub fn new<'a>(
+            span: Span,
+            server_fn: &ItemFn,
+            http_methods: &[Ident],
+            url_params: &[PatType],
+            inputs: impl IntoIterator<Item = &'a PatType>
+        ) -> Self {
+            let ItemFn { vis, sig, .. } = server_fn;
+            let fn_ident = &sig.ident;
+
+            let Some(encoded_args) = encoded_args(inputs) else {
+                return Self {
+                    span,
+                    call_fns: Vec::new()
+                };
+            };
+            let (args, encode_args): (Vec<_>, Vec<_>) = encoded_args
+                .into_iter()
+                .map(
+                    |EncodedArg {
+                         span,
+                         ident,
+                         name,
+                         encode,
+                         ty
+                     }| {
+                        let encode_arg = if encode == "text" {
+                            quote_spanned! { span => .text(#name, #ident) }
+                        } else {
+                            quote_spanned! { span => .#encode(#name, &#ident) }
+                        };
+
+                        (quote_spanned! { span => #ident: #ty }, encode_arg)
+                    }
+                )
+                .unzip();
+
+            let (value_type, decode) = match &sig.output {
+                ReturnType::Type(_, ty) => match split_codec(ty) {
+                    ("Json", inner) => (
+                        inner.to_token_stream(),
+                        quote_spanned! { span => json::<#inner> }
+                    ),
+                    ("Html" | "Text", _) => (
+                        quote_spanned! { span => ::std::string::String },
+                        quote_spanned! { span => text }
+                    ),
+                    _ => (
+                        quote_spanned! { span => ::std::vec::Vec<u8> },
+                        quote_spanned! { span => bytes }
+                    )
+                },
+                ReturnType::Default => (
+                    quote_spanned! { span => ::std::vec::Vec<u8> },
+                    quote_spanned! { span => bytes }
+                )
+            };
+
+            let url_param_names = url_params
+                .iter()
+                .map(|param| &param.pat)
+                .collect::<Vec<_>>();
+            let url_param_strs = url_param_names
+                .iter()
+                .map(|param| param.to_token_stream().to_string());
+
+            let call_fns = http_methods
+                .iter()
+                .map(|method| {
+                    let request_method = match method.to_string().as_str() {
+                        "any" => "POST".to_owned(),
+                        method => method.to_uppercase()
+                    };
+                    let url_fn_ident = format_ident!("{method}_{fn_ident}_url");
+                    let fetch_fn_ident = format_ident!("{method}_{fn_ident}_fetch");
+                    let url_param_strs = url_param_strs.clone();
+
+                    parse_quote_spanned! { span =>
+                        #vis async fn #fetch_fn_ident(
+                            #(#url_params,)*
+                            #(#args),*
+                        ) -> ::std::result::Result<#value_type, ::server_fns::client::ServerFnError> {
+                            ::server_fns::client::ClientRequest::new(
+                                #request_method,
+                                #url_fn_ident(#(&#url_param_names),*)
+                            )
+                            #(.param(#url_param_strs, &#url_param_names))*
+                            #(#encode_args)*
+                            .send()
+                            .await?
+                            .#decode()
+                        }
+                    }
+                })
+                .collect();
+
+            Self { span, call_fns }
+        }
+    }
+
+    impl ToTokens for ClientCalls {
+        fn to_tokens(&self, tokens: &mut TokenStream2) {
+            let Self { span, call_fns } = self;
+
+            tokens.append_all(quote_spanned! { *span =>
+                #(
+                    #[cfg(feature = "web")]
+                    #[allow(unused)]
+                    #call_fns
+                )*
+            });
+        }
+    }
+}
+
 mod inner_handler {
     use std::{
         env,
@@ -546,3 +1730,233 @@ mod inner_handler {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layer_router_outermost_first() {
+        let args: ServerFnArgs = parse_quote!(middlewares = [outer, before_routing(inner)]);
+        let server_fn: ItemFn = parse_quote!(
+            async fn handler() {}
+        );
+
+        let ServerFn { router_fn, .. } = ServerFn::try_new(args, server_fn).unwrap();
+        let block = router_fn.block.to_token_stream().to_string();
+
+        let inner = block.find("before_routing (inner)").unwrap();
+        let outer = block.find("after_routing (outer)").unwrap();
+        assert!(
+            inner < outer,
+            "the last middleware written must be applied first"
+        );
+    }
+
+    #[test]
+    fn limits_outside_middlewares() {
+        let args: ServerFnArgs = parse_quote!(
+            middlewares = [traced],
+            timeout = "1m",
+            max_body = 64,
+            concurrency = 2
+        );
+        let server_fn: ItemFn = parse_quote!(
+            async fn handler() {}
+        );
+
+        let ServerFn { router_fn, .. } = ServerFn::try_new(args, server_fn).unwrap();
+        let block = router_fn.block.to_token_stream().to_string();
+
+        let position = |needle: &str| block.find(needle).unwrap();
+        let middleware = position("after_routing (traced)");
+        let timeout = position(":: server_fns :: limit :: timeout");
+        let concurrency = position(":: server_fns :: limit :: concurrency");
+        let max_body = position(":: server_fns :: limit :: max_body");
+
+        assert!(middleware < concurrency && concurrency < max_body && max_body < timeout);
+        assert!(block.contains("from_millis (60000u64)"));
+        assert!(block.contains("DefaultBodyLimit :: max (64)"));
+    }
+
+    #[cfg(feature = "tracing-macros")]
+    #[test]
+    fn trace_outside_limits() {
+        let args: ServerFnArgs = parse_quote!(timeout = "1s", trace_args = true);
+        let server_fn: ItemFn = parse_quote!(
+            async fn handler(#[state] state: AppState, id: String) {}
+        );
+
+        let ServerFn {
+            router_fn,
+            stateful_handler,
+            ..
+        } = ServerFn::try_new(args, server_fn).unwrap();
+        let block = router_fn.block.to_token_stream().to_string();
+        let handler = stateful_handler.to_token_stream().to_string();
+
+        let timeout = block.find(":: server_fns :: limit :: timeout").unwrap();
+        let trace = block.find(":: server_fns :: trace :: layer").unwrap();
+        assert!(timeout < trace);
+        assert!(handler.contains("record_args (& [(\"id\" , & arg1"));
+        assert!(!handler.contains("\"state\""));
+    }
+
+    #[test]
+    fn rate_limit_outside_middlewares() {
+        let args: ServerFnArgs =
+            parse_quote!(middlewares = [traced], rate_limit(requests = 3, per = "2s"));
+        let server_fn: ItemFn = parse_quote!(
+            async fn handler() {}
+        );
+
+        let ServerFn { router_fn, .. } = ServerFn::try_new(args, server_fn).unwrap();
+        let block = router_fn.block.to_token_stream().to_string();
+
+        let middleware = block.find("after_routing (traced)").unwrap();
+        let rate_limit = block.find(":: server_fns :: rate_limit :: limit").unwrap();
+        assert!(middleware < rate_limit);
+        assert!(block.contains("requests : 3"));
+        assert!(block.contains("from_millis (2000u64)"));
+    }
+
+    #[test]
+    fn cache_inside_middlewares() {
+        let args: ServerFnArgs = parse_quote!(
+            method = "get",
+            middlewares = [traced],
+            cache(ttl = "30s", key = ["x-tenant"])
+        );
+        let server_fn: ItemFn = parse_quote!(
+            async fn handler() {}
+        );
+
+        let ServerFn {
+            router_fn,
+            invalidate_cache_fn,
+            ..
+        } = ServerFn::try_new(args, server_fn).unwrap();
+        let block = router_fn.block.to_token_stream().to_string();
+        let cache = router_fn.cache.to_token_stream().to_string();
+
+        let cache_layer = block.find(":: server_fns :: cache :: cache").unwrap();
+        let middleware = block.find("after_routing (traced)").unwrap();
+        assert!(cache_layer < middleware);
+        assert!(cache.contains("from_millis (30000u64)"));
+        assert!(cache.contains("key : & [\"x-tenant\"]"));
+        assert_eq!(
+            invalidate_cache_fn.unwrap().sig.ident,
+            "invalidate_handler_cache"
+        );
+
+        let server_fn: ItemFn = parse_quote!(
+            async fn handler() {}
+        );
+        let args: ServerFnArgs = parse_quote!(method = "post", cache(ttl = "30s"));
+        assert!(ServerFn::try_new(args, server_fn.clone()).is_err());
+        let args: ServerFnArgs = parse_quote!(
+            method = "get",
+            guards = [guard(signed_in)],
+            cache(ttl = "30s")
+        );
+        assert!(ServerFn::try_new(args, server_fn).is_err());
+
+        let server_fn: ItemFn = parse_quote!(
+            async fn handler(user: Principal<User>) {}
+        );
+        let args: ServerFnArgs = parse_quote!(method = "get", cache(ttl = "30s"));
+        let err = ServerFn::try_new(args, server_fn).err().unwrap();
+        assert!(err.to_string().contains("`Principal`"));
+    }
+
+    #[test]
+    fn skip_guards_of_direct_calls() {
+        let server_fn: ItemFn = parse_quote!(
+            async fn handler() {}
+        );
+        let args: ServerFnArgs = parse_quote!(guards = [guard(require_user, state = Sessions)]);
+
+        let ServerFn { direct_call, .. } = ServerFn::try_new(args, server_fn).unwrap();
+        let call_fn = direct_call.call_fn.to_token_stream().to_string();
+        let oneshot_fn = direct_call.oneshot_fn.to_token_stream().to_string();
+
+        assert!(!call_fn.contains("Sessions"));
+        assert!(
+            oneshot_fn.contains("Sessions : :: server_fns :: axum :: extract :: FromRef < State >")
+        );
+    }
+
+    #[test]
+    fn skip_calls_for_args_without_codec() {
+        let server_fn: ItemFn = parse_quote!(
+            async fn handler(Json(name): Json<String>, headers: HeaderMap) {}
+        );
+        let args: ServerFnArgs = parse_quote!(method = "post");
+
+        let ServerFn {
+            route_const,
+            test_calls,
+            client_calls,
+            direct_call,
+            ..
+        } = ServerFn::try_new(args, server_fn.clone()).unwrap();
+        assert_eq!(route_const.ident, "POST_HANDLER");
+        assert!(test_calls.call_fns.is_empty());
+        assert!(client_calls.call_fns.is_empty());
+        assert_eq!(direct_call.call_fn.sig.ident, "handler_with_state");
+
+        let server_fn: ItemFn = parse_quote!(
+            async fn handler(Json(name): Json<String>) {}
+        );
+        let args: ServerFnArgs = parse_quote!(method = "post");
+        let ServerFn {
+            test_calls,
+            client_calls,
+            ..
+        } = ServerFn::try_new(args, server_fn).unwrap();
+        assert_eq!(test_calls.call_fns[0].sig.ident, "post_handler_test");
+        assert_eq!(client_calls.call_fns[0].sig.ident, "post_handler_fetch");
+    }
+
+    #[test]
+    fn hash_default_paths() {
+        let server_fn: ItemFn = parse_quote!(
+            async fn handler() {}
+        );
+
+        let args: ServerFnArgs = parse_quote!(method = "get", id = "billing.charge");
+        let ServerFn {
+            route_const,
+            format_url_fns,
+            router_fn,
+            ..
+        } = ServerFn::try_new(args, server_fn.clone()).unwrap();
+        let route_const = route_const.to_token_stream().to_string();
+        let block = router_fn.block.to_token_stream().to_string();
+
+        assert!(route_const.contains("hashed_path (\"billing.charge\")"));
+        assert!(block.contains(". route (GET_HANDLER ,"));
+        assert!(format_url_fns[0].sig.inputs.is_empty());
+
+        let args: ServerFnArgs = parse_quote!(hashed = true);
+        let ServerFn { route_const, .. } = ServerFn::try_new(args, server_fn.clone()).unwrap();
+        assert!(route_const
+            .to_token_stream()
+            .to_string()
+            .contains(":: std :: module_path ! () , \"::\" , \"handler\""));
+
+        let args: ServerFnArgs = parse_quote!(path = "/api/handler", hashed = true);
+        assert!(ServerFn::try_new(args, server_fn).is_err());
+
+        let server_fn: ItemFn = parse_quote!(
+            async fn handler(Path(id): Path<u64>) {}
+        );
+        for args in [
+            parse_quote!(id = "billing.charge"),
+            parse_quote!(hashed = true)
+        ] {
+            let err = ServerFn::try_new(args, server_fn.clone()).err().unwrap();
+            assert!(err.to_string().contains("can't take a `Path`"));
+        }
+    }
+}
@@ -1,24 +1,248 @@
 #![cfg(feature = "server")]
 
+use std::{convert::Infallible, fmt, marker::PhantomData};
+
 use inventory::Collect;
 
-use crate::server_state::ServerState;
+use crate::{cors::Cors, path::path_matches, server_state::ServerState};
 
 pub type RouterFn<S> = fn() -> axum::Router<S>;
 
+pub type MiddlewareFn<S> = fn(axum::Router<S>) -> axum::Router<S>;
+
+/// Metadata describing a route registered through a [ServerRouter].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteInfo {
+    /// The axum path the route is mounted at.
+    pub path: &'static str,
+    /// The lowercase http methods the route responds to.
+    pub methods: &'static [&'static str],
+    /// The name of the annotated server function.
+    pub name: &'static str,
+    /// The module path the server function was declared in.
+    pub module_path: &'static str,
+    /// The package (crate) the server function was declared in.
+    pub package: &'static str,
+    /// The number of middlewares layered onto the route.
+    pub middleware_count: usize,
+    /// The `embed` base path given to the server attribute, if any.
+    pub embed: Option<&'static str>,
+    /// The arguments of the server function, in declaration order.
+    pub args: &'static [ArgInfo],
+    /// The return type of the server function.
+    pub output: TypeInfo,
+    /// The route's own CORS configuration, replacing its state's.
+    pub cors: Option<Cors>,
+    /// Whether requests with unsafe methods are checked for cross-site forgery.
+    pub csrf: bool,
+    /// Whether the server function was tagged `rpc = true`, for JSON-RPC endpoints exposing only
+    /// tagged functions.
+    pub rpc: bool
+}
+
+/// How a value is carried by a request or response, inferred from its extractor or response type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// Provided by the router's state through `#[state]` or [axum::extract::State].
+    State,
+    /// The caller authenticated by a `#[guard]`, through [Principal](crate::guard::Principal).
+    Principal,
+    /// Parsed from the route's path parameters.
+    Path,
+    /// Parsed from the url query string.
+    Query,
+    /// A json encoded body.
+    Json,
+    /// A url encoded form body.
+    Form,
+    /// A html response body.
+    Html,
+    /// A plain text body.
+    Text,
+    /// Any other extractor or response type.
+    Other
+}
+
+/// The type carried by an argument or response, along with how it is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeInfo {
+    pub codec: Codec,
+    /// The carried type as written in source, with the extractor stripped off.
+    pub ty: &'static str
+}
+
+/// A single server function argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArgInfo {
+    /// The argument's binding name, or `argN` when it's bound with a pattern.
+    pub name: &'static str,
+    pub ty: TypeInfo
+}
+
+/// Metadata describing a state-wide middleware registered through a [ServerMiddleware].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MiddlewareInfo {
+    /// The name of the function providing the middleware's layer.
+    pub name: &'static str,
+    /// The module path the function was declared in.
+    pub module_path: &'static str,
+    /// Where the middleware sits among the state's middlewares; lower orders are further out.
+    pub order: i32
+}
+
+impl RouteInfo {
+    /// Whether the route serves requests for `path` with the http `method`.
+    pub fn responds_to(&self, path: &str, method: &str) -> bool {
+        path_matches(self.path, path)
+            && self
+                .methods
+                .iter()
+                .any(|allowed| *allowed == "any" || allowed.eq_ignore_ascii_case(method))
+    }
+}
+
+impl fmt::Display for RouteInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<7} {} -> {}::{}",
+            self.methods.join(",").to_uppercase(),
+            self.path,
+            self.module_path,
+            self.name
+        )
+    }
+}
+
 /// Trait corresponding to types that can provide an [axum::Router] at startup.
 pub trait ServerRouter: Collect {
     type State: ServerState<Router = Self>;
 
     fn router(&self) -> axum::Router<Self::State>;
 
+    /// The metadata of the route this registration provides.
+    fn route(&self) -> &RouteInfo;
+
     fn load_routes() -> axum::Router<Self::State> {
         let mut loaded = axum::Router::new();
 
         for next in inventory::iter::<Self> {
-            loaded = loaded.merge(next.router());
+            let router = next.router();
+            #[cfg(feature = "metrics")]
+            let router = crate::metrics::layer(router, next.route());
+
+            loaded = loaded.merge(router);
         }
 
         loaded
     }
+
+    /// Iterate the metadata of every route collected for [Self::State].
+    fn routes() -> impl Iterator<Item = &'static RouteInfo> {
+        inventory::iter::<Self>.into_iter().map(|next| next.route())
+    }
+}
+
+/// Trait corresponding to types that layer state-wide middleware onto every route of a state.
+pub trait ServerMiddleware: Collect {
+    type State: ServerState<Middleware = Self>;
+
+    /// Layer the middleware onto `router`.
+    fn apply(&self, router: axum::Router<Self::State>) -> axum::Router<Self::State>;
+
+    /// The metadata of the middleware this registration provides.
+    fn info(&self) -> &MiddlewareInfo;
+
+    /// Every middleware collected for [Self::State], outermost first.
+    ///
+    /// Middlewares are ordered by [MiddlewareInfo::order], then module path and name. The lowest
+    /// order is the outermost layer, so it sees requests first and responses last.
+    fn collected() -> Vec<&'static Self> {
+        let mut collected = inventory::iter::<Self>.into_iter().collect::<Vec<_>>();
+        collected.sort_by_key(|next| {
+            let info = next.info();
+            (info.order, info.module_path, info.name)
+        });
+
+        collected
+    }
+
+    /// Layer every middleware collected for [Self::State] onto `router`, in the order of
+    /// [Self::collected].
+    fn apply_all(router: axum::Router<Self::State>) -> axum::Router<Self::State> {
+        // Each layer wraps the ones applied before it, so the outermost is applied last.
+        Self::collected()
+            .into_iter()
+            .rev()
+            .fold(router, |router, next| next.apply(router))
+    }
+
+    /// Iterate the metadata of every middleware collected for [Self::State], outermost first.
+    fn middlewares() -> impl Iterator<Item = &'static MiddlewareInfo> {
+        Self::collected().into_iter().map(|next| next.info())
+    }
+}
+
+/// The [ServerState::Middleware] of a state implemented by hand with no state-wide middlewares,
+/// `type Middleware = NoMiddleware<Self>;`.
+///
+/// It can't be constructed, so nothing is ever collected for it.
+pub struct NoMiddleware<S>(Infallible, PhantomData<fn() -> S>);
+
+// Implemented by hand since `inventory::collect!` needs a concrete type. The shared registry stays
+// empty for every `S`, since no value can be submitted to it.
+impl<S: 'static> Collect for NoMiddleware<S> {
+    fn registry() -> &'static inventory::Registry {
+        static REGISTRY: inventory::Registry = inventory::Registry::new();
+        &REGISTRY
+    }
+}
+
+impl<S: ServerState<Middleware = Self>> ServerMiddleware for NoMiddleware<S> {
+    type State = S;
+
+    fn apply(&self, _: axum::Router<S>) -> axum::Router<S> {
+        match self.0 {}
+    }
+
+    fn info(&self) -> &MiddlewareInfo {
+        match self.0 {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Clone)]
+    struct HandWrittenState;
+
+    struct HandWrittenRouter(RouteInfo, RouterFn<HandWrittenState>);
+
+    impl ServerRouter for HandWrittenRouter {
+        type State = HandWrittenState;
+
+        fn router(&self) -> axum::Router<Self::State> {
+            (self.1)()
+        }
+
+        fn route(&self) -> &RouteInfo {
+            &self.0
+        }
+    }
+
+    inventory::collect!(HandWrittenRouter);
+
+    impl ServerState for HandWrittenState {
+        type Router = HandWrittenRouter;
+        type Middleware = NoMiddleware<Self>;
+    }
+
+    #[test]
+    fn implement_state_without_middlewares() {
+        assert!(HandWrittenState::middlewares().is_empty());
+        assert!(HandWrittenState::routes().is_empty());
+
+        let _router = HandWrittenState.load_routes();
+    }
 }
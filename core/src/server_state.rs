@@ -1,11 +1,17 @@
 use convert_case::{Case, Casing};
 use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::{format_ident, quote_spanned, ToTokens, TokenStreamExt};
-use syn::{spanned::Spanned, Ident, ItemStruct};
+use syn::{
+    spanned::Spanned, GenericArgument, Ident, ItemStruct, LitStr, Member, Path, PathArguments,
+    Type, TypePath
+};
 
 #[cfg(feature = "server")]
-use crate::server_router::ServerRouter;
-use crate::{current_package, make_router};
+use crate::{
+    cors::Cors,
+    server_router::{MiddlewareInfo, RouteInfo, ServerMiddleware, ServerRouter}
+};
+use crate::{current_package, make_cors, make_middleware, make_router, parse::CorsArgs};
 
 /// Trait corresponding to types that can be used as the state of an [axum::Router].
 ///
@@ -17,25 +23,223 @@ pub trait ServerState: Clone + Send + Sync + Sized + 'static {
     /// The type through which routes are collected.
     type Router: ServerRouter<State = Self>;
 
+    /// The type through which state-wide middlewares are collected.
+    ///
+    /// `#[derive(ServerState)]` declares it along with [Self::Router]. Implementations written by
+    /// hand without state-wide middlewares can name
+    /// [NoMiddleware](crate::server_router::NoMiddleware), `type Middleware = NoMiddleware<Self>;`.
+    type Middleware: ServerMiddleware<State = Self>;
+
     fn load_routes(self) -> axum::Router {
-        Self::Router::load_routes().with_state(self)
+        let router = Self::Middleware::apply_all(Self::Router::load_routes());
+        let router = crate::csrf::layer(router, Self::cors(), Self::routes()).with_state(self);
+        let router = match Self::rpc() {
+            Some(endpoint) => crate::rpc::route(router, endpoint, Self::routes()),
+            None => router
+        };
+        let router = match Self::batch_path() {
+            Some(path) => crate::batch::route(router, path),
+            None => router
+        };
+
+        // CORS is outermost, so forgery rejections and the batch and JSON-RPC endpoints carry
+        // its headers too.
+        let endpoints = [
+            Self::rpc().map(|endpoint| endpoint.path),
+            Self::batch_path()
+        ];
+        crate::cors::layer(
+            router,
+            Self::cors(),
+            Self::routes(),
+            endpoints.into_iter().flatten().collect()
+        )
+    }
+
+    /// The CORS configuration of every route without its own, from `#[server_state(cors(..))]`.
+    fn cors() -> Option<Cors> {
+        None
+    }
+
+    /// The path of the route dispatching batches of calls to the state's other routes, from
+    /// `#[server_state(batch = "/api/batch")]`.
+    fn batch_path() -> Option<&'static str> {
+        None
+    }
+
+    /// The JSON-RPC 2.0 endpoint calling the state's server functions by name, from
+    /// `#[server_state(rpc = "/rpc")]`.
+    fn rpc() -> Option<crate::rpc::RpcEndpoint> {
+        None
+    }
+
+    /// Start a [ServerApp](crate::app::ServerApp) serving [ServerState::load_routes].
+    fn app(self) -> crate::app::ServerApp {
+        crate::app::ServerApp::new(self)
+    }
+
+    /// Metadata for every route mounted by [ServerState::load_routes], ordered by path and method.
+    fn routes() -> Vec<&'static RouteInfo> {
+        let mut routes = Self::Router::routes().collect::<Vec<_>>();
+        routes.sort_by_key(|route| (route.path, route.methods));
+
+        routes
+    }
+
+    /// Metadata for every middleware layered by [ServerState::load_routes], outermost first.
+    fn middlewares() -> Vec<&'static MiddlewareInfo> {
+        Self::Middleware::middlewares().collect()
     }
+
+    /// Assemble an OpenAPI 3.1 document describing every route in [ServerState::routes].
+    #[cfg(feature = "openapi")]
+    fn openapi(title: &str, version: &str) -> serde_json::Value {
+        crate::openapi::document(title, version, Self::routes())
+    }
+
+    /// Generate TypeScript bindings for every route in [ServerState::routes].
+    #[cfg(feature = "typescript")]
+    fn typescript() -> String {
+        crate::typescript::bindings(Self::routes())
+    }
+}
+
+/// Arguments of the `#[server_state(...)]` helper attribute.
+#[derive(Default, deluxe::ExtractAttributes)]
+#[deluxe(attributes(server_state), default)]
+struct ServerStateAttrs {
+    /// Path to serve the state's OpenAPI document from.
+    openapi: Option<LitStr>,
+    /// Path to serve the Prometheus metrics of every server function from.
+    metrics: Option<LitStr>,
+    /// Path to serve batches of server function calls from.
+    batch: Option<LitStr>,
+    /// Path to serve a JSON-RPC 2.0 endpoint calling every server function by name from.
+    rpc: Option<LitStr>,
+    /// Only expose server functions tagged `rpc = true` on the JSON-RPC endpoint.
+    rpc_tagged: deluxe::Flag,
+    /// Server functions declared with `shared = true` to serve with this state.
+    shared: Vec<Path>,
+    /// Cross-origin access to every route without its own `cors(..)` argument, off until it lists
+    /// some origins.
+    cors: CorsArgs
+}
+
+/// Arguments of the `#[server_state(...)]` helper attribute on the state's fields.
+#[derive(Default, deluxe::ExtractAttributes)]
+#[deluxe(attributes(server_state), default)]
+struct ServerStateFieldAttrs {
+    /// Implement `FromRef<State>` for the field's type.
+    from_ref: deluxe::Flag,
+    /// Types to implement `FromRef<State>` for through the field's own `FromRef` impls.
+    nested: Vec<Type>
+}
+
+/// `FromRef` impls requested on a single field of the state.
+pub(crate) struct FieldRefs {
+    pub member: Member,
+    pub ty: Type,
+    pub from_ref: bool,
+    pub nested: Vec<Type>
 }
 
 pub(crate) struct ServerStateImpl {
     pub span: Span,
     pub ident: Ident,
-    pub current_package: String
+    pub current_package: String,
+    pub openapi: Option<LitStr>,
+    pub metrics: Option<LitStr>,
+    pub batch: Option<LitStr>,
+    /// The `Option<RpcEndpoint>` returned by [ServerState::rpc].
+    pub rpc: Option<TokenStream2>,
+    pub shared: Vec<Path>,
+    /// The `Option<Cors>` returned by [ServerState::cors].
+    pub cors: Option<TokenStream2>,
+    pub field_refs: Vec<FieldRefs>
 }
 
 impl ServerStateImpl {
-    pub fn try_new(item: ItemStruct) -> Result<Self, syn::Error> {
+    pub fn try_new(mut item: ItemStruct) -> Result<Self, syn::Error> {
         let current_package = current_package(item.span())?;
+        let ServerStateAttrs {
+            openapi,
+            metrics,
+            batch,
+            rpc,
+            rpc_tagged,
+            shared,
+            cors
+        } = deluxe::extract_attributes(&mut item)?;
+        let cors = (!cors.origins.is_empty())
+            .then(|| make_cors(item.span(), Some(&cors)))
+            .transpose()?;
+        let rpc = match (rpc, rpc_tagged.is_set()) {
+            (Some(path), tagged_only) => Some(quote_spanned! { path.span() =>
+                ::std::option::Option::Some(::server_fns::rpc::RpcEndpoint {
+                    path: #path,
+                    tagged_only: #tagged_only
+                })
+            }),
+            (None, true) => {
+                return Err(syn::Error::new(
+                    item.span(),
+                    "`rpc_tagged` needs an `rpc` endpoint path."
+                ));
+            }
+            (None, false) => None
+        };
+
+        let mut field_refs = Vec::new();
+        let mut targets = Vec::new();
+
+        for (i, field) in item.fields.iter_mut().enumerate() {
+            let ServerStateFieldAttrs { from_ref, nested } = deluxe::extract_attributes(field)?;
+
+            // Two fields providing the same type would be conflicting impls, reported far from
+            // the attributes that asked for them.
+            let field_targets = from_ref
+                .is_set()
+                .then_some(&field.ty)
+                .into_iter()
+                .chain(&nested);
+            for target in field_targets {
+                let target_str = target.to_token_stream().to_string();
+                if targets.contains(&target_str) {
+                    return Err(syn::Error::new(
+                        target.span(),
+                        format!(
+                            "`FromRef` is already implemented for ({target_str}) by another \
+                             field of the state."
+                        )
+                    ));
+                }
+                targets.push(target_str);
+            }
+
+            if from_ref.is_set() || !nested.is_empty() {
+                field_refs.push(FieldRefs {
+                    member: match &field.ident {
+                        Some(ident) => Member::Named(ident.clone()),
+                        None => Member::Unnamed(i.into())
+                    },
+                    ty: field.ty.clone(),
+                    from_ref: from_ref.is_set(),
+                    nested
+                });
+            }
+        }
 
         Ok(Self {
             span: item.span(),
             ident: item.ident,
-            current_package
+            current_package,
+            openapi,
+            metrics,
+            batch,
+            rpc,
+            shared,
+            cors,
+            field_refs
         })
     }
 }
@@ -45,7 +249,14 @@ impl ToTokens for ServerStateImpl {
         let Self {
             span,
             ident,
-            current_package
+            current_package,
+            openapi,
+            metrics,
+            batch,
+            rpc,
+            shared,
+            cors,
+            field_refs
         } = self;
 
         let ident_str = ident.to_string();
@@ -56,34 +267,166 @@ impl ToTokens for ServerStateImpl {
         );
 
         let state_router = make_router(&ident_str);
-        let package_router = make_router(current_package);
         let router_fn_type = format_ident!("{state_router}Fn");
+        let state_middleware = make_middleware(&ident_str);
+        let middleware_fn_type = format_ident!("{state_middleware}Fn");
+
+        let openapi_route = openapi.as_ref().map(|path| {
+            quote_spanned! { *span =>
+                fn __openapi_router() -> ::server_fns::axum::Router<super::#ident> {
+                    use ::server_fns::axum::{
+                        http::header::CONTENT_TYPE, response::IntoResponse, routing, Router
+                    };
+                    use ::std::sync::OnceLock;
+
+                    async fn openapi() -> impl IntoResponse {
+                        static DOCUMENT: OnceLock<String> = OnceLock::new();
+
+                        let document = DOCUMENT.get_or_init(|| {
+                            <super::#ident as ::server_fns::server_state::ServerState>::openapi(
+                                ::std::env!("CARGO_PKG_NAME"),
+                                ::std::env!("CARGO_PKG_VERSION")
+                            )
+                            .to_string()
+                        });
+
+                        ([(CONTENT_TYPE, "application/json")], document.as_str())
+                    }
+
+                    Router::new().route(#path, routing::get(openapi))
+                }
+
+                ::server_fns::inventory::submit! {
+                    #state_router::register(
+                        ::server_fns::server_router::RouteInfo {
+                            path: #path,
+                            methods: &["get"],
+                            name: "openapi",
+                            module_path: ::std::module_path!(),
+                            package: #current_package,
+                            middleware_count: 0,
+                            embed: ::std::option::Option::None,
+                            args: &[],
+                            output: ::server_fns::server_router::TypeInfo {
+                                codec: ::server_fns::server_router::Codec::Json,
+                                ty: "Value"
+                            },
+                            cors: ::std::option::Option::None,
+                            csrf: true,
+                            rpc: false
+                        },
+                        __openapi_router
+                    )
+                }
+            }
+        });
+
+        let metrics_route = metrics.as_ref().map(|path| {
+            quote_spanned! { *span =>
+                fn __metrics_router() -> ::server_fns::axum::Router<super::#ident> {
+                    use ::server_fns::axum::{routing, Router};
+
+                    Router::new().route(#path, routing::get(::server_fns::metrics::handler))
+                }
+
+                ::server_fns::inventory::submit! {
+                    #state_router::register(
+                        ::server_fns::server_router::RouteInfo {
+                            path: #path,
+                            methods: &["get"],
+                            name: "metrics",
+                            module_path: ::std::module_path!(),
+                            package: #current_package,
+                            middleware_count: 0,
+                            embed: ::std::option::Option::None,
+                            args: &[],
+                            output: ::server_fns::server_router::TypeInfo {
+                                codec: ::server_fns::server_router::Codec::Text,
+                                ty: "String"
+                            },
+                            cors: ::std::option::Option::None,
+                            csrf: true,
+                            rpc: false
+                        },
+                        __metrics_router
+                    )
+                }
+            }
+        });
+
+        // Shared routes are generic over their state, so each state using one registers it.
+        let shared_routes = shared.iter().map(|server_fn| {
+            let with_suffix = |suffix: &str| {
+                let mut path = server_fn.clone();
+                if let Some(last) = path.segments.last_mut() {
+                    last.ident = format_ident!("{}{suffix}", last.ident);
+                }
+                path
+            };
+            let route_fn = with_suffix("_route");
+            let router_fn = with_suffix("_router");
+
+            quote_spanned! { server_fn.span() =>
+                #[cfg(feature = "server")]
+                ::server_fns::inventory::submit! {
+                    #module::#state_router::register(#route_fn(), #router_fn::<#ident>)
+                }
+            }
+        });
+
+        let from_refs = field_refs.iter().flat_map(|field| field.to_impls(ident));
+        let cors = cors.iter();
+        let batch = batch.iter();
+        let rpc = rpc.iter();
 
         tokens.append_all(quote_spanned! { *span =>
-            #[cfg(feature = "server")]
-            #[allow(unused)]
-            pub(crate) type #package_router = #module::#state_router;
+            #(#shared_routes)*
+
+            #(
+                #[cfg(feature = "server")]
+                #[automatically_derived]
+                #from_refs
+            )*
 
             #[cfg(feature = "server")]
             mod #module {
                 #[automatically_derived]
                 impl ::server_fns::server_state::ServerState for super::#ident {
                     type Router = #state_router;
+                    type Middleware = #state_middleware;
+
+                    #(
+                        fn cors() -> ::std::option::Option<::server_fns::cors::Cors> {
+                            #cors
+                        }
+                    )*
+
+                    #(
+                        fn batch_path() -> ::std::option::Option<&'static str> {
+                            ::std::option::Option::Some(#batch)
+                        }
+                    )*
+
+                    #(
+                        fn rpc() -> ::std::option::Option<::server_fns::rpc::RpcEndpoint> {
+                            #rpc
+                        }
+                    )*
                 }
 
                 type #router_fn_type = ::server_fns::server_router::RouterFn<super::#ident>;
 
                 pub struct #state_router {
-                    pub path: &'static str,
+                    pub route: ::server_fns::server_router::RouteInfo,
                     pub router_fn: #router_fn_type
                 }
 
                 impl #state_router {
                     pub const fn register(
-                        path: &'static str,
+                        route: ::server_fns::server_router::RouteInfo,
                         router_fn: #router_fn_type
                     ) -> Self {
-                        Self { path, router_fn }
+                        Self { route, router_fn }
                     }
                 }
 
@@ -94,10 +437,193 @@ impl ToTokens for ServerStateImpl {
                     fn router(&self) -> ::server_fns::axum::Router<Self::State> {
                         (self.router_fn)()
                     }
+
+                    fn route(&self) -> &::server_fns::server_router::RouteInfo {
+                        &self.route
+                    }
                 }
 
                 ::server_fns::inventory::collect!(#state_router);
+
+                type #middleware_fn_type = ::server_fns::server_router::MiddlewareFn<super::#ident>;
+
+                pub struct #state_middleware {
+                    pub info: ::server_fns::server_router::MiddlewareInfo,
+                    pub apply: #middleware_fn_type
+                }
+
+                impl #state_middleware {
+                    pub const fn register(
+                        info: ::server_fns::server_router::MiddlewareInfo,
+                        apply: #middleware_fn_type
+                    ) -> Self {
+                        Self { info, apply }
+                    }
+                }
+
+                #[automatically_derived]
+                impl ::server_fns::server_router::ServerMiddleware for #state_middleware {
+                    type State = super::#ident;
+
+                    fn apply(
+                        &self,
+                        router: ::server_fns::axum::Router<Self::State>
+                    ) -> ::server_fns::axum::Router<Self::State> {
+                        (self.apply)(router)
+                    }
+
+                    fn info(&self) -> &::server_fns::server_router::MiddlewareInfo {
+                        &self.info
+                    }
+                }
+
+                ::server_fns::inventory::collect!(#state_middleware);
+
+                #openapi_route
+
+                #metrics_route
             }
         });
     }
 }
+
+impl FieldRefs {
+    /// The `FromRef` impls for the field, cloning its value out of a `&State`.
+    ///
+    /// Nested types are resolved through the field's own `FromRef` impls, looking through an
+    /// `Arc` around the field.
+    fn to_impls(&self, state: &Ident) -> Vec<TokenStream2> {
+        let Self {
+            member,
+            ty,
+            from_ref,
+            nested
+        } = self;
+        let span = ty.span();
+
+        let mut impls = Vec::new();
+
+        if *from_ref {
+            impls.push(quote_spanned! { span =>
+                impl ::server_fns::axum::extract::FromRef<#state> for #ty {
+                    fn from_ref(state: &#state) -> Self {
+                        ::std::clone::Clone::clone(&state.#member)
+                    }
+                }
+            });
+        }
+
+        let (parent, field) = match arc_inner(ty) {
+            Some(inner) => (inner, quote_spanned! { span => &*state.#member }),
+            None => (ty, quote_spanned! { span => &state.#member })
+        };
+
+        for nested in nested {
+            impls.push(quote_spanned! { nested.span() =>
+                impl ::server_fns::axum::extract::FromRef<#state> for #nested {
+                    fn from_ref(state: &#state) -> Self {
+                        <#nested as ::server_fns::axum::extract::FromRef<#parent>>::from_ref(#field)
+                    }
+                }
+            });
+        }
+
+        impls
+    }
+}
+
+/// The type inside an `Arc<T>`, if `ty` is one.
+fn arc_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(TypePath { path, .. }) = ty else {
+        return None;
+    };
+    let last = path.segments.last().filter(|last| last.ident == "Arc")?;
+    let PathArguments::AngleBracketed(args) = &last.arguments else {
+        return None;
+    };
+
+    args.args.iter().find_map(|arg| match arg {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use syn::parse_quote;
+
+    use super::*;
+
+    fn expand(item: ItemStruct) -> String {
+        ServerStateImpl::try_new(item)
+            .unwrap()
+            .to_token_stream()
+            .to_string()
+    }
+
+    #[test]
+    fn implement_field_refs() {
+        let tokens = expand(parse_quote!(
+            struct AppState {
+                #[server_state(from_ref)]
+                db: Db,
+                #[server_state(nested = [Sessions, Keys])]
+                auth: AuthState,
+                plain: Config
+            }
+        ));
+
+        assert!(tokens.contains(
+            "impl :: server_fns :: axum :: extract :: FromRef < AppState > for Db { fn from_ref \
+             (state : & AppState) -> Self { :: std :: clone :: Clone :: clone (& state . db) } }"
+        ));
+        for nested in ["Sessions", "Keys"] {
+            assert!(tokens.contains(&format!(
+                "< {nested} as :: server_fns :: axum :: extract :: FromRef < AuthState >> :: \
+                 from_ref (& state . auth)"
+            )));
+        }
+        assert!(!tokens.contains("for AuthState"));
+        assert!(!tokens.contains("for Config"));
+    }
+
+    #[test]
+    fn implement_arc_field_refs() {
+        let tokens = expand(parse_quote!(
+            struct AppState(#[server_state(from_ref, nested = [Sessions])] Arc<Inner>);
+        ));
+
+        assert!(tokens.contains("FromRef < AppState > for Arc < Inner >"));
+        assert!(tokens.contains(
+            "< Sessions as :: server_fns :: axum :: extract :: FromRef < Inner >> :: from_ref (& * \
+             state . 0)"
+        ));
+    }
+
+    #[test]
+    fn reject_duplicate_field_refs() {
+        let duplicates: [ItemStruct; 2] = [
+            parse_quote!(
+                struct AppState {
+                    #[server_state(from_ref)]
+                    primary: Db,
+                    #[server_state(from_ref)]
+                    replica: Db
+                }
+            ),
+            parse_quote!(
+                struct AppState {
+                    #[server_state(from_ref)]
+                    sessions: Sessions,
+                    #[server_state(nested = [Sessions])]
+                    auth: AuthState
+                }
+            )
+        ];
+
+        for item in duplicates {
+            let err = ServerStateImpl::try_new(item).err().unwrap();
+            assert!(err.to_string().contains("already implemented for"));
+        }
+    }
+}
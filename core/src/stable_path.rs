@@ -0,0 +1,60 @@
+/// The length of a [hashed_path]: `/api/` followed by 16 hex digits.
+pub const HASHED_PATH_LEN: usize = 21;
+
+/// The path `/api/<hash>` of a server function with `hashed = true` or an explicit `id`, from a
+/// 64-bit FNV-1a hash of `seed`.
+///
+/// The seed is the function's id, or its module path and name, so the path stays the same across
+/// builds and differs between functions sharing a name.
+pub const fn hashed_path(seed: &str) -> [u8; HASHED_PATH_LEN] {
+    const PREFIX: &[u8] = b"/api/";
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    let seed = seed.as_bytes();
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    let mut i = 0;
+    while i < seed.len() {
+        hash ^= seed[i] as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01B3);
+        i += 1;
+    }
+
+    let mut path = [0; HASHED_PATH_LEN];
+    let mut i = 0;
+    while i < PREFIX.len() {
+        path[i] = PREFIX[i];
+        i += 1;
+    }
+    while i < HASHED_PATH_LEN {
+        let shift = (HASHED_PATH_LEN - 1 - i) * 4;
+        path[i] = HEX[(hash >> shift) as usize & 0xF];
+        i += 1;
+    }
+
+    path
+}
+
+/// A [hashed_path] as a `str`, usable in the `const` generated for the route.
+pub const fn as_str(path: &[u8; HASHED_PATH_LEN]) -> &str {
+    match std::str::from_utf8(path) {
+        Ok(path) => path,
+        Err(_) => panic!("Hashed paths are ascii.")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hash_paths() {
+        const PATH: [u8; HASHED_PATH_LEN] = hashed_path("");
+        assert_eq!(as_str(&PATH), "/api/cbf29ce484222325");
+
+        assert_eq!(as_str(&hashed_path("a")), "/api/af63dc4c8601ec8c");
+        assert_ne!(
+            hashed_path("app::users::list"),
+            hashed_path("app::posts::list")
+        );
+    }
+}
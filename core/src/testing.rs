@@ -0,0 +1,265 @@
+#![cfg(feature = "server")]
+
+use std::fmt;
+
+use axum::{
+    body::{self, Body, Bytes},
+    extract::Request,
+    http::{
+        header::{self, HeaderName, HeaderValue},
+        request, HeaderMap, Method, StatusCode
+    },
+    response::Response,
+    Router
+};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use tower::ServiceExt;
+
+use crate::server_state::ServerState;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TestError {
+    #[error("Failed to encode request; {0}")]
+    Encode(String),
+
+    #[error("Failed to decode response; {0}")]
+    Decode(String),
+
+    #[error("Server function responded with ({0}); {1}")]
+    Status(StatusCode, String)
+}
+
+/// A router serving every route of a [ServerState], called in process through
+/// [ServiceExt::oneshot].
+///
+/// Server functions also get a `#[cfg(test)]` helper per method, `<method>_<name>_test`, that
+/// encodes its arguments with the same codecs as its extractors and decodes the typed result:
+///
+/// ```ignore
+/// let server = TestServer::new(AppState::default());
+/// let response = post_rename_user_test(&server, 42, "name".to_owned()).await;
+///
+/// assert_eq!(response.status(), StatusCode::OK);
+/// assert!(response.value().unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct TestServer {
+    router: Router
+}
+
+impl TestServer {
+    pub fn new(state: impl ServerState) -> Self {
+        Self::from_router(state.load_routes())
+    }
+
+    pub fn from_router(router: Router) -> Self {
+        Self { router }
+    }
+
+    /// Start building a request to `uri`.
+    pub fn request(&self, method: Method, uri: impl AsRef<str>) -> TestRequest<'_> {
+        TestRequest {
+            server: self,
+            builder: Request::builder().method(method),
+            uri: uri.as_ref().to_owned(),
+            body: Body::empty(),
+            error: None
+        }
+    }
+
+    /// Send a raw request through the router.
+    pub async fn send(&self, request: Request) -> Response {
+        match self.router.clone().oneshot(request).await {
+            Ok(response) => response,
+            Err(infallible) => match infallible {}
+        }
+    }
+}
+
+pub struct TestRequest<'a> {
+    server: &'a TestServer,
+    builder: request::Builder,
+    uri: String,
+    body: Body,
+    error: Option<TestError>
+}
+
+impl<'a> TestRequest<'a> {
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<axum::http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<axum::http::Error>
+    {
+        self.builder = self.builder.header(key, value);
+        self
+    }
+
+    /// Url encode `query` onto the request uri, as read by [axum::extract::Query].
+    pub fn query<T: Serialize>(mut self, query: &T) -> Self {
+        match serde_urlencoded::to_string(query) {
+            Ok(encoded) if encoded.is_empty() => {}
+            Ok(encoded) => {
+                let separator = if self.uri.contains('?') { '&' } else { '?' };
+                self.uri = format!("{}{separator}{encoded}", self.uri);
+            }
+            Err(err) => self.error = Some(TestError::Encode(err.to_string()))
+        }
+
+        self
+    }
+
+    /// Set a json body, as read by `axum::Json`.
+    pub fn json<T: Serialize>(self, body: &T) -> Self {
+        match serde_json::to_vec(body) {
+            Ok(encoded) => self.body(mime("application/json"), encoded),
+            Err(err) => self.fail(err)
+        }
+    }
+
+    /// Set a url encoded body, as read by `axum::Form`.
+    pub fn form<T: Serialize>(self, body: &T) -> Self {
+        match serde_urlencoded::to_string(body) {
+            Ok(encoded) => self.body(mime("application/x-www-form-urlencoded"), encoded),
+            Err(err) => self.fail(err)
+        }
+    }
+
+    /// Set a plain text body, as read by [String].
+    pub fn text(self, body: impl Into<String>) -> Self {
+        self.body(mime("text/plain; charset=utf-8"), body.into())
+    }
+
+    /// Send the request, keeping the response body undecoded.
+    pub async fn send(self) -> TestResponse {
+        self.send_decoded(decode_bytes).await
+    }
+
+    /// Send the request, decoding the response body with `decode` in [TestResponse::value].
+    pub async fn send_decoded<T>(self, decode: Decode<T>) -> TestResponse<T> {
+        let Self {
+            server,
+            builder,
+            uri,
+            body,
+            error
+        } = self;
+
+        let request = match (error, builder.uri(uri).body(body)) {
+            (Some(err), _) => return TestResponse::error(err),
+            (None, Err(err)) => return TestResponse::error(TestError::Encode(err.to_string())),
+            (None, Ok(request)) => request
+        };
+
+        let (parts, body) = server.send(request).await.into_parts();
+
+        match body::to_bytes(body, usize::MAX).await {
+            Ok(body) => TestResponse {
+                status: parts.status,
+                headers: parts.headers,
+                body,
+                decode,
+                error: None
+            },
+            Err(err) => TestResponse::error(TestError::Decode(err.to_string()))
+        }
+    }
+
+    fn body(mut self, content_type: HeaderValue, body: impl Into<Body>) -> Self {
+        self.builder = self.builder.header(header::CONTENT_TYPE, content_type);
+        self.body = body.into();
+        self
+    }
+
+    fn fail(mut self, err: impl fmt::Display) -> Self {
+        self.error = Some(TestError::Encode(err.to_string()));
+        self
+    }
+}
+
+fn mime(mime: &'static str) -> HeaderValue {
+    HeaderValue::from_static(mime)
+}
+
+pub type Decode<T> = fn(&Bytes) -> Result<T, TestError>;
+
+/// A buffered response, with the body decoded on demand by [TestResponse::value].
+pub struct TestResponse<T = Bytes> {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    decode: Decode<T>,
+    error: Option<TestError>
+}
+
+impl<T> TestResponse<T> {
+    fn error(err: TestError) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            decode: |_| Err(TestError::Decode("request was never sent".into())),
+            error: Some(err)
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn json<U: DeserializeOwned>(&self) -> Result<U, TestError> {
+        decode_json(&self.body)
+    }
+
+    /// Decode the typed result of the call, failing on non-success statuses.
+    pub fn value(&self) -> Result<T, TestError> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+
+        if !self.status.is_success() {
+            return Err(TestError::Status(self.status, self.text()));
+        }
+
+        (self.decode)(&self.body)
+    }
+}
+
+pub fn decode_json<T: DeserializeOwned>(body: &Bytes) -> Result<T, TestError> {
+    serde_json::from_slice(body).map_err(|err| TestError::Decode(err.to_string()))
+}
+
+pub fn decode_text(body: &Bytes) -> Result<String, TestError> {
+    String::from_utf8(body.to_vec()).map_err(|err| TestError::Decode(err.to_string()))
+}
+
+pub fn decode_bytes(body: &Bytes) -> Result<Bytes, TestError> {
+    Ok(body.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keep_errors() {
+        let err = TestError::Decode("length limit exceeded".into());
+        let response = TestResponse::<Bytes>::error(err.clone());
+
+        assert_eq!(response.value(), Err(err));
+    }
+}
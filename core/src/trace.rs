@@ -0,0 +1,293 @@
+#![cfg(any(feature = "server", feature = "web"))]
+
+use std::{
+    cell::Cell,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll}
+};
+
+/// The W3C trace context header generated clients propagate.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// A W3C trace context, identifying the trace a call belongs to and the span that made it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: u128,
+    /// The span the next call is made from, sent as the `parent-id` of its `traceparent`.
+    pub parent_id: u64,
+    pub sampled: bool
+}
+
+impl TraceContext {
+    /// Parse a version `00` `traceparent` header, rejecting all-zero ids.
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut parts = header.trim().split('-');
+        let (Some("00"), Some(trace_id), Some(parent_id), Some(flags), None) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next()
+        ) else {
+            return None;
+        };
+
+        if trace_id.len() != 32 || parent_id.len() != 16 || flags.len() != 2 {
+            return None;
+        }
+
+        let context = Self {
+            trace_id: u128::from_str_radix(trace_id, 16).ok()?,
+            parent_id: u64::from_str_radix(parent_id, 16).ok()?,
+            sampled: u8::from_str_radix(flags, 16).ok()? & 1 == 1
+        };
+
+        (context.trace_id != 0 && context.parent_id != 0).then_some(context)
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id,
+            self.parent_id,
+            u8::from(self.sampled)
+        )
+    }
+}
+
+thread_local! {
+    static SCOPED_CONTEXT: Cell<Option<TraceContext>> = const { Cell::new(None) };
+}
+
+/// Run `future` with every client call it makes carrying `context` as its `traceparent`.
+///
+/// Traced server functions run their handlers this way, so calls they make continue the trace.
+pub fn with_trace_context<F: Future>(context: TraceContext, future: F) -> WithTraceContext<F> {
+    WithTraceContext {
+        context,
+        future: Box::pin(future)
+    }
+}
+
+pub struct WithTraceContext<F> {
+    context: TraceContext,
+    future: Pin<Box<F>>
+}
+
+impl<F: Future> Future for WithTraceContext<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let outer = SCOPED_CONTEXT.with(|scoped| scoped.replace(Some(self.context)));
+        let poll = self.future.as_mut().poll(cx);
+
+        SCOPED_CONTEXT.with(|scoped| scoped.set(outer));
+        poll
+    }
+}
+
+/// The trace context of the [with_trace_context] currently being polled.
+pub fn current_trace_context() -> Option<TraceContext> {
+    SCOPED_CONTEXT.with(Cell::get)
+}
+
+#[cfg(feature = "tracing")]
+pub use runtime::*;
+
+#[cfg(feature = "tracing")]
+mod runtime {
+    use std::{
+        collections::hash_map::RandomState,
+        fmt::Debug,
+        hash::{BuildHasher, Hasher},
+        sync::atomic::{AtomicU64, Ordering},
+        time::Instant
+    };
+
+    use axum::{
+        extract::{Request, State},
+        middleware::Next,
+        response::Response
+    };
+    use itertools::Itertools;
+    use tracing::{field, Instrument, Span};
+
+    use super::*;
+
+    /// A new span or trace id. Ids only need to be unique, not unpredictable.
+    fn new_id() -> u64 {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.finish().max(1)
+    }
+
+    impl TraceContext {
+        /// A context starting a new sampled trace.
+        pub fn root() -> Self {
+            Self {
+                trace_id: u128::from(new_id()) << 64 | u128::from(new_id()),
+                parent_id: new_id(),
+                sampled: true
+            }
+        }
+
+        /// The context of a new span within the same trace.
+        pub fn child(self) -> Self {
+            Self {
+                parent_id: new_id(),
+                ..self
+            }
+        }
+    }
+
+    /// The server function a traced route serves, generated when the `tracing` feature is on.
+    #[derive(Debug, Clone, Copy)]
+    pub struct TracedRoute {
+        pub name: &'static str,
+        pub path: &'static str
+    }
+
+    /// Middleware running a route inside a `server_fn` span, continuing the caller's trace, and
+    /// logging the response's status and latency.
+    pub async fn layer(State(route): State<TracedRoute>, request: Request, next: Next) -> Response {
+        let parent = request
+            .headers()
+            .get(TRACEPARENT_HEADER)
+            .and_then(|header| header.to_str().ok())
+            .and_then(TraceContext::parse);
+        let context = parent.map_or_else(TraceContext::root, TraceContext::child);
+
+        let span = tracing::info_span!(
+            "server_fn",
+            server_fn = route.name,
+            route = route.path,
+            method = %request.method(),
+            trace_id = %format_args!("{:032x}", context.trace_id),
+            span_id = %format_args!("{:016x}", context.parent_id),
+            parent_id = field::Empty,
+            args = field::Empty,
+            status = field::Empty,
+            latency_ms = field::Empty
+        );
+        if let Some(parent) = parent {
+            span.record(
+                "parent_id",
+                field::display(format_args!("{:016x}", parent.parent_id))
+            );
+        }
+
+        let start = Instant::now();
+        let response = with_trace_context(context, next.run(request))
+            .instrument(span.clone())
+            .await;
+        let latency_ms = start.elapsed().as_secs_f64() * 1_000.0;
+        let status = response.status();
+
+        span.record("status", status.as_u16());
+        span.record("latency_ms", latency_ms);
+        span.in_scope(|| {
+            let status = status.as_u16();
+
+            if response.status().is_server_error() {
+                tracing::error!(status, latency_ms, "server function failed");
+            } else if response.status().is_client_error() {
+                tracing::warn!(status, latency_ms, "server function rejected request");
+            } else {
+                tracing::info!(status, latency_ms, "server function responded");
+            }
+        });
+
+        response
+    }
+
+    /// Record a traced handler's arguments on its span, for server functions with
+    /// `trace_args = true`.
+    pub fn record_args(args: &[(&str, &dyn Debug)]) {
+        let args = args
+            .iter()
+            .map(|(name, value)| format!("{name}={value:?}"))
+            .join(", ");
+
+        Span::current().record("args", args);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_traceparent() {
+        let header = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let context = TraceContext::parse(header).unwrap();
+
+        assert_eq!(context.trace_id, 0x4BF92F3577B34DA6A3CE929D0E0E4736);
+        assert_eq!(context.parent_id, 0x00F067AA0BA902B7);
+        assert!(context.sampled);
+        assert_eq!(context.to_string(), header);
+
+        assert!(
+            TraceContext::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+                .is_none()
+        );
+        assert!(
+            TraceContext::parse("00-00000000000000000000000000000000-00f067aa0ba902b7-01")
+                .is_none()
+        );
+        assert!(TraceContext::parse("00-4bf92f3577b34da6-00f067aa0ba902b7-01").is_none());
+    }
+
+    #[test]
+    fn scope_trace_context() {
+        let context =
+            TraceContext::parse("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00").unwrap();
+
+        let mut future = with_trace_context(context, async { current_trace_context() });
+        let waker = std::task::Waker::noop();
+        let poll = Pin::new(&mut future).poll(&mut Context::from_waker(waker));
+
+        assert_eq!(poll, Poll::Ready(Some(context)));
+        assert_eq!(current_trace_context(), None);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn continue_traces() {
+        use axum::{http::Method, middleware::from_fn_with_state, routing::get, Router};
+
+        use crate::testing::TestServer;
+
+        let route = TracedRoute {
+            name: "traced",
+            path: "/traced"
+        };
+        let router = Router::new()
+            .route(
+                "/traced",
+                get(|| async { current_trace_context().unwrap().to_string() })
+            )
+            .route_layer(from_fn_with_state(route, layer));
+        let server = TestServer::from_router(router);
+
+        let parent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+        let response = server
+            .request(Method::GET, "/traced")
+            .header(TRACEPARENT_HEADER, parent)
+            .send()
+            .await;
+        let context = TraceContext::parse(&response.text()).unwrap();
+
+        assert_eq!(context.trace_id, 0x0AF7651916CD43DD8448EB211C80319C);
+        assert_ne!(context.parent_id, 0xB7AD6B7169203331);
+
+        let response = server.request(Method::GET, "/traced").send().await;
+        assert!(TraceContext::parse(&response.text()).is_some());
+    }
+}
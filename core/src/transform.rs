@@ -2,13 +2,20 @@ use std::env;
 
 use convert_case::{Case, Casing};
 use proc_macro2::{Span, TokenStream as TokenStream2};
-use quote::{format_ident, quote, ToTokens};
-use syn::{spanned::Spanned, ExprStruct, Ident, ItemFn, ItemStruct, TypePath};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
+use syn::{
+    parse::Parser, parse_quote, punctuated::Punctuated, spanned::Spanned, Expr, ExprLit,
+    ExprStruct, Ident, ItemFn, ItemStruct, Lit, Meta, Token, TypePath
+};
 
 use crate::{
-    embed_asset::LoadAssetImpl, middleware::MiddlewareImpl, parse::ServerFnArgs,
-    server_fn::ServerFn, server_state::ServerStateImpl, AttrMacro, DeriveMacro, FnMacro,
-    HttpMethod
+    embed_asset::LoadAssetImpl,
+    guard::GuardImpl,
+    middleware::{GlobalMiddlewareImpl, MiddlewareGroupImpl, MiddlewareImpl, ServerAttr},
+    parse::{CorsArgs, GlobalMiddlewareArgs, MiddlewareGroup, ServerFnArgs},
+    server_fn::ServerFn,
+    server_state::ServerStateImpl,
+    AttrMacro, DeriveMacro, FnMacro, HttpMethod
 };
 
 pub(crate) fn current_package(span: Span) -> Result<String, syn::Error> {
@@ -19,10 +26,42 @@ pub(crate) fn make_router(make: impl AsRef<str>) -> Ident {
     format_ident!("{}Router", make.as_ref().to_case(Case::Pascal))
 }
 
+pub(crate) fn make_middleware(make: impl AsRef<str>) -> Ident {
+    format_ident!("{}Middleware", make.as_ref().to_case(Case::Pascal))
+}
+
 pub(crate) fn make_server_state(make: impl AsRef<str>) -> Ident {
     format_ident!("{}ServerState", make.as_ref().to_case(Case::Pascal))
 }
 
+/// An `Option<Cors>` expression for a `cors(..)` argument.
+pub(crate) fn make_cors(span: Span, cors: Option<&CorsArgs>) -> Result<TokenStream2, syn::Error> {
+    let Some(cors) = cors else {
+        return Ok(quote_spanned! { span => ::std::option::Option::None });
+    };
+    cors.check()?;
+
+    let CorsArgs {
+        origins,
+        headers,
+        credentials,
+        ..
+    } = cors;
+    let max_age = match cors.max_age_secs()? {
+        Some(secs) => quote_spanned! { span => ::std::option::Option::Some(#secs) },
+        None => quote_spanned! { span => ::std::option::Option::None }
+    };
+
+    Ok(quote_spanned! { span =>
+        ::std::option::Option::Some(::server_fns::cors::Cors {
+            origins: &[#(#origins),*],
+            headers: &[#(#headers),*],
+            credentials: #credentials,
+            max_age: #max_age
+        })
+    })
+}
+
 pub struct ServerFnAttrMacro;
 
 impl AttrMacro for ServerFnAttrMacro {
@@ -31,14 +70,44 @@ impl AttrMacro for ServerFnAttrMacro {
     type Result = Result<Self::TokenStream, Self::Error>;
 
     fn transform2(&self, args: Self::TokenStream, body: Self::TokenStream) -> Self::Result {
-        let annotated_fn: ItemFn = syn::parse2(body)?;
+        let mut annotated_fn: ItemFn = syn::parse2(body)?;
         let args: ServerFnArgs = syn::parse2(args)?;
+
+        // Stacked server attributes are merged into the next one down, which expands them all.
+        if let Some(ServerAttr {
+            index,
+            path,
+            args: inner_args
+        }) = ServerAttr::find(&annotated_fn)?
+        {
+            let merged = listed_methods(args.merge(inner_args)?)?;
+            annotated_fn.attrs[index] = parse_quote!(#[#path(#merged)]);
+
+            return Ok(quote!(#annotated_fn));
+        }
+
         let server_fn = ServerFn::try_new(args, annotated_fn)?;
 
         Ok(quote!(#server_fn))
     }
 }
 
+/// Merged arguments with their methods written as `methods = [..]`, since method attributes
+/// reject a `method` other than their own.
+fn listed_methods(args: ServerFnArgs) -> syn::Result<TokenStream2> {
+    let metas = Punctuated::<Meta, Token![,]>::parse_terminated.parse2(args.to_token_stream())?;
+
+    let metas = metas.into_iter().map(|meta| match meta {
+        Meta::NameValue(method) if method.path.is_ident("method") => {
+            let value = method.value;
+            parse_quote!(methods = [#value])
+        }
+        meta => meta
+    });
+
+    Ok(quote!(#(#metas),*))
+}
+
 pub struct ServerFnMethodAttr(pub HttpMethod);
 
 impl AttrMacro for ServerFnMethodAttr {
@@ -47,10 +116,41 @@ impl AttrMacro for ServerFnMethodAttr {
     type Result = <ServerFnAttrMacro as AttrMacro>::Result;
 
     fn transform2(&self, args: Self::TokenStream, body: Self::TokenStream) -> Self::Result {
+        let metas = Punctuated::<Meta, Token![,]>::parse_terminated.parse2(args.clone())?;
+        let method = self.0.as_ref();
+
+        // `#[get(method = "post")]` is ambiguous, while stacked method attributes list the
+        // methods they add with `methods = [..]`.
+        for meta in metas {
+            let Meta::NameValue(other) = meta else {
+                continue;
+            };
+            if !other.path.is_ident("method") {
+                continue;
+            }
+            let Expr::Lit(ExprLit {
+                lit: Lit::Str(other),
+                ..
+            }) = other.value
+            else {
+                continue;
+            };
+
+            if !other.value().eq_ignore_ascii_case(method) {
+                return Err(syn::Error::new(
+                    other.span(),
+                    format!(
+                        "`#[{method}]` can't be given another `method`; stack a method attribute \
+                         or list them with `methods = [..]` instead."
+                    )
+                ));
+            }
+        }
+
         let mut args: ServerFnArgs = syn::parse2(args)?;
 
-        // Set (or override) the method to the method macro used instead.
-        args.method = Some(Ident::new(self.0.as_ref(), body.span()));
+        // Add the method of the method macro used.
+        args.push_method(Ident::new(self.0.as_ref(), body.span()));
 
         ServerFnAttrMacro.transform2(args.into_token_stream(), body)
     }
@@ -71,6 +171,52 @@ impl AttrMacro for MiddlewareAttrMacro {
     }
 }
 
+pub struct GuardAttrMacro;
+
+impl AttrMacro for GuardAttrMacro {
+    type TokenStream = TokenStream2;
+    type Error = syn::Error;
+    type Result = Result<Self::TokenStream, Self::Error>;
+
+    fn transform2(&self, args: Self::TokenStream, body: Self::TokenStream) -> Self::Result {
+        let annotated_fn: ItemFn = syn::parse2(body)?;
+        let guard = GuardImpl::try_new(args, annotated_fn)?;
+
+        Ok(quote!(#guard))
+    }
+}
+
+pub struct MiddlewareGroupFnMacro;
+
+impl FnMacro for MiddlewareGroupFnMacro {
+    type TokenStream = TokenStream2;
+    type Error = syn::Error;
+    type Result = Result<Self::TokenStream, Self::Error>;
+
+    fn transform2(&self, item: Self::TokenStream) -> Self::Result {
+        let group: MiddlewareGroup = syn::parse2(item)?;
+        let group_impl = MiddlewareGroupImpl::new(group);
+
+        Ok(quote!(#group_impl))
+    }
+}
+
+pub struct GlobalMiddlewareAttrMacro;
+
+impl AttrMacro for GlobalMiddlewareAttrMacro {
+    type TokenStream = TokenStream2;
+    type Error = syn::Error;
+    type Result = Result<Self::TokenStream, Self::Error>;
+
+    fn transform2(&self, args: Self::TokenStream, body: Self::TokenStream) -> Self::Result {
+        let annotated_fn: ItemFn = syn::parse2(body)?;
+        let args: GlobalMiddlewareArgs = syn::parse2(args)?;
+        let global_middleware = GlobalMiddlewareImpl::try_new(args, annotated_fn)?;
+
+        Ok(quote!(#global_middleware))
+    }
+}
+
 pub struct ServerStateDeriveMacro;
 
 impl DeriveMacro for ServerStateDeriveMacro {
@@ -118,3 +264,61 @@ impl FnMacro for LoadAssetInternalMacro {
         Ok(quote!(#embed_impl))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reject_other_methods() {
+        let body = quote! {
+            async fn handler() {}
+        };
+
+        let get = ServerFnMethodAttr(HttpMethod::Get);
+        assert!(get
+            .transform2(quote!(method = "post"), body.clone())
+            .is_err());
+        assert!(get.transform2(quote!(method = "GET"), body).is_ok());
+
+        // Stacked method attributes hand their methods down as a list.
+        let stacked = get
+            .transform2(
+                quote!(),
+                quote! {
+                    #[post]
+                    async fn handler() {}
+                }
+            )
+            .unwrap();
+        let stacked: ItemFn = syn::parse2(stacked).unwrap();
+        assert_eq!(
+            stacked.attrs[0].to_token_stream().to_string(),
+            quote!(#[post(methods = ["get"])]).to_string()
+        );
+
+        // Every merged argument is handed down, and malformed ones are errors.
+        let stacked = get
+            .transform2(
+                quote!(timeout = "1s"),
+                quote! {
+                    #[post(max_body = 8)]
+                    async fn handler() {}
+                }
+            )
+            .unwrap();
+        let stacked: ItemFn = syn::parse2(stacked).unwrap();
+        let merged = stacked.attrs[0].to_token_stream().to_string();
+        assert!(merged.contains("timeout = \"1s\"") && merged.contains("max_body = 8"));
+
+        assert!(get
+            .transform2(
+                quote!(),
+                quote! {
+                    #[post(max_body = "eight")]
+                    async fn handler() {}
+                }
+            )
+            .is_err());
+    }
+}
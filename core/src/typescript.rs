@@ -0,0 +1,463 @@
+#![cfg(feature = "typescript")]
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    fs, io,
+    path::Path
+};
+
+use convert_case::{Case, Casing};
+use serde_json::Value;
+
+use crate::{
+    openapi::{path_params, schema_for, schema_fragments, split_top_level},
+    server_router::{Codec, RouteInfo}
+};
+
+const PRELUDE: &str = r#"// Generated by server-fns; do not edit by hand.
+
+let baseUrl = "";
+
+/** Set the origin (and optional path prefix) prepended to every server function path. */
+export function setBaseUrl(url: string) {
+  baseUrl = url.replace(/\/$/, "");
+}
+
+export class ServerFnError extends Error {
+  constructor(public status: number, public body: string) {
+    super(`Server function failed with status ${status}: ${body}`);
+  }
+}
+
+async function send(method: string, path: string, init: RequestInit = {}): Promise<Response> {
+  // Marks the request as sent by this client rather than forged by another site's form.
+  const safe = ["GET", "HEAD", "OPTIONS", "TRACE"].includes(method);
+  const headers = safe ? init.headers : { ...init.headers, "x-server-fns-csrf": "1" };
+  const response = await fetch(baseUrl + path, { ...init, method, headers });
+  if (!response.ok) {
+    throw new ServerFnError(response.status, await response.text());
+  }
+  return response;
+}
+
+function query(params: object): string {
+  const search = new URLSearchParams();
+  for (const [key, value] of Object.entries(params)) {
+    if (value === undefined || value === null) continue;
+    for (const item of Array.isArray(value) ? value : [value]) {
+      search.append(key, String(item));
+    }
+  }
+  const encoded = search.toString();
+  return encoded ? `?${encoded}` : "";
+}
+"#;
+
+/// Generate a TypeScript module with typed bindings for the given routes.
+///
+/// Every [SchemaFragment](crate::openapi::SchemaFragment) is emitted as a named type, and each
+/// route gets a `<Name>Request` interface, a `<Name>Response` type and a fetch wrapper that
+/// encodes its arguments with the route's [Codec]s. Routes sharing a name are exported under their
+/// module path joined with the name, e.g. `adminListUsers` for `admin::list_users`.
+pub fn bindings<'a>(routes: impl IntoIterator<Item = &'a RouteInfo>) -> String {
+    let routes = routes.into_iter().collect::<Vec<_>>();
+    let mut name_counts = HashMap::<_, usize>::new();
+    for route in &routes {
+        *name_counts.entry(route.name).or_default() += 1;
+    }
+
+    let schemas = schema_fragments();
+    let mut module = String::from(PRELUDE);
+
+    for (name, schema) in &schemas {
+        module.push('\n');
+
+        match ts_type(schema) {
+            object if object.starts_with('{') => {
+                let _ = writeln!(module, "export interface {name} {object}");
+            }
+            alias => {
+                let _ = writeln!(module, "export type {name} = {alias};");
+            }
+        }
+    }
+
+    for route in routes {
+        let name = match name_counts[route.name] {
+            1 => route.name.to_owned(),
+            _ => format!("{}_{}", route.module_path.replace("::", "_"), route.name)
+        };
+
+        module.push('\n');
+        module.push_str(&route_bindings(route, &name, &schemas));
+    }
+
+    module
+}
+
+/// Generate bindings with [bindings] and write them to `path`, creating parent directories.
+pub fn write_bindings<'a>(
+    path: impl AsRef<Path>,
+    routes: impl IntoIterator<Item = &'a RouteInfo>
+) -> io::Result<()> {
+    let path = path.as_ref();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(path, bindings(routes))
+}
+
+fn route_bindings(route: &RouteInfo, name: &str, schemas: &BTreeMap<&str, Value>) -> String {
+    let fn_name = name.to_case(Case::Camel);
+    let type_name = name.to_case(Case::Pascal);
+    let methods = route
+        .methods
+        .iter()
+        .map(|method| match *method {
+            "any" => "\"POST\"".to_owned(),
+            method => format!("\"{}\"", method.to_uppercase())
+        })
+        .collect::<Vec<_>>();
+
+    let params = path_params(route.path);
+    let mut param_types = vec!["string | number".to_owned(); params.len()];
+    let mut fields = Vec::new();
+    let mut query = None;
+    let mut body = None;
+
+    for arg in route.args {
+        let ty = ts_type(&schema_for(arg.ty.ty, schemas));
+
+        match arg.ty.codec {
+            Codec::Path => match tuple_items(&ty) {
+                Some(items) if items.len() == params.len() => param_types = items,
+                _ if params.len() == 1 => param_types = vec![ty],
+                _ => {}
+            },
+            Codec::Query => {
+                fields.push(format!("{}: {ty};", arg.name));
+                query = Some(arg.name);
+            }
+            Codec::Json | Codec::Form | Codec::Text => {
+                fields.push(format!("{}: {ty};", arg.name));
+                body = Some((arg.name, arg.ty.codec));
+            }
+            Codec::State | Codec::Principal | Codec::Html | Codec::Other => {}
+        }
+    }
+
+    for (param, ty) in params.iter().zip(param_types).rev() {
+        fields.insert(0, format!("{param}: {ty};"));
+    }
+
+    let url = route
+        .path
+        .split('/')
+        .map(|part| match part.strip_prefix([':', '*']) {
+            Some(param) => format!("${{encodeURIComponent(String(request.{param}))}}"),
+            None => part.to_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    let url = match query {
+        Some(query) => format!("`{url}` + query(request.{query})"),
+        None => format!("`{url}`")
+    };
+
+    let init = match body {
+        Some((name, Codec::Json)) => format!(
+            "{{\n    headers: {{ \"Content-Type\": \"application/json\" }},\n    body: \
+             JSON.stringify(request.{name}),\n  }}"
+        ),
+        Some((name, Codec::Form)) => format!(
+            "{{\n    headers: {{ \"Content-Type\": \"application/x-www-form-urlencoded\" }},\n    \
+             body: query(request.{name}).slice(1),\n  }}"
+        ),
+        Some((name, _)) => format!(
+            "{{\n    headers: {{ \"Content-Type\": \"text/plain\" }},\n    body: \
+             request.{name},\n  }}"
+        ),
+        None => "{}".to_owned()
+    };
+
+    let (response_type, decode) = match route.output.codec {
+        Codec::Json => (
+            ts_type(&schema_for(route.output.ty, schemas)),
+            "response.json()"
+        ),
+        Codec::Html | Codec::Text => ("string".to_owned(), "response.text()"),
+        _ => ("Response".to_owned(), "response")
+    };
+
+    let mut bindings = String::new();
+    let _ = writeln!(
+        bindings,
+        "export const {}_PATH = \"{}\";\n",
+        name.to_case(Case::UpperSnake),
+        route.path
+    );
+    let _ = writeln!(bindings, "export interface {type_name}Request {{");
+    for field in &fields {
+        let _ = writeln!(bindings, "  {field}");
+    }
+    let _ = writeln!(bindings, "}}\n");
+    let _ = writeln!(
+        bindings,
+        "export type {type_name}Response = {response_type};\n"
+    );
+    let default = if fields.is_empty() { " = {}" } else { "" };
+    // Routes registered for several methods take the method to request with, defaulting to the first.
+    let (method_param, method) = match methods.as_slice() {
+        [method] => (String::new(), method.clone()),
+        methods => (
+            format!(", method: {} = {}", methods.join(" | "), methods[0]),
+            "method".to_owned()
+        )
+    };
+    let _ = writeln!(
+        bindings,
+        "export async function {fn_name}(request: {type_name}Request{default}{method_param}): \
+         Promise<{type_name}Response> {{"
+    );
+    let _ = writeln!(
+        bindings,
+        "  const response = await send({method}, {url}, {init});"
+    );
+    let _ = writeln!(bindings, "  return {decode};\n}}");
+
+    bindings
+}
+
+/// Convert a json schema into the equivalent TypeScript type expression.
+pub fn ts_type(schema: &Value) -> String {
+    if let Some(reference) = schema["$ref"].as_str() {
+        return reference.rsplit('/').next().unwrap_or(reference).to_owned();
+    }
+
+    if let Some(variants) = schema["anyOf"].as_array().or(schema["oneOf"].as_array()) {
+        return variants.iter().map(ts_type).collect::<Vec<_>>().join(" | ");
+    }
+
+    if let Some(values) = schema["enum"].as_array() {
+        return values
+            .iter()
+            .map(Value::to_string)
+            .collect::<Vec<_>>()
+            .join(" | ");
+    }
+
+    match schema["type"].as_str() {
+        Some("string") => "string".to_owned(),
+        Some("integer" | "number") => "number".to_owned(),
+        Some("boolean") => "boolean".to_owned(),
+        Some("null") => "null".to_owned(),
+        Some("array") => match (schema["prefixItems"].as_array(), &schema["items"]) {
+            (Some(items), _) => format!(
+                "[{}]",
+                items.iter().map(ts_type).collect::<Vec<_>>().join(", ")
+            ),
+            (None, Value::Null) => "unknown[]".to_owned(),
+            (None, items) => match ts_type(items) {
+                union if union.contains(' ') => format!("({union})[]"),
+                item => format!("{item}[]")
+            }
+        },
+        Some("object") => object_type(schema),
+        _ => "unknown".to_owned()
+    }
+}
+
+fn object_type(schema: &Value) -> String {
+    let Some(properties) = schema["properties"].as_object() else {
+        return match &schema["additionalProperties"] {
+            additional @ Value::Object(_) => format!("Record<string, {}>", ts_type(additional)),
+            _ => "Record<string, unknown>".to_owned()
+        };
+    };
+
+    let required = schema["required"]
+        .as_array()
+        .map(|required| {
+            required
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let fields = properties
+        .iter()
+        .map(|(name, property)| {
+            let optional = if required.contains(&name.as_str()) {
+                ""
+            } else {
+                "?"
+            };
+            format!("  {name}{optional}: {};\n", ts_type(property))
+        })
+        .collect::<String>();
+
+    format!("{{\n{fields}}}")
+}
+
+/// The item types of a TypeScript tuple type, or [None] if the type isn't a tuple.
+fn tuple_items(ty: &str) -> Option<Vec<String>> {
+    let inner = ty.strip_prefix('[')?.strip_suffix(']')?;
+
+    // Types like `[number][]` or `[number] | [string]` start and end with brackets too, but close
+    // the first one before the end.
+    let mut depth = 0usize;
+    for char_ in inner.chars() {
+        match char_ {
+            '<' | '(' | '[' | '{' => depth += 1,
+            '>' | ')' | ']' | '}' => depth = depth.checked_sub(1)?,
+            _ => {}
+        }
+    }
+
+    Some(
+        split_top_level(inner)
+            .into_iter()
+            .map(|item| item.trim().to_owned())
+            .collect()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::server_router::{ArgInfo, TypeInfo};
+
+    #[test]
+    fn convert_schemas() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "tags": { "type": "array", "items": { "anyOf": [{ "type": "string" }, { "type": "null" }] } },
+                "owner": { "$ref": "#/components/schemas/User" }
+            },
+            "required": ["id", "tags"]
+        });
+
+        assert_eq!(
+            ts_type(&schema),
+            "{\n  id: number;\n  owner?: User;\n  tags: (string | null)[];\n}"
+        );
+        assert_eq!(
+            ts_type(
+                &json!({ "type": "array", "prefixItems": [{ "type": "integer" }, { "type": "boolean" }] })
+            ),
+            "[number, boolean]"
+        );
+    }
+
+    #[test]
+    fn generate_route_bindings() {
+        const ARGS: &[ArgInfo] = &[
+            ArgInfo {
+                name: "id",
+                ty: TypeInfo {
+                    codec: Codec::Path,
+                    ty: "u64"
+                }
+            },
+            ArgInfo {
+                name: "name",
+                ty: TypeInfo {
+                    codec: Codec::Json,
+                    ty: "String"
+                }
+            }
+        ];
+
+        let route = RouteInfo {
+            path: "/api/users/:id",
+            methods: &["put"],
+            name: "rename_user",
+            module_path: "app",
+            package: "app",
+            middleware_count: 0,
+            embed: None,
+            args: ARGS,
+            output: TypeInfo {
+                codec: Codec::Json,
+                ty: "bool"
+            },
+            cors: None,
+            csrf: true,
+            rpc: false
+        };
+
+        let bindings = route_bindings(&route, route.name, &BTreeMap::new());
+
+        assert!(bindings.contains("export const RENAME_USER_PATH = \"/api/users/:id\";"));
+        assert!(bindings
+            .contains("export interface RenameUserRequest {\n  id: number;\n  name: string;\n}"));
+        assert!(bindings.contains("export type RenameUserResponse = boolean;"));
+        assert!(bindings.contains(
+            "await send(\"PUT\", `/api/users/${encodeURIComponent(String(request.id))}`"
+        ));
+        assert!(bindings.contains("body: JSON.stringify(request.name)"));
+        assert!(bindings.contains("return response.json();"));
+    }
+
+    #[test]
+    fn split_tuple_items() {
+        assert_eq!(
+            tuple_items("[number, [string, boolean], Record<string, number>]").unwrap(),
+            ["number", "[string, boolean]", "Record<string, number>"]
+        );
+        assert_eq!(
+            tuple_items("[{\n  a: number;\n  b: string;\n}, number]").unwrap(),
+            ["{\n  a: number;\n  b: string;\n}", "number"]
+        );
+        assert_eq!(tuple_items("[number][]"), None);
+        assert_eq!(tuple_items("[number] | [string]"), None);
+    }
+
+    #[test]
+    fn qualify_shared_names() {
+        const fn route(
+            path: &'static str,
+            name: &'static str,
+            module_path: &'static str
+        ) -> RouteInfo {
+            RouteInfo {
+                path,
+                methods: &["get"],
+                name,
+                module_path,
+                package: "app",
+                middleware_count: 0,
+                embed: None,
+                args: &[],
+                output: TypeInfo {
+                    codec: Codec::Text,
+                    ty: "String"
+                },
+                cors: None,
+                csrf: true,
+                rpc: false
+            }
+        }
+
+        let routes = [
+            route("/api/users", "list", "app::users"),
+            route("/api/posts", "list", "app::posts"),
+            route("/api/health", "health", "app")
+        ];
+        let bindings = bindings(&routes);
+
+        assert!(bindings.contains("export const APP_USERS_LIST_PATH = \"/api/users\";"));
+        assert!(bindings.contains("export async function appPostsList("));
+        assert!(bindings.contains("export type AppPostsListResponse = string;"));
+        assert!(bindings.contains("export async function health("));
+        assert!(!bindings.contains("function list("));
+    }
+}
@@ -1,14 +1,143 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+use std::{collections::HashMap, sync::Arc};
+
+#[cfg(feature = "server")]
+use server_fns::{
+    axum::http::{header::AUTHORIZATION, request::Parts, StatusCode},
+    guard::Principal,
+    ServerState
+};
+use server_fns::{get, guard, use_server_state};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub name: String,
+    pub admin: bool
+}
+
+/// Signed in users by their bearer token.
+#[derive(Debug, Default, Clone)]
+pub struct Sessions(Arc<HashMap<String, User>>);
+
+impl Sessions {
+    pub fn new(sessions: impl IntoIterator<Item = (String, User)>) -> Self {
+        Self(Arc::new(sessions.into_iter().collect()))
+    }
+
+    pub fn user(&self, token: &str) -> Option<User> {
+        self.0.get(token).cloned()
+    }
+}
+
+#[cfg_attr(feature = "server", derive(ServerState))]
+#[derive(Debug, Default, Clone)]
+pub struct AuthState {
+    #[cfg_attr(feature = "server", server_state(from_ref))]
+    pub sessions: Sessions
+}
+
+use_server_state!(AuthState);
+
+/// Resolve the request's bearer token to a signed in user.
+#[cfg(feature = "server")]
+pub async fn require_user(parts: Parts, state: AuthState) -> Result<User, StatusCode> {
+    parts
+        .headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| state.sessions.user(token))
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+/// Only let admins through, reading the user found by [require_user] before it.
+#[cfg(feature = "server")]
+pub async fn require_admin(parts: Parts, _: Sessions) -> Result<(), StatusCode> {
+    match parts.extensions.get::<Principal<User>>() {
+        Some(Principal(user)) if user.admin => Ok(()),
+        _ => Err(StatusCode::FORBIDDEN)
+    }
 }
 
-#[cfg(test)]
-mod tests {
+#[guard(require_user)]
+#[get(path = "/api/me")]
+pub async fn me(Principal(user): Principal<User>) -> String {
+    format!("signed in as {}", user.name)
+}
+
+#[guard(require_user)]
+#[guard(require_admin, state = Sessions)]
+#[get(path = "/api/admin")]
+pub async fn admin(Principal(user): Principal<User>) -> String {
+    format!("{} is an admin", user.name)
+}
+
+#[cfg(all(test, feature = "server"))]
+mod test {
+    use server_fns::{axum::http::Method, testing::TestServer};
+
     use super::*;
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn state() -> AuthState {
+        let user = |name: &str, admin| User {
+            name: name.to_owned(),
+            admin
+        };
+
+        AuthState {
+            sessions: Sessions::new([
+                ("alice-token".to_owned(), user("alice", true)),
+                ("bob-token".to_owned(), user("bob", false))
+            ])
+        }
+    }
+
+    async fn get(server: &TestServer, url: String, token: Option<&str>) -> (StatusCode, String) {
+        let mut request = server.request(Method::GET, url);
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+
+        let response = request.send().await;
+        (response.status(), response.text())
+    }
+
+    #[tokio::test]
+    async fn guard_routes() {
+        let server = TestServer::new(state());
+
+        assert_eq!(
+            get(&server, get_me_url(), Some("bob-token")).await,
+            (StatusCode::OK, "signed in as bob".to_owned())
+        );
+        assert_eq!(
+            get(&server, get_me_url(), Some("stolen-token")).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get(&server, get_me_url(), None).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+
+        assert_eq!(
+            get(&server, get_admin_url(), Some("alice-token")).await,
+            (StatusCode::OK, "alice is an admin".to_owned())
+        );
+        assert_eq!(
+            get(&server, get_admin_url(), Some("bob-token")).await.0,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn call_with_principal() {
+        let user = User {
+            name: "carol".to_owned(),
+            admin: false
+        };
+
+        assert_eq!(
+            me_with_state(&state(), Principal(user)).await,
+            "signed in as carol"
+        );
     }
 }
@@ -1,3 +1,25 @@
+use server_fns::get;
+
+/// Describes the service answering [status], provided by any state serving the shared route.
+#[derive(Debug, Clone)]
+pub struct ServiceInfo {
+    pub name: &'static str
+}
+
+impl Default for ServiceInfo {
+    fn default() -> Self {
+        Self {
+            name: env!("CARGO_PKG_NAME")
+        }
+    }
+}
+
+/// Served by every state listing it in `#[server_state(shared = [extra_routes::status])]`.
+#[get(path = "/api/status", shared = true)]
+pub async fn status(#[state] service: ServiceInfo) -> String {
+    format!("{} ok", service.name)
+}
+
 pub fn add(left: usize, right: usize) -> usize {
     left + right
 }
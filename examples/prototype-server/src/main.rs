@@ -1,10 +1,18 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use axum::{
-    extract::Request,
+    extract::{Path, Request},
+    http::StatusCode,
     middleware::Next,
-    response::{Html, Response}
+    response::{Html, Response},
+    Json
+};
+use server_fns::{
+    get, global_middleware, head, middleware, middleware_group, post, server_state::ServerState,
+    use_server_state
 };
-use server_fns::{get, middleware, server_state::ServerState, use_server_state};
-use server_state::{AppState, InnerState};
+use server_state::{AdminState, AppState, InnerState};
+use tower::util::MapResponseLayer;
 
 use_server_state!(AppState);
 
@@ -12,18 +20,22 @@ use_server_state!(AppState);
 async fn main() {
     println!("after auto routes");
 
-    let app = AppState {
+    for route in AppState::routes() {
+        println!("{route}");
+    }
+
+    AppState {
         inner: InnerState {
             state: "fucking works bitch".to_string()
-        }
+        },
+        ..Default::default()
     }
-    .load_routes();
-
-    println!("app {app:?}");
-
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3333").await.unwrap();
-    println!("bound to {listener:?}");
-    axum::serve(listener, app).await.unwrap();
+    .app()
+    .fallback(|| async { (StatusCode::NOT_FOUND, "not found") })
+    .bind("0.0.0.0:3333")
+    .serve()
+    .await
+    .unwrap();
 }
 
 async fn test_middleware(request: Request, next: Next) -> Response {
@@ -36,15 +48,491 @@ async fn test_middleware2(request: Request, next: Next) -> Response {
     response
 }
 
+type StampLayer = MapResponseLayer<fn(Response) -> Response>;
+
+/// Stamp every response of [AppState] with the server's name.
+#[global_middleware(order = 0)]
+fn server_header() -> StampLayer {
+    MapResponseLayer::new(|mut response| {
+        response.headers_mut().append(
+            "x-served-by",
+            axum::http::HeaderValue::from_static("prototype-server")
+        );
+        response
+    })
+}
+
+/// Sits inside [server_header], so its header is appended first.
+#[global_middleware(state = AppState, order = 10)]
+fn route_header() -> StampLayer {
+    MapResponseLayer::new(|mut response| {
+        response.headers_mut().append(
+            "x-served-by",
+            axum::http::HeaderValue::from_static("routes")
+        );
+        response
+    })
+}
+
+fn group_header(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert("x-group", axum::http::HeaderValue::from_static("traced"));
+    response
+}
+
+middleware_group!(
+    traced = [
+        axum::middleware::from_fn(test_middleware),
+        axum::middleware::from_fn(test_middleware2),
+        MapResponseLayer::new(group_header as fn(Response) -> Response)
+    ]
+);
+
 #[middleware(axum::middleware::from_fn(test_middleware))]
 #[middleware(axum::middleware::from_fn(test_middleware2))]
 #[get(path = "/", embed = "$CARGO_MANIFEST_DIR/..")]
-async fn index(#[state] AppState { inner }: AppState) -> Html<String> {
+async fn index(#[state] AppState { inner, .. }: AppState) -> Html<String> {
     let html = format!("<body>index and {inner:?}</body>");
 
     // let path = "asset/something/else.ts";
 
-    let test = load_asset!("/test");
+    let _test = load_asset!("/test");
 
     Html(html)
 }
+
+/// Health checks may come from any origin.
+#[get(cors(origins = ["*"]))]
+#[head]
+async fn health() -> &'static str {
+    "ok"
+}
+
+/// Deployed clients keep calling the same path if the function is renamed or moved.
+#[get(id = "prototype.version")]
+async fn version() -> &'static str {
+    "1"
+}
+
+#[get(path = "/admin/health", state = AdminState)]
+async fn admin_health(#[state] inner: InnerState) -> String {
+    format!("ok and {inner:?}")
+}
+
+fn stamp_outer(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .append("x-layer", axum::http::HeaderValue::from_static("outer"));
+    response
+}
+
+fn stamp_inner(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .append("x-layer", axum::http::HeaderValue::from_static("inner"));
+    response
+}
+
+/// The first middleware written is the outermost layer, so it stamps the response last.
+#[middleware(MapResponseLayer::new(stamp_outer as fn(Response) -> Response))]
+#[middleware(MapResponseLayer::new(stamp_inner as fn(Response) -> Response))]
+#[get]
+async fn layers() -> &'static str {
+    "layered"
+}
+
+/// Each client may subscribe once an hour.
+#[post(rate_limit(requests = 1, per = "1h", key = header("x-client")))]
+async fn subscribe() -> &'static str {
+    "subscribed"
+}
+
+#[post(timeout = "5s", max_body = 16, concurrency = 4)]
+async fn note(note: String) -> String {
+    format!("noted {}", note.len())
+}
+
+/// Posted by other sites' servers, which can't send the client's CSRF header.
+#[post(csrf = false)]
+async fn webhook(event: String) -> String {
+    format!("received {event}")
+}
+
+#[middleware(group = traced)]
+#[post(path = "/api/echo/:times", trace_args = true, rpc = true)]
+async fn echo(Path(times): Path<usize>, Json(message): Json<String>) -> Json<Vec<String>> {
+    Json(vec![message; times])
+}
+
+/// Counts the visits it actually handles, so cached responses repeat the count.
+#[get(cache(ttl = "1m", key = ["accept-language"]))]
+async fn visits() -> String {
+    static VISITS: AtomicUsize = AtomicUsize::new(0);
+
+    VISITS.fetch_add(1, Ordering::SeqCst).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{body::Body, http::Method};
+    use server_fns::testing::TestServer;
+
+    use super::*;
+
+    /// Generate the TypeScript bindings for every route served by [AppState].
+    #[cfg(feature = "typescript")]
+    #[test]
+    fn write_typescript_bindings() {
+        let path = std::env::temp_dir().join("prototype-server/server_fns.ts");
+
+        server_fns::typescript::write_bindings(&path, AppState::routes()).unwrap();
+        assert!(std::fs::read_to_string(path)
+            .unwrap()
+            .contains("export async function echo("));
+    }
+
+    #[test]
+    fn list_registered_routes() {
+        let routes = AppState::routes();
+        let route = |name: &str| {
+            routes
+                .iter()
+                .find(|route| route.name == name)
+                .copied()
+                .unwrap()
+        };
+
+        let echo = route("echo");
+        assert_eq!(echo.path, "/api/echo/:times");
+        assert_eq!(echo.methods, ["post"]);
+        assert_eq!(echo.module_path, "prototype_server");
+        assert_eq!(echo.package, "prototype-server");
+
+        let health = route("health");
+        assert_eq!(health.path, "/api/health");
+        assert_eq!(health.methods, ["get", "head"]);
+        assert_eq!(
+            health.to_string(),
+            "GET,HEAD /api/health -> prototype_server::health"
+        );
+
+        assert!(routes.windows(2).all(|pair| pair[0].path <= pair[1].path));
+    }
+
+    #[test]
+    fn register_with_explicit_state() {
+        let admin_routes = AdminState::routes();
+
+        assert_eq!(admin_routes.len(), 1);
+        assert_eq!(admin_routes[0].name, "admin_health");
+        assert!(AppState::routes()
+            .iter()
+            .all(|route| route.name != "admin_health"));
+    }
+
+    #[tokio::test]
+    async fn call_in_process() {
+        let state = AppState::default();
+
+        let Html(html) = index_with_state(&state).await;
+        assert!(html.starts_with("<body>index"));
+
+        let request = Request::get(get_health_url()).body(Body::empty()).unwrap();
+        let response = health_oneshot(state, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let admin = AdminState::default();
+        assert!(admin_health_with_state(&admin).await.starts_with("ok and"));
+    }
+
+    #[tokio::test]
+    async fn call_through_test_server() {
+        let server = TestServer::new(AppState::default());
+
+        let response = get_health_test(&server).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.value().unwrap(), "ok");
+
+        let response = post_echo_test(&server, 2, "hi".to_owned()).await;
+        assert_eq!(response.value().unwrap(), vec!["hi", "hi"]);
+        assert_eq!(response.headers()["x-group"], "traced");
+    }
+
+    #[tokio::test]
+    async fn apply_global_middleware_in_order() {
+        let names = AppState::middlewares()
+            .iter()
+            .map(|info| info.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["server_header", "route_header"]);
+        assert!(AdminState::middlewares().is_empty());
+
+        let server = TestServer::new(AppState::default());
+        let response = get_health_test(&server).await;
+        let served_by = response
+            .headers()
+            .get_all("x-served-by")
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(served_by, vec!["routes", "prototype-server"]);
+    }
+
+    #[tokio::test]
+    async fn layer_middleware_outermost_first() {
+        let server = TestServer::new(AppState::default());
+
+        let response = get_layers_test(&server).await;
+        let layers = response
+            .headers()
+            .get_all("x-layer")
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(layers, vec!["inner", "outer"]);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_per_client() {
+        let server = TestServer::new(AppState::default());
+        let subscribe = |client: &'static str| {
+            server
+                .request(Method::POST, post_subscribe_url())
+                .header("x-client", client)
+                .send()
+        };
+
+        assert_eq!(subscribe("first").await.text(), "subscribed");
+
+        let response = subscribe("first").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "3600");
+
+        assert_eq!(subscribe("second").await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn cache_responses() {
+        let server = TestServer::new(AppState::default());
+
+        let first = get_visits_test(&server).await.text();
+        let response = get_visits_test(&server).await;
+        assert_eq!(response.text(), first);
+        assert_eq!(response.headers()["cache-control"], "max-age=60");
+        assert_eq!(response.headers()["vary"], "accept-language");
+
+        invalidate_visits_cache().await;
+        assert_ne!(get_visits_test(&server).await.text(), first);
+    }
+
+    #[tokio::test]
+    async fn dispatch_batched_calls() {
+        use server_fns::batch::{decode_all, encode_all, BatchCall, BatchResult};
+
+        let server = TestServer::new(AppState::default());
+        let calls = [
+            BatchCall {
+                method: "GET".to_owned(),
+                url: get_health_url(),
+                headers: Vec::new(),
+                body: None
+            },
+            BatchCall {
+                method: "POST".to_owned(),
+                url: post_echo_url(2),
+                headers: vec![("content-type".to_owned(), "application/json".to_owned())],
+                body: Some(r#""hi""#.to_owned())
+            }
+        ];
+
+        let response = server
+            .request(Method::POST, "/api/batch")
+            .header("content-type", "application/json")
+            .text(encode_all(&calls, BatchCall::to_json))
+            .send()
+            .await;
+        let results = decode_all(response.body(), BatchResult::from_json).unwrap();
+
+        assert_eq!(results[0].body, "ok");
+        assert_eq!(results[1].body, r#"["hi","hi"]"#);
+        assert!(results[1]
+            .headers
+            .contains(&("x-group".to_owned(), "traced".to_owned())));
+    }
+
+    #[tokio::test]
+    async fn call_json_rpc() {
+        let server = TestServer::new(AppState::default());
+
+        let response = server
+            .request(Method::POST, "/rpc")
+            .header("content-type", "application/json")
+            .text(
+                r#"[
+                    {"jsonrpc": "2.0", "method": "echo", "params": [2, "hi"], "id": 1},
+                    {"jsonrpc": "2.0", "method": "health", "id": 2}
+                ]"#
+            )
+            .send()
+            .await;
+
+        assert_eq!(
+            response.text(),
+            r#"[{"id":1,"jsonrpc":"2.0","result":["hi","hi"]},{"error":{"code":-32601,"message":"Method not found"},"id":2,"jsonrpc":"2.0"}]"#
+        );
+    }
+
+    #[tokio::test]
+    async fn limit_body_size() {
+        let server = TestServer::new(AppState::default());
+
+        let response = post_note_test(&server, "short".to_owned()).await;
+        assert_eq!(response.value().unwrap(), "noted 5");
+
+        let response = post_note_test(&server, "much too long for a note".to_owned()).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            response.headers()[server_fns::limit::LIMIT_HEADER],
+            "max-body"
+        );
+    }
+
+    #[tokio::test]
+    async fn answer_cors_preflight() {
+        let server = TestServer::new(AppState::default());
+
+        let response = server
+            .request(Method::OPTIONS, "/api/echo/2")
+            .header("origin", "http://localhost:8080")
+            .header("access-control-request-method", "POST")
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()["access-control-allow-methods"],
+            "OPTIONS, POST"
+        );
+        assert_eq!(response.headers()["access-control-max-age"], "600");
+
+        let response = server
+            .request(Method::OPTIONS, "/api/batch")
+            .header("origin", "http://localhost:8080")
+            .header("access-control-request-method", "POST")
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "http://localhost:8080"
+        );
+
+        let response = server
+            .request(Method::GET, get_health_url())
+            .header("origin", "https://status.test")
+            .send()
+            .await;
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://status.test"
+        );
+        assert!(!response
+            .headers()
+            .contains_key("access-control-allow-credentials"));
+    }
+
+    #[tokio::test]
+    async fn reject_cross_site_posts() {
+        let server = TestServer::new(AppState::default());
+        let forged = |path: String| {
+            server
+                .request(Method::POST, path)
+                .header("sec-fetch-site", "cross-site")
+                .header("origin", "https://evil.test")
+                .text("hi")
+                .send()
+        };
+
+        let response = forged(post_echo_url(1)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = forged(post_webhook_url()).await;
+        assert_eq!(response.text(), "received hi");
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn serve_metrics() {
+        let server = TestServer::new(AppState::default());
+        let scrape = || async { server.request(Method::GET, "/metrics").send().await.text() };
+
+        get_layers_test(&server).await;
+        scrape().await;
+        let text = scrape().await;
+
+        // Other tests call the same routes concurrently, so only the scrapes have known counts.
+        let labels = r#"server_fn="metrics",module="server_state::__server_state_app_state",route="/metrics",method="GET""#;
+        assert!(text.contains(&format!(
+            "server_fn_requests_total{{{labels},status=\"200\"}} 1\n"
+        )));
+        assert!(text.contains(&format!(
+            "server_fn_request_duration_seconds_count{{{labels}}} 1\n"
+        )));
+        assert!(text.contains(
+            r#"server_fn_requests_total{server_fn="layers",module="prototype_server",route="/api/layers",method="GET",status="200"}"#
+        ));
+    }
+
+    #[tokio::test]
+    async fn serve_hashed_path() {
+        let server = TestServer::new(AppState::default());
+
+        assert_eq!(GET_VERSION, "/api/63384cdfcdbd8b07");
+        assert_eq!(get_version_url(), GET_VERSION);
+        assert_eq!(get_version_test(&server).await.text(), "1");
+    }
+
+    #[tokio::test]
+    async fn serve_shared_route() {
+        let server = TestServer::new(AppState::default());
+
+        let response = server
+            .request(Method::GET, extra_routes::get_status_url())
+            .send()
+            .await;
+        assert_eq!(response.text(), "extra-routes ok");
+
+        assert!(AppState::routes()
+            .iter()
+            .any(|route| route.name == "status" && route.package == "extra-routes"));
+        assert!(AdminState::routes()
+            .iter()
+            .all(|route| route.name != "status"));
+    }
+
+    #[cfg(feature = "web")]
+    #[tokio::test]
+    async fn call_through_mock_transport() {
+        use server_fns::client::{with_transport, MockTransport, ServerFnError};
+
+        let mock = MockTransport::default();
+        mock.respond(POST_ECHO, &["hi", "hi"])
+            .fail(GET_HEAD_HEALTH, ServerFnError::Transport("offline".into()));
+
+        let (echoed, health) = with_transport(mock.clone(), async {
+            (
+                post_echo_fetch(2, "hi".to_owned()).await,
+                get_health_fetch().await
+            )
+        })
+        .await;
+
+        assert_eq!(echoed.unwrap(), vec!["hi", "hi"]);
+        assert_eq!(health, Err(ServerFnError::Transport("offline".into())));
+
+        let calls = mock.calls();
+        assert_eq!(calls[0].method, "POST");
+        assert_eq!(calls[0].path(), "/api/echo/2");
+        assert_eq!(calls[0].arg::<String>("times"), Ok("2".to_owned()));
+        assert_eq!(calls[0].arg::<String>("arg1"), Ok("hi".to_owned()));
+        assert_eq!(calls[1].method, "GET");
+    }
+}
@@ -1,5 +1,6 @@
-#[cfg(feature = "server")]
-use axum::extract::FromRef;
+use std::sync::Arc;
+
+use extra_routes::ServiceInfo;
 #[cfg(feature = "server")]
 use server_fns::ServerState;
 
@@ -8,8 +9,39 @@ pub struct InnerState {
     pub state: String
 }
 
-#[cfg_attr(feature = "server", derive(ServerState, FromRef))]
+#[cfg_attr(feature = "server", derive(ServerState))]
+#[cfg_attr(
+    feature = "server",
+    server_state(
+        shared = [extra_routes::status],
+        batch = "/api/batch",
+        rpc = "/rpc",
+        rpc_tagged
+    )
+)]
+#[cfg_attr(
+    feature = "server",
+    server_state(cors(
+        origins = ["http://localhost:8080"],
+        headers = ["content-type"],
+        credentials = true,
+        max_age = "10m"
+    ))
+)]
+#[cfg_attr(feature = "openapi", server_state(openapi = "/openapi.json"))]
+#[cfg_attr(feature = "metrics", server_state(metrics = "/metrics"))]
 #[derive(Debug, Default, Clone)]
 pub struct AppState {
-    pub inner: InnerState
+    #[cfg_attr(feature = "server", server_state(from_ref))]
+    pub inner: InnerState,
+    #[cfg_attr(feature = "server", server_state(from_ref))]
+    pub service: ServiceInfo
+}
+
+/// A second state in the same package, for routes served by a separate admin app.
+#[cfg_attr(feature = "server", derive(ServerState))]
+#[derive(Debug, Default, Clone)]
+pub struct AdminState {
+    #[cfg_attr(feature = "server", server_state(from_ref, nested = [InnerState]))]
+    pub app: Arc<AppState>
 }
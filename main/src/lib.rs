@@ -6,5 +6,11 @@ pub use gloo_net;
 #[cfg(feature = "server")]
 pub use inventory;
 pub use paste;
+#[cfg(feature = "openapi")]
+pub use serde_json;
 pub use server_fns_core::*;
 pub use server_fns_procm::*;
+#[cfg(feature = "server")]
+pub use tower;
+#[cfg(feature = "tracing")]
+pub use tracing;
@@ -1,8 +1,8 @@
 use proc_macro::TokenStream;
 use server_fns_core::{
-    http_methods, AttrMacro, DeriveMacro, FnMacro, HttpMethod, LoadAssetInternalMacro,
-    MiddlewareAttrMacro, ServerFnAttrMacro, ServerFnMethodAttr, ServerStateDeriveMacro,
-    UseServerStateFnMacro
+    http_methods, AttrMacro, DeriveMacro, FnMacro, GlobalMiddlewareAttrMacro, GuardAttrMacro,
+    HttpMethod, LoadAssetInternalMacro, MiddlewareAttrMacro, MiddlewareGroupFnMacro,
+    ServerFnAttrMacro, ServerFnMethodAttr, ServerStateDeriveMacro, UseServerStateFnMacro
 };
 
 #[proc_macro_attribute]
@@ -10,16 +10,44 @@ pub fn server(args: TokenStream, body: TokenStream) -> TokenStream {
     ServerFnAttrMacro.transform(args, body)
 }
 
+/// Layer a middleware onto the server function beneath it.
+///
+/// Middlewares are layered outermost-first in the order they're written, top to bottom and then
+/// the server attribute's own `middlewares = [..]`, so the first one sees the request first and
+/// the response last. Groups from `middleware_group!` take the position they're referenced at.
 #[proc_macro_attribute]
 pub fn middleware(args: TokenStream, body: TokenStream) -> TokenStream {
     MiddlewareAttrMacro.transform(args, body)
 }
 
-#[proc_macro_derive(ServerState)]
+/// Run an async guard over the request parts and state before the server function beneath it.
+///
+/// `#[guard(require_user)]` calls `require_user(parts, state).await`, where `state` is the server
+/// function's state, or the one given with `#[guard(require_user, state = Sessions)]`. An `Err`
+/// is returned as the response; an `Ok` value is handed to the handler through
+/// `Principal<T>`. Guards run in the order they're written, before any other extractor.
+#[proc_macro_attribute]
+pub fn guard(args: TokenStream, body: TokenStream) -> TokenStream {
+    GuardAttrMacro.transform(args, body)
+}
+
+#[proc_macro_attribute]
+pub fn global_middleware(args: TokenStream, body: TokenStream) -> TokenStream {
+    GlobalMiddlewareAttrMacro.transform(args, body)
+}
+
+#[proc_macro_derive(ServerState, attributes(server_state))]
 pub fn server_state(item: TokenStream) -> TokenStream {
     ServerStateDeriveMacro.transform(item)
 }
 
+/// Define a named group of middlewares, layered outermost-first in declared order wherever it's
+/// referenced with `#[middleware(group = name)]`.
+#[proc_macro]
+pub fn middleware_group(item: TokenStream) -> TokenStream {
+    MiddlewareGroupFnMacro.transform(item)
+}
+
 #[proc_macro]
 pub fn use_server_state(item: TokenStream) -> TokenStream {
     UseServerStateFnMacro.transform(item)